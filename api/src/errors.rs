@@ -1,7 +1,10 @@
-use axum::http::StatusCode;
+use axum::http::header::RETRY_AFTER;
+use axum::http::{HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use bcrypt::BcryptError;
+use chrono::Utc;
+use serde::Deserialize;
 use serde_json::json;
 use tokio::task::JoinError;
 use wither::bson;
@@ -9,6 +12,7 @@ use wither::mongodb::error::Error as MongoError;
 use wither::WitherError;
 
 use crate::utils::get_feed::Error as GetFeedError;
+use crate::utils::websub::Error as WebSubError;
 
 #[derive(thiserror::Error, Debug)]
 #[error("...")]
@@ -25,9 +29,15 @@ pub enum Error {
   #[error("{0}")]
   ParseObjectID(#[from] bson::oid::Error),
 
+  #[error("{0}")]
+  Write(#[from] WriteError),
+
   #[error("{0}")]
   SerializeMongoResponse(#[from] bson::de::Error),
 
+  #[error("{0}")]
+  SerializeBson(#[from] bson::ser::Error),
+
   #[error("{0}")]
   Authenticate(#[from] AuthenticateError),
 
@@ -45,10 +55,13 @@ pub enum Error {
 
   #[error("{0}")]
   GetFeed(#[from] GetFeedError),
+
+  #[error("{0}")]
+  WebSub(#[from] WebSubError),
 }
 
 impl Error {
-  fn get_codes(&self) -> (StatusCode, u16) {
+  pub fn get_codes(&self) -> (StatusCode, u16) {
     match *self {
       // 4XX Errors
       Error::ParseObjectID(_) => (StatusCode::BAD_REQUEST, 40001),
@@ -64,7 +77,24 @@ impl Error {
       // }
       Error::BadRequest(_) => (StatusCode::BAD_REQUEST, 40003),
       Error::NotFound(_) => (StatusCode::NOT_FOUND, 40003),
-      Error::GetFeed(_) => (StatusCode::BAD_REQUEST, 40003),
+      Error::GetFeed(GetFeedError::InvalidUrl(_)) => (StatusCode::BAD_REQUEST, 40010),
+      Error::GetFeed(GetFeedError::Dns(_)) => (StatusCode::BAD_REQUEST, 40011),
+      Error::GetFeed(GetFeedError::Connect(_)) => (StatusCode::BAD_REQUEST, 40012),
+      Error::GetFeed(GetFeedError::Timeout(_)) => (StatusCode::BAD_REQUEST, 40013),
+      Error::GetFeed(GetFeedError::Tls(_)) => (StatusCode::BAD_REQUEST, 40014),
+      Error::GetFeed(GetFeedError::HttpStatus(_)) => (StatusCode::BAD_REQUEST, 40015),
+      Error::GetFeed(GetFeedError::TooLarge) => (StatusCode::BAD_REQUEST, 40016),
+      Error::GetFeed(GetFeedError::UnsupportedContentType(_)) => (StatusCode::BAD_REQUEST, 40017),
+      Error::GetFeed(GetFeedError::Parse(_)) => (StatusCode::BAD_REQUEST, 40018),
+      Error::GetFeed(GetFeedError::Request(_)) => (StatusCode::BAD_REQUEST, 40019),
+      Error::GetFeed(GetFeedError::NoFeedFound) => (StatusCode::BAD_REQUEST, 40020),
+      Error::GetFeed(GetFeedError::TooManyRedirects) => (StatusCode::BAD_REQUEST, 40021),
+      Error::GetFeed(GetFeedError::Blocked(_)) => (StatusCode::BAD_REQUEST, 40022),
+      Error::GetFeed(GetFeedError::DisallowedByRobots) => (StatusCode::BAD_REQUEST, 40023),
+      Error::GetFeed(GetFeedError::RateLimited(_)) => (StatusCode::TOO_MANY_REQUESTS, 40024),
+      Error::GetFeed(GetFeedError::Scrape(_)) => (StatusCode::BAD_REQUEST, 40025),
+      Error::GetFeed(GetFeedError::NoItemsFound) => (StatusCode::BAD_REQUEST, 40026),
+      Error::GetFeed(GetFeedError::MapJson(_)) => (StatusCode::BAD_REQUEST, 40027),
 
       Error::Authenticate(AuthenticateError::WrongCredentials) => (StatusCode::UNAUTHORIZED, 40003),
       Error::Authenticate(AuthenticateError::InvalidToken) => (StatusCode::UNAUTHORIZED, 40003),
@@ -76,10 +106,13 @@ impl Error {
       }
       Error::Wither(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5001),
       Error::Mongo(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5003),
+      Error::Write(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5003),
       Error::SerializeMongoResponse(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
+      Error::SerializeBson(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
       Error::RunSyncTask(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
       Error::HashPassword(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5009),
       Error::ParseURL => (StatusCode::INTERNAL_SERVER_ERROR, 5010),
+      Error::WebSub(_) => (StatusCode::INTERNAL_SERVER_ERROR, 5011),
     }
   }
 }
@@ -90,7 +123,16 @@ impl IntoResponse for Error {
     let message = self.to_string();
     let body = Json(json!({ "code": code, "message": message }));
 
-    (status_code, body).into_response()
+    let mut response = (status_code, body).into_response();
+    // The feed host is rate limiting us, clients can try again once it allows
+    // us to fetch the feed.
+    if let Error::GetFeed(GetFeedError::RateLimited(retry_at)) = self {
+      let retry_after = (retry_at - Utc::now()).num_seconds().max(1);
+      response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    response
   }
 }
 
@@ -153,3 +195,11 @@ impl NotFound {
     }
   }
 }
+
+#[derive(thiserror::Error, Debug, Clone, Deserialize)]
+#[error("Write error at statement {index}. Code: {code}, message: {errmsg}")]
+pub struct WriteError {
+  pub index: u64,
+  pub code: i32,
+  pub errmsg: String,
+}
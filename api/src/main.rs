@@ -1,12 +1,14 @@
 use axum::Router;
 use http::header;
 use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::sleep;
 use tower_http::cors::CorsLayer;
 use tower_http::{
   compression::CompressionLayer, propagate_header::PropagateHeaderLayer,
   sensitive_headers::SetSensitiveHeadersLayer, trace,
 };
-use tracing::info;
+use tracing::{info, warn};
 
 mod authentication;
 mod database;
@@ -33,14 +35,36 @@ async fn main() {
   let app = create_app().await;
 
   info!("Starting schedulers");
-  schedulers::feed::start();
-  schedulers::subscription::start();
+  schedulers::jobs::start();
+  schedulers::websub::start();
+
+  tokio::spawn(async {
+    utils::shutdown::wait_for_signal().await;
+    utils::shutdown::trigger();
+  });
 
   info!("listening on {}", &address);
-  axum::Server::bind(&address)
+  let server = axum::Server::bind(&address)
     .serve(app.into_make_service())
-    .await
-    .expect("Failed to start server");
+    .with_graceful_shutdown(utils::shutdown::wait());
+
+  // On shutdown the server stops accepting connections and the schedulers
+  // stop picking up new work. Open requests, feed syncs and webhooks are
+  // given the grace period to complete.
+  let drain = async {
+    server.await.expect("Failed to start server");
+    utils::shutdown::wait_for_tasks().await;
+  };
+  let grace_period = Duration::from_secs(settings.server.shutdown_grace_period);
+  let deadline = async {
+    utils::shutdown::wait().await;
+    sleep(grace_period).await;
+  };
+
+  tokio::select! {
+    _ = drain => info!("Shutdown complete"),
+    _ = deadline => warn!("Shutdown grace period elapsed, exiting with work in flight"),
+  }
 }
 
 pub async fn create_app() -> Router {
@@ -1,7 +1,10 @@
 use again::RetryPolicy;
 use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
+use chrono::Utc;
 use lazy_static::lazy_static;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::redirect::Policy;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as Json;
 use std::time::Duration;
@@ -13,17 +16,25 @@ use wither::Model as WitherModel;
 
 use crate::errors::Error;
 use crate::errors::NotFound;
-use crate::models::entry::Entry;
+use crate::models::entry::PublicEntry;
 use crate::models::feed::Feed;
+use crate::models::job::{Job, JobKind};
 use crate::models::webhook::Status;
 use crate::models::webhook::Webhook;
 use crate::models::webhook::WebhookSendPayload;
+use crate::settings::get_settings;
 use crate::utils::database_model::ModelExt;
 use crate::utils::date::{now, Date};
+use crate::utils::outbound::{self, check_url};
 
 lazy_static! {
+  // Redirects are not followed, the redirect URL could not be allowed by the
+  // outbound policy.
   static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+    .dns_resolver(outbound::resolver())
     .timeout(Duration::from_secs(5))
+    .redirect(Policy::none())
+    .user_agent(&get_settings().outbound.user_agent)
     .build()
     .expect("Failed to create a reqwest client");
 }
@@ -72,7 +83,8 @@ impl Endpoint {
     application: ObjectId,
     subscription: ObjectId,
     feed: ObjectId,
-    entries: Vec<Entry>,
+    entries: Vec<PublicEntry>,
+    updated: Vec<PublicEntry>,
     metadata: Option<Json>,
   ) -> Result<Webhook, Error> {
     debug!("Notifying endpoint");
@@ -108,26 +120,14 @@ impl Endpoint {
       application,
       subscription,
       endpoint: endpoint_id,
-      entries: entries.into_iter().map(Into::into).collect(),
+      entries,
+      updated,
       metadata,
     };
 
     let sent_at = now();
-    let policy = RetryPolicy::exponential(Duration::from_millis(200))
-      .with_max_retries(3)
-      .with_max_delay(Duration::from_secs(1));
-
-    let res = policy
-      .retry(|| CLIENT.post(&endpoint_url).json(&payload).send())
-      .await;
-
-    let status = match res {
-      Err(_) => Status::Failed,
-      Ok(res) => match res.error_for_status() {
-        Ok(_) => Status::Sent,
-        Err(_) => Status::Failed,
-      },
-    };
+    let body = serde_json::to_string(&payload).expect("Failed to serialize webhook payload");
+    let status = deliver_webhook(&endpoint_id, &endpoint_url, &body).await;
 
     let webhook = Webhook {
       id: None,
@@ -144,8 +144,78 @@ impl Endpoint {
 
     let webhook = Webhook::create(webhook).await?;
 
+    // Failed webhooks are sent again later, the subscription entries are
+    // already marked as notified.
+    if let Status::Failed = webhook.status {
+      let kind = JobKind::RetryWebhook {
+        webhook: webhook.id.unwrap(),
+        payload: body,
+      };
+      Job::enqueue(kind, Utc::now()).await?;
+    }
+
     Ok(webhook)
   }
+
+  /// Send again a webhook that could not be delivered. Returns whether the
+  /// webhook was delivered this time.
+  pub async fn retry_webhook(webhook_id: ObjectId, payload: String) -> Result<bool, Error> {
+    let webhook = match Webhook::find_by_id(&webhook_id).await? {
+      Some(webhook) => webhook,
+      // The webhook was removed with its application, there is nothing to
+      // retry.
+      None => return Ok(true),
+    };
+
+    let status = deliver_webhook(&webhook.endpoint, &webhook.endpoint_url, &payload).await;
+    match status {
+      Status::Sent => {
+        Webhook::update_one(
+          doc! { "_id": &webhook_id },
+          doc! { "$set": { "status": bson::to_bson(&status)? } },
+          None,
+        )
+        .await?;
+        Ok(true)
+      }
+      Status::Failed => Ok(false),
+    }
+  }
+}
+
+/// POST the webhook body to the endpoint URL. Requests are retried a few times
+/// right away to get over short network issues.
+async fn deliver_webhook(endpoint_id: &ObjectId, endpoint_url: &str, body: &str) -> Status {
+  let policy = RetryPolicy::exponential(Duration::from_millis(200))
+    .with_max_retries(3)
+    .with_max_delay(Duration::from_secs(1));
+
+  match check_url(endpoint_url).await {
+    Err(err) => {
+      error!(
+        "Endpoint {} URL is not allowed. Error: {}",
+        endpoint_id, err
+      );
+      Status::Failed
+    }
+    Ok(_) => {
+      let res = policy
+        .retry(|| {
+          CLIENT
+            .post(endpoint_url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_owned())
+            .send()
+        })
+        .await;
+
+      match res {
+        Ok(res) if res.status().is_success() => Status::Sent,
+        // Redirects are not followed, a redirected webhook was not delivered.
+        _ => Status::Failed,
+      }
+    }
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
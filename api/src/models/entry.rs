@@ -1,19 +1,32 @@
+use chrono::{Duration, Utc};
 use feed_rs::model::Entry as RawEntry;
-use futures::{stream, StreamExt};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tracing::{debug, error};
 use validator::Validate;
-use wither::bson::{doc, oid::ObjectId};
+use wither::bson::{doc, oid::ObjectId, Document};
 use wither::mongodb::options::FindOneOptions;
 use wither::Model as WitherModel;
-use wither::WitherError;
 
 use crate::errors::Error;
+use crate::models::subscription::Subscription;
+use crate::settings::Retention;
 use crate::utils::database_model::ModelExt;
 use crate::utils::date::now;
 use crate::utils::date::Date;
-use crate::utils::serde::bson_datetime_option_as_rfc3339_string;
+use crate::utils::extract::extract_article;
+use crate::utils::get_feed::{get_page, Error as GetFeedError};
+use crate::utils::hash::sha256;
+use crate::utils::html;
+use crate::utils::podcast::Episode;
+use crate::utils::scrape::ScrapedItem;
+use crate::utils::serde::{
+  bson_datetime_option_as_rfc3339_string, bson_datetime_option_from_rfc3339_string,
+};
+
+// Maximum number of characters of the entry excerpt.
+const EXCERPT_LENGTH: usize = 280;
 
 lazy_static! {
   static ref SORT_DESC: FindOneOptions = FindOneOptions::builder().sort(doc! { "_id": -1 }).build();
@@ -33,16 +46,137 @@ pub struct Entry {
   #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
   pub id: Option<ObjectId>,
   pub feed: ObjectId,
+  // ID used to match the entry between syncs, given by the feed ID strategy.
+  // The guid is the ID parsed by feed-rs, which generates one when the entry
+  // does not have it. The fingerprint is a hash of the title, published date
+  // and link.
   pub public_id: String,
+  pub guid: Option<String>,
+  pub fingerprint: Option<String>,
   pub url: Option<String>,
   pub title: Option<String>,
   pub description: Option<String>,
   pub published_at: Option<Date>,
+
+  // Full content of the entry, usually HTML, and the rest of the metadata
+  // parsed from the feed.
+  pub content: Option<String>,
+  #[serde(default)]
+  pub authors: Vec<Author>,
+  #[serde(default)]
+  pub categories: Vec<String>,
+  #[serde(default)]
+  pub links: Vec<Link>,
+  #[serde(default)]
+  pub enclosures: Vec<Enclosure>,
+  #[serde(default)]
+  pub thumbnails: Vec<Thumbnail>,
+  pub language: Option<String>,
+  // Last time the publisher modified the entry, as reported by the feed.
+  pub modified_at: Option<Date>,
+  // iTunes and Podcasting 2.0 metadata of podcast episodes.
+  pub podcast: Option<Episode>,
+
+  // Sanitized HTML and plain text versions of the description and content,
+  // and a plain text excerpt. Entries stored before these existed are
+  // rendered when they are sent.
+  pub description_html: Option<String>,
+  pub description_text: Option<String>,
+  pub content_html: Option<String>,
+  pub content_text: Option<String>,
+  pub excerpt: Option<String>,
+
+  // Main article of the page the entry links to, extracted for subscriptions
+  // that opted in to receive the full content. Pages are fetched once, the
+  // fetch date is stored even when the extraction fails.
+  pub full_content: Option<String>,
+  pub full_content_fetched_at: Option<Date>,
+
+  // Hash of the entry content, used to detect entries the publisher changed
+  // after we stored them. The revision starts at 0 and increments on every
+  // change.
+  pub content_hash: Option<String>,
+  #[serde(default)]
+  pub revision: i32,
+  pub updated_at: Option<Date>,
+
   pub created_at: Date,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Author {
+  pub name: String,
+  pub email: Option<String>,
+  pub uri: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+  pub href: String,
+  pub rel: Option<String>,
+  pub content_type: Option<String>,
+  pub title: Option<String>,
+}
+
+/// File attached to the entry, E.g. podcast audio or an image. Duration is in
+/// seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Enclosure {
+  pub url: String,
+  pub content_type: Option<String>,
+  pub length: Option<i64>,
+  pub duration: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Thumbnail {
+  pub url: String,
+  pub width: Option<i32>,
+  pub height: Option<i32>,
+}
+
+/// How entry public IDs are assigned. Feeds start with the guid strategy and
+/// move to the next strategy when their IDs are unstable across fetches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdStrategy {
+  #[default]
+  Guid,
+  Link,
+  Hash,
+}
+
+/// Representation of the entry description and content sent to subscriptions.
+/// Raw is the markup as found in the feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryFormat {
+  #[default]
+  Raw,
+  Html,
+  Text,
+}
+
+impl IdStrategy {
+  fn next(&self) -> Option<Self> {
+    match self {
+      IdStrategy::Guid => Some(IdStrategy::Link),
+      IdStrategy::Link => Some(IdStrategy::Hash),
+      IdStrategy::Hash => None,
+    }
+  }
+}
+
 impl Entry {
-  pub fn from_raw_entry(feed: ObjectId, raw_entry: RawEntry) -> Self {
+  pub fn from_raw_entry(feed: ObjectId, raw_entry: RawEntry, id_strategy: IdStrategy) -> Self {
+    let mut entry = Self::new(feed, raw_entry);
+    entry.prepare(id_strategy);
+    entry
+  }
+
+  /// Entry with the fields of the raw entry, before it is rendered and its
+  /// hashes and public ID are computed.
+  fn new(feed: ObjectId, raw_entry: RawEntry) -> Self {
     let url = raw_entry.links.get(0).map(|link| link.href.clone());
     let title = raw_entry.title.clone().map(|title| title.content);
     let description = raw_entry.summary.clone().map(|summary| summary.content);
@@ -51,66 +185,446 @@ impl Entry {
     Self {
       id: None,
       feed,
-      public_id: raw_entry.id,
       url,
       title,
       description,
       published_at,
+      content: raw_entry
+        .content
+        .as_ref()
+        .and_then(|content| content.body.clone()),
+      authors: get_authors(&raw_entry),
+      categories: get_categories(&raw_entry),
+      links: get_links(&raw_entry),
+      enclosures: get_enclosures(&raw_entry),
+      thumbnails: get_thumbnails(&raw_entry),
+      language: raw_entry.language.clone(),
+      modified_at: raw_entry.updated.map(|updated| updated.into()),
+      podcast: None,
+      description_html: None,
+      description_text: None,
+      content_html: None,
+      content_text: None,
+      excerpt: None,
+      full_content: None,
+      full_content_fetched_at: None,
+      public_id: raw_entry.id.clone(),
+      guid: Some(raw_entry.id),
+      fingerprint: None,
+      content_hash: None,
+      revision: 0,
+      updated_at: None,
       created_at: now(),
     }
   }
 
-  /// Order is extremely important, entries should be sorted chronologically
-  /// (From oldest to newest). The biggest MongoDB ID will end up being the most
-  /// recent feed entry.
-  pub async fn sync(feed: &ObjectId, entries: Vec<Entry>) -> Result<(), Error> {
-    let entries_count: u64 = entries
-      .len()
-      .try_into()
-      .expect("Failed to convert entries len to u64");
-
-    debug!("Upserting {} new entries to feed {}", entries.len(), feed);
-    stream::iter(entries)
-      // This is done sequential because we depend on the insertion order. We do
-      // not use the insert_many function because it does not allow to insert
-      // docs in order and skip duplicates. This can be improved in the future
-      // using a bulk insert.
-      .for_each(|entry| async move {
-        let public_id = entry.public_id.clone();
-        let res = <Entry as ModelExt>::create(entry).await;
-        if let Err(err) = res {
-          let is_duplicate = is_duplicate_error(&err);
-          if !is_duplicate {
-            error!(
-              "Error inserting entry with public ID {} to feed {}. Error: {}",
-              public_id, &feed, err
-            );
-          }
+  /// Entry from an item found in an HTML page or a JSON API response. Items
+  /// are identified by their ID or link, items without both by their
+  /// fingerprint.
+  pub fn from_scraped_item(feed: ObjectId, item: ScrapedItem, id_strategy: IdStrategy) -> Self {
+    let mut entry = Self::new(feed, RawEntry::default());
+    entry.url = item.url;
+    entry.title = item.title;
+    entry.description = item.summary;
+    entry.published_at = item.published_at.map(Into::into);
+    entry.links = entry
+      .url
+      .iter()
+      .map(|url| Link {
+        href: url.clone(),
+        rel: None,
+        content_type: None,
+        title: None,
+      })
+      .collect();
+    entry.guid = item.id.or_else(|| entry.url.clone());
+
+    entry.prepare(id_strategy);
+    entry
+  }
+
+  /// Render the entry and compute its hashes and public ID, once its fields
+  /// are set.
+  fn prepare(&mut self, id_strategy: IdStrategy) {
+    self.render();
+    self.content_hash = Some(self.get_content_hash());
+    self.fingerprint = Some(self.get_fingerprint());
+    self.public_id = self.get_public_id(id_strategy);
+  }
+
+  /// Render the sanitized HTML and plain text versions of the description and
+  /// content, and the excerpt.
+  fn render(&mut self) {
+    let base_url = self.url.as_deref();
+    self.description_html = self
+      .description
+      .as_deref()
+      .map(|description| html::sanitize(description, base_url));
+    self.description_text = self.description.as_deref().map(html::to_text);
+    self.content_html = self
+      .content
+      .as_deref()
+      .map(|content| html::sanitize(content, base_url));
+    self.content_text = self.content.as_deref().map(html::to_text);
+    self.excerpt = self
+      .description_text
+      .as_ref()
+      .or(self.content_text.as_ref())
+      .filter(|text| !text.is_empty())
+      .map(|text| html::excerpt(text, EXCERPT_LENGTH));
+  }
+
+  /// Fetch the page the entry links to and store its main article as the full
+  /// content. Pages are only fetched once per entry, failed fetches are not
+  /// retried unless the host is rate limiting us.
+  pub async fn fetch_full_content(&mut self) -> Result<(), Error> {
+    let url = match (&self.url, self.full_content_fetched_at) {
+      (Some(url), None) => url.clone(),
+      _ => return Ok(()),
+    };
+
+    self.full_content = match get_page(url.clone()).await {
+      Ok((page, url)) => extract_article(&page, &url),
+      Err(err @ GetFeedError::RateLimited(_)) => return Err(err.into()),
+      Err(err) => {
+        debug!("Failed to fetch the page of entry {}. Error: {}", &url, err);
+        None
+      }
+    };
+    self.full_content_fetched_at = Some(now());
+
+    <Entry as ModelExt>::update_one(
+      doc! { "_id": self.id.unwrap() },
+      doc! {
+        "$set": {
+          "full_content": &self.full_content,
+          "full_content_fetched_at": self.full_content_fetched_at,
+        }
+      },
+      None,
+    )
+    .await?;
+
+    Ok(())
+  }
+
+  fn get_fingerprint(&self) -> String {
+    let content = serde_json::json!([
+      self.title,
+      self.published_at.map(|date| date.timestamp_millis()),
+      self.url,
+    ]);
+    sha256(content.to_string())
+  }
+
+  /// Public ID of the entry using the given strategy. Falls back to the next
+  /// strategy when the entry does not have a link.
+  fn get_public_id(&self, id_strategy: IdStrategy) -> String {
+    let fingerprint = || {
+      self
+        .fingerprint
+        .clone()
+        .unwrap_or_else(|| self.get_fingerprint())
+    };
+
+    // Entries without a guid use their previous public ID, scraped items
+    // without an ID nor a link do not have one and use their fingerprint.
+    let public_id = || Some(self.public_id.clone()).filter(|public_id| !public_id.is_empty());
+
+    match id_strategy {
+      IdStrategy::Guid => self
+        .guid
+        .clone()
+        .or_else(public_id)
+        .unwrap_or_else(fingerprint),
+      IdStrategy::Link => self.url.clone().unwrap_or_else(fingerprint),
+      IdStrategy::Hash => fingerprint(),
+    }
+  }
+
+  /// Find the first strategy, starting from the current one, that assigns the
+  /// same IDs to the given entries as to the stored entries with the same
+  /// fingerprint. Feeds without real IDs get a different generated guid on
+  /// every fetch.
+  pub async fn find_id_strategy(
+    feed: &ObjectId,
+    entries: &[Entry],
+    id_strategy: IdStrategy,
+  ) -> Result<IdStrategy, Error> {
+    let fingerprints = entries
+      .iter()
+      .filter_map(|entry| entry.fingerprint.clone())
+      .collect::<Vec<String>>();
+
+    let stored = <Entry as ModelExt>::find(
+      doc! { "feed": feed, "fingerprint": { "$in": fingerprints } },
+      None,
+    )
+    .await?
+    .into_iter()
+    .filter_map(|entry| Some((entry.fingerprint.clone()?, entry)))
+    .collect::<HashMap<String, Entry>>();
+
+    let is_stable = |id_strategy: IdStrategy| {
+      entries.iter().all(|entry| {
+        let stored = entry
+          .fingerprint
+          .as_ref()
+          .and_then(|fingerprint| stored.get(fingerprint));
+        match stored {
+          Some(stored) => stored.get_public_id(id_strategy) == entry.get_public_id(id_strategy),
+          None => true,
         }
       })
+    };
+
+    let mut id_strategy = id_strategy;
+    while !is_stable(id_strategy) {
+      id_strategy = match id_strategy.next() {
+        Some(next) => next,
+        None => break,
+      };
+    }
+
+    Ok(id_strategy)
+  }
+
+  /// Assign new public IDs to the stored entries of the feed using the given
+  /// strategy, so they match the entries of the next syncs.
+  pub async fn set_id_strategy(feed: &ObjectId, id_strategy: IdStrategy) -> Result<(), Error> {
+    let entries = <Entry as ModelExt>::find(doc! { "feed": feed }, None).await?;
+
+    for entry in entries {
+      let public_id = entry.get_public_id(id_strategy);
+      if public_id == entry.public_id {
+        continue;
+      }
+
+      let res = <Entry as ModelExt>::update_one(
+        doc! { "_id": entry.id.unwrap() },
+        doc! { "$set": { "public_id": &public_id } },
+        None,
+      )
       .await;
 
-    // Remove outdated entries from the database.
-    let options = FindOneOptions::builder()
-      .sort(doc! { "_id": -1_i32 })
-      .skip(Some(entries_count))
-      .build();
-    let query = doc! { "feed": feed };
-    let entry = <Entry as ModelExt>::find_one(query, options.into()).await?;
-
-    let removed = match entry {
-      // There are no outdated entries.
-      None => 0,
-      Some(entry) => {
-        let query = doc! { "feed": feed, "_id": { "$lte": entry.id.unwrap() } };
-        let res = <Entry as ModelExt>::delete_many(query).await?;
-        res.deleted_count
+      // Entries sharing a link keep their previous ID.
+      if let Err(err) = res {
+        error!(
+          "Failed to update public ID of entry {:?} of feed {}. Error: {}",
+          entry.id, feed, err
+        );
       }
-    };
-    debug!("Removed {} outdated entries from feed {}", removed, feed);
+    }
 
     Ok(())
   }
+
+  /// Use the given strategy for the entry public ID.
+  pub fn set_public_id(&mut self, id_strategy: IdStrategy) {
+    self.public_id = self.get_public_id(id_strategy);
+  }
+
+  /// Hash of the entry fields sent to the subscriptions.
+  fn get_content_hash(&self) -> String {
+    let content = serde_json::json!([
+      self.url,
+      self.title,
+      self.description,
+      self.published_at.map(|date| date.timestamp_millis()),
+      self.content,
+    ]);
+    sha256(content.to_string())
+  }
+
+  /// Date used to order new entries, feeds do not always list entries from
+  /// newest to oldest.
+  fn get_date(&self) -> Option<Date> {
+    self.published_at.or(self.modified_at)
+  }
+
+  /// Sync the entries of a feed with the stored entries. Entries are matched by
+  /// their public ID, new entries are inserted and changed entries updated.
+  ///
+  /// Order is extremely important, new entries are inserted chronologically
+  /// (From oldest to newest) so the biggest MongoDB ID ends up being the most
+  /// recent feed entry. Entries are expected in feed order, which is usually
+  /// from newest to oldest, and sorted by their dates.
+  pub async fn sync(
+    feed: &ObjectId,
+    entries: Vec<Entry>,
+    retention: &Retention,
+  ) -> Result<Synced, Error> {
+    let public_ids = entries
+      .iter()
+      .map(|entry| entry.public_id.clone())
+      .collect::<Vec<String>>();
+
+    let stored = <Entry as ModelExt>::find(
+      doc! { "feed": feed, "public_id": { "$in": &public_ids } },
+      None,
+    )
+    .await?
+    .into_iter()
+    .map(|entry| (entry.public_id.clone(), entry))
+    .collect::<HashMap<String, Entry>>();
+
+    let mut statements: Vec<Document> = vec![];
+    let mut new_entries: Vec<Entry> = vec![];
+    let mut total = 0;
+    let mut backfilled = 0;
+    for entry in entries {
+      match stored.get(&entry.public_id) {
+        Some(stored) => {
+          total += 1;
+          if let Some((update, is_updated)) = get_update(stored, &entry)? {
+            if !is_updated {
+              backfilled += 1;
+            }
+            statements.push(doc! { "q": { "_id": stored.id.unwrap() }, "u": update });
+          }
+        }
+        // Feeds can list the same entry twice.
+        None
+          if new_entries
+            .iter()
+            .any(|new_entry| new_entry.public_id == entry.public_id) => {}
+        None => {
+          total += 1;
+          new_entries.push(entry);
+        }
+      }
+    }
+
+    // Entries without dates keep their feed position, the feed order is
+    // reversed first because feeds usually list the newest entries first.
+    new_entries.reverse();
+    let new_entries = sort_by_date(new_entries);
+
+    // IDs are generated here, in insertion order, and the bulk update is
+    // ordered so the entries are stored chronologically. New entries are
+    // upserted by their public ID, an entry inserted by a concurrent sync is
+    // left untouched.
+    for mut entry in new_entries {
+      entry.id = Some(ObjectId::new());
+      statements.push(doc! {
+        "q": { "feed": feed, "public_id": &entry.public_id },
+        "u": { "$setOnInsert": bson::to_document(&entry)? },
+        "upsert": true,
+      });
+    }
+
+    let res = <Entry as ModelExt>::bulk_update(statements).await?;
+    // Statements only storing the content hash of old entries are not counted
+    // as updates.
+    let inserted = res.upserted.len() as u64;
+    let updated = res.modified.saturating_sub(backfilled);
+    let synced = Synced {
+      inserted,
+      updated,
+      unchanged: total - inserted - updated,
+      removed: 0,
+    };
+    debug!(
+      "Synced entries of feed {}. inserted={} updated={} unchanged={}",
+      feed, synced.inserted, synced.updated, synced.unchanged
+    );
+
+    let removed = Self::remove_expired(feed, retention, &public_ids).await?;
+    debug!("Removed {} expired entries from feed {}", removed, feed);
+
+    Ok(Synced { removed, ..synced })
+  }
+
+  /// Remove the feed entries exceeding the retention policy. Entries still
+  /// listed in the feed are never removed, otherwise they would be inserted
+  /// again as new entries on the next sync. Entries not yet sent to every
+  /// subscription of the feed are never removed either.
+  async fn remove_expired(
+    feed: &ObjectId,
+    retention: &Retention,
+    public_ids: &[String],
+  ) -> Result<u64, Error> {
+    let mut expired: Vec<Document> = vec![];
+
+    if let Some(max_entries) = retention.max_entries {
+      let options = FindOneOptions::builder()
+        .sort(doc! { "_id": -1_i32 })
+        .skip(Some(max_entries.max(0) as u64))
+        .build();
+      let entry = <Entry as ModelExt>::find_one(doc! { "feed": feed }, Some(options)).await?;
+      if let Some(entry) = entry {
+        expired.push(doc! { "_id": { "$lte": entry.id.unwrap() } });
+      }
+    }
+
+    if let Some(max_age_days) = retention.max_age_days {
+      let created_before: Date = (Utc::now() - Duration::days(max_age_days)).into();
+      expired.push(doc! { "created_at": { "$lt": created_before } });
+    }
+
+    if expired.is_empty() {
+      return Ok(0);
+    }
+
+    // Subscriptions receive the entries after their last notified entry, or
+    // every entry when they were never notified.
+    let subscriptions = <Subscription as ModelExt>::find(doc! { "feed": feed }, None).await?;
+    let mut query = doc! {
+      "feed": feed,
+      "public_id": { "$nin": public_ids },
+      "$or": expired,
+    };
+    if !subscriptions.is_empty() {
+      let last_notified_entries = subscriptions
+        .iter()
+        .map(|subscription| subscription.last_notified_entry)
+        .collect::<Option<Vec<ObjectId>>>();
+
+      match last_notified_entries.and_then(|ids| ids.into_iter().min()) {
+        Some(oldest) => query.insert("_id", doc! { "$lt": oldest }),
+        None => return Ok(0),
+      };
+    }
+
+    let res = <Entry as ModelExt>::delete_many(query).await?;
+    Ok(res.deleted_count)
+  }
+}
+
+/// Sort the entries with a date among the positions of the entries with a
+/// date, entries without a date stay in their position.
+fn sort_by_date(entries: Vec<Entry>) -> Vec<Entry> {
+  let mut entries = entries
+    .into_iter()
+    .map(Some)
+    .collect::<Vec<Option<Entry>>>();
+  let positions = entries
+    .iter()
+    .enumerate()
+    .filter(|(_, entry)| entry.as_ref().and_then(Entry::get_date).is_some())
+    .map(|(position, _)| position)
+    .collect::<Vec<usize>>();
+
+  let mut dated = positions
+    .iter()
+    .filter_map(|position| entries[*position].take())
+    .collect::<Vec<Entry>>();
+  dated.sort_by_key(Entry::get_date);
+
+  for (position, entry) in positions.into_iter().zip(dated) {
+    entries[position] = Some(entry);
+  }
+  entries.into_iter().flatten().collect()
+}
+
+/// Result of syncing the entries of a feed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Synced {
+  pub inserted: u64,
+  pub updated: u64,
+  pub unchanged: u64,
+  /// Expired entries removed after the sync.
+  pub removed: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -118,59 +632,239 @@ pub struct PublicEntry {
   pub url: Option<String>,
   pub title: Option<String>,
   pub description: Option<String>,
-  #[serde(serialize_with = "bson_datetime_option_as_rfc3339_string")]
+  #[serde(
+    default,
+    serialize_with = "bson_datetime_option_as_rfc3339_string",
+    deserialize_with = "bson_datetime_option_from_rfc3339_string"
+  )]
   pub published_at: Option<Date>,
+  pub content: Option<String>,
+  #[serde(default)]
+  pub authors: Vec<Author>,
+  #[serde(default)]
+  pub categories: Vec<String>,
+  #[serde(default)]
+  pub links: Vec<Link>,
+  #[serde(default)]
+  pub enclosures: Vec<Enclosure>,
+  #[serde(default)]
+  pub thumbnails: Vec<Thumbnail>,
+  pub language: Option<String>,
+  #[serde(
+    default,
+    serialize_with = "bson_datetime_option_as_rfc3339_string",
+    deserialize_with = "bson_datetime_option_from_rfc3339_string"
+  )]
+  pub modified_at: Option<Date>,
+  pub podcast: Option<Episode>,
+  pub excerpt: Option<String>,
+  #[serde(default)]
+  pub revision: i32,
+  #[serde(
+    default,
+    serialize_with = "bson_datetime_option_as_rfc3339_string",
+    deserialize_with = "bson_datetime_option_from_rfc3339_string"
+  )]
+  pub updated_at: Option<Date>,
 }
 
-impl From<Entry> for PublicEntry {
-  fn from(entry: Entry) -> Self {
+impl PublicEntry {
+  /// Public entry with the description and content in the given format. The
+  /// full content extracted from the entry page replaces the content when
+  /// requested and available.
+  pub fn from_entry(mut entry: Entry, format: EntryFormat, full_content: bool) -> Self {
+    // Entries stored before the renders existed.
+    if entry.excerpt.is_none() {
+      entry.render();
+    }
+
+    let (description, content) = match format {
+      EntryFormat::Raw => (entry.description, entry.content),
+      EntryFormat::Html => (entry.description_html, entry.content_html),
+      EntryFormat::Text => (entry.description_text, entry.content_text),
+    };
+    let content = match (full_content, entry.full_content) {
+      (true, Some(full_content)) if format == EntryFormat::Text => {
+        Some(html::to_text(&full_content))
+      }
+      (true, Some(full_content)) => Some(full_content),
+      _ => content,
+    };
+
     Self {
       url: entry.url,
       title: entry.title,
-      description: entry.description,
+      description,
       published_at: entry.published_at,
+      content,
+      authors: entry.authors,
+      categories: entry.categories,
+      links: entry.links,
+      enclosures: entry.enclosures,
+      thumbnails: entry.thumbnails,
+      language: entry.language,
+      modified_at: entry.modified_at,
+      podcast: entry.podcast,
+      excerpt: entry.excerpt,
+      revision: entry.revision,
+      updated_at: entry.updated_at,
     }
   }
 }
 
-// TODO: Move this to a separate file where the conversion between raw feed and
-// feed happens
+impl From<Entry> for PublicEntry {
+  fn from(entry: Entry) -> Self {
+    Self::from_entry(entry, EntryFormat::Raw, false)
+  }
+}
+
 impl From<RawEntry> for PublicEntry {
   fn from(entry: RawEntry) -> Self {
-    let url = entry.links.get(0).map(|link| link.href.clone());
-    let title = entry.title.clone().map(|title| title.content);
-    let description = entry.summary.clone().map(|summary| summary.content);
-    let published_at = entry.published.map(|published| published.into());
+    // The entry is not stored, the feed ID is not used.
+    Entry::from_raw_entry(ObjectId::new(), entry, IdStrategy::default()).into()
+  }
+}
 
-    Self {
-      url,
-      title,
-      description,
-      published_at,
+fn get_authors(raw_entry: &RawEntry) -> Vec<Author> {
+  raw_entry
+    .authors
+    .iter()
+    .filter(|person| !person.name.trim().is_empty())
+    .map(|person| Author {
+      name: person.name.trim().to_owned(),
+      email: person.email.clone(),
+      uri: person.uri.clone(),
+    })
+    .collect()
+}
+
+fn get_categories(raw_entry: &RawEntry) -> Vec<String> {
+  let mut categories: Vec<String> = vec![];
+  for category in raw_entry.categories.iter() {
+    let name = category.label.as_ref().unwrap_or(&category.term).trim();
+    if !name.is_empty() && !categories.iter().any(|existing| existing == name) {
+      categories.push(name.to_owned());
     }
   }
+  categories
 }
 
-// TODO: Extend wither error to do this.
-fn is_duplicate_error(error: &Error) -> bool {
-  use wither::mongodb::error::CommandError;
-  use wither::mongodb::error::ErrorKind;
+fn get_links(raw_entry: &RawEntry) -> Vec<Link> {
+  raw_entry
+    .links
+    .iter()
+    .map(|link| Link {
+      href: link.href.clone(),
+      rel: link.rel.clone(),
+      content_type: link.media_type.clone(),
+      title: link.title.clone(),
+    })
+    .collect()
+}
 
-  // Is wither error.
-  let error = match error {
-    Error::Wither(error) => error,
-    _ => return false,
-  };
+/// Enclosures come from links with the enclosure relation (Atom) and Media RSS
+/// contents, feed-rs parses RSS enclosures as media contents.
+fn get_enclosures(raw_entry: &RawEntry) -> Vec<Enclosure> {
+  let links = raw_entry
+    .links
+    .iter()
+    .filter(|link| link.rel.as_deref() == Some("enclosure"))
+    .map(|link| Enclosure {
+      url: link.href.clone(),
+      content_type: link.media_type.clone(),
+      length: link.length.and_then(|length| length.try_into().ok()),
+      duration: None,
+    });
 
-  // Is Mongo error.
-  let error = match error {
-    WitherError::Mongo(error) => error,
-    _ => return false,
-  };
+  let media = raw_entry
+    .media
+    .iter()
+    .flat_map(|media| media.content.iter())
+    .filter_map(|content| {
+      let url = content.url.as_ref()?;
+      Some(Enclosure {
+        url: url.to_string(),
+        content_type: content
+          .content_type
+          .as_ref()
+          .map(|content_type| content_type.to_string()),
+        length: content.size.and_then(|size| size.try_into().ok()),
+        duration: content
+          .duration
+          .and_then(|duration| duration.as_secs().try_into().ok()),
+      })
+    });
+
+  let mut enclosures: Vec<Enclosure> = vec![];
+  for enclosure in links.chain(media) {
+    if !enclosures
+      .iter()
+      .any(|existing| existing.url == enclosure.url)
+    {
+      enclosures.push(enclosure);
+    }
+  }
+  enclosures
+}
+
+fn get_thumbnails(raw_entry: &RawEntry) -> Vec<Thumbnail> {
+  raw_entry
+    .media
+    .iter()
+    .flat_map(|media| media.thumbnails.iter())
+    .map(|thumbnail| Thumbnail {
+      url: thumbnail.image.uri.clone(),
+      width: thumbnail
+        .image
+        .width
+        .and_then(|width| width.try_into().ok()),
+      height: thumbnail
+        .image
+        .height
+        .and_then(|height| height.try_into().ok()),
+    })
+    .collect()
+}
 
-  // TODO: Not sure how to pattern match a Box.
-  let kind = *error.kind.clone();
+/// Update for the stored entry when its content changed, incrementing its
+/// revision. The flag is true when the update is an actual entry update.
+fn get_update(stored: &Entry, entry: &Entry) -> Result<Option<(Document, bool)>, Error> {
+  // Podcast metadata is not part of the content hash, entries stored before
+  // it was parsed get it without being considered updated.
+  if stored.content_hash == entry.content_hash {
+    return match stored.podcast == entry.podcast {
+      true => Ok(None),
+      false => Ok(Some((
+        doc! { "$set": { "podcast": bson::to_bson(&entry.podcast)? } },
+        false,
+      ))),
+    };
+  }
+
+  // Entries stored before content hashes existed only get their hash, we do
+  // not know if they changed.
+  let update = match stored.content_hash {
+    None => (
+      doc! { "$set": { "content_hash": &entry.content_hash } },
+      false,
+    ),
+    Some(_) => {
+      let mut set = bson::to_document(entry)?;
+      for key in [
+        "_id",
+        "feed",
+        "public_id",
+        "revision",
+        "full_content",
+        "full_content_fetched_at",
+        "created_at",
+      ] {
+        set.remove(key);
+      }
+      set.insert("updated_at", now());
+      (doc! { "$set": set, "$inc": { "revision": 1_i32 } }, true)
+    }
+  };
 
-  // Is duplicate
-  matches!(kind, ErrorKind::Command(CommandError { code: 11000, .. }))
+  Ok(Some(update))
 }
@@ -1,61 +1,232 @@
 use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
-use feed_rs::model::Feed as RawFeed;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::time::Instant;
 use tracing::{debug, error};
 use validator::Validate;
-use wither::bson::{doc, oid::ObjectId};
-use wither::mongodb::options::FindOptions;
+use wither::bson::{doc, oid::ObjectId, Bson, Document};
+use wither::mongodb::options::FindOneOptions;
 use wither::Model as WitherModel;
 
+use crate::errors::BadRequest;
 use crate::errors::Error;
 use crate::errors::NotFound;
-use crate::models::entry::Entry;
+use crate::models::entry::{Entry, IdStrategy};
+use crate::models::feed_fetch::{FeedFetch, FetchOutcome};
+use crate::models::job::{Job, JobKind};
 use crate::models::subscription::Subscription;
+use crate::settings::{get_settings, Retention};
+use crate::utils::create_random_string::create_random_string;
 use crate::utils::database_model::ModelExt;
 use crate::utils::date::{now, Date};
-use crate::utils::get_feed::get_feed;
+use crate::utils::get_feed::Error as GetFeedError;
+use crate::utils::get_feed::{
+  fetch_items, get_feed_if_modified, get_permanent_url, resolve_feed, Content, Fetched, Resolved,
+  Source, Validators,
+};
+use crate::utils::map_json::JsonMapping;
+use crate::utils::normalize_url::{get_url_variants, normalize_url};
+use crate::utils::podcast::Podcast;
+use crate::utils::scrape::Selectors;
+use crate::utils::serde::bson_datetime_option_as_rfc3339_string;
+use crate::utils::sync_schedule::{get_next_sync_at, get_retry_at, FeedHints, ResponseHints};
+use crate::utils::websub::{self, Hub, Mode};
+
+// Hours given to hubs to verify a WebSub subscription request before it is
+// sent again.
+const WEBSUB_RETRY_HOURS: i64 = 1;
 
 impl ModelExt for Feed {
   type T = Feed;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
-#[model(index(keys = r#"doc!{ "url": 1 }"#, options = r#"doc!{ "unique": true }"#))]
+#[model(index(
+  keys = r#"doc!{ "url": 1, "selectors": 1, "mapping": 1 }"#,
+  options = r#"doc!{ "unique": true }"#
+))]
 #[model(index(keys = r#"doc!{ "synced_at": 1 }"#))]
+#[model(index(keys = r#"doc!{ "next_sync_at": 1 }"#))]
+#[model(index(keys = r#"doc!{ "aliases": 1 }"#))]
 pub struct Feed {
   #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
   pub id: Option<ObjectId>,
   pub public_id: String,
   pub feed_type: FeedType,
   pub url: String,
+  // Normalized URLs this feed is known by, including URLs the feed was moved
+  // from with a permanent redirect. Used to find the feed by any of them.
+  #[serde(default)]
+  pub aliases: Vec<String>,
   pub title: Option<String>,
   pub description: Option<String>,
+
+  // CSS selectors of feeds created from HTML pages without a feed, and JSON
+  // pointers of feeds created from JSON APIs. The same URL can be mapped in
+  // different ways, each one is a different feed.
+  pub selectors: Option<Selectors>,
+  pub mapping: Option<JsonMapping>,
+
+  // iTunes and Podcasting 2.0 metadata of podcast feeds, updated on every
+  // sync. Episode metadata is stored in the entries.
+  pub podcast: Option<Podcast>,
+
+  // HTTP cache validators and content hash from the last successful sync.
+  // These are sent back to the server on the next sync to avoid downloading
+  // and processing a feed that did not change.
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+  pub content_hash: Option<String>,
+
+  // Hints from the feed document used to schedule the next sync. Feeds
+  // without a next_sync_at date are synced as soon as possible.
+  #[serde(default)]
+  pub hints: FeedHints,
+  pub next_sync_at: Option<Date>,
+
+  // WebSub subscription for feeds that advertise a hub. The hub notifies us
+  // when the feed is updated, polling is only used as a fallback.
+  pub websub: Option<WebSub>,
+
+  // Entries retention, the default retention from the settings is used when
+  // it is not set.
+  pub retention: Option<Retention>,
+
+  // Strategy used to assign entry public IDs, changed automatically when the
+  // feed entry IDs are unstable across fetches.
+  #[serde(default)]
+  pub id_strategy: IdStrategy,
+
+  // Health of the feed based on the last syncs. Failed syncs are retried with
+  // an exponential backoff and dead feeds are no longer synced.
+  #[serde(default)]
+  pub status: FeedStatus,
+  #[serde(default)]
+  pub consecutive_failures: i32,
+  pub last_error: Option<String>,
+  pub last_error_at: Option<Date>,
+
+  // Lease of the instance syncing the feed, so that feeds are synced by one
+  // instance at a time. Expired leases are taken over by other instances.
+  pub locked_until: Option<Date>,
+  pub locked_by: Option<String>,
+
   pub synced_at: Date,
   pub updated_at: Date,
   pub created_at: Date,
 }
 
 impl Feed {
-  pub async fn from_url(url: String) -> Self {
-    // TODO: Remove this unwrap. This is important.
-    let raw_feed = get_feed(url.clone()).await.unwrap();
-
-    let title = raw_feed.title.clone().map(|title| title.content);
-    let description = raw_feed
-      .description
-      .clone()
-      .map(|description| description.content);
+  /// Create a feed from the given URL, which can be a website URL. In that
+  /// case the feed is created from the feed advertised by the website.
+  pub async fn from_url(url: String) -> Result<Self, Error> {
+    let requested_url = url;
+    let Resolved {
+      url,
+      feed: raw_feed,
+      hub,
+      podcast,
+      redirects,
+      ..
+    } = resolve_feed(requested_url.clone()).await?;
+
+    // The feed is also known by the URL given by the user and the URLs it was
+    // permanently redirected from.
+    let redirected_from = redirects
+      .iter()
+      .filter(|redirect| redirect.is_permanent())
+      .map(|redirect| &redirect.from);
+    let mut aliases = [&requested_url, &url]
+      .into_iter()
+      .chain(redirected_from)
+      .filter_map(|url| normalize_url(url).ok())
+      .collect::<Vec<String>>();
+    aliases.sort();
+    aliases.dedup();
+
+    let mut feed = Self::new(url, FeedType::from(raw_feed.feed_type));
+    feed.public_id = raw_feed.id;
+    feed.aliases = aliases;
+    feed.title = raw_feed.title.map(|title| title.content);
+    feed.description = raw_feed.description.map(|description| description.content);
+    feed.websub = hub.map(WebSub::new);
+    feed.podcast = podcast.map(|podcast| podcast.podcast);
+
+    Ok(feed)
+  }
+
+  /// Create a feed from an HTML page or a JSON API, the items are found using
+  /// the given selectors or JSON mapping. Fails when no item is found.
+  pub async fn from_source(
+    url: String,
+    selectors: Option<Selectors>,
+    mapping: Option<JsonMapping>,
+  ) -> Result<Self, Error> {
+    let feed_type = match (&selectors, &mapping) {
+      (Some(_), None) => FeedType::Html,
+      (None, Some(_)) => FeedType::JsonApi,
+      _ => {
+        return Err(Error::BadRequest(BadRequest::new(
+          "selectors",
+          "Invalid source",
+        )))
+      }
+    };
+
+    let mut feed = Self::new(url, feed_type);
+    feed.public_id = feed.url.clone();
+    feed.selectors = selectors;
+    feed.mapping = mapping;
+
+    let (scraped, _) = fetch_items(feed.url.clone(), feed.get_source()).await?;
+    if scraped.items.is_empty() {
+      return Err(Error::GetFeed(GetFeedError::NoItemsFound));
+    }
+    feed.title = scraped.title;
+
+    Ok(feed)
+  }
+
+  /// Where the feed entries come from.
+  pub fn get_source(&self) -> Source<'_> {
+    match (&self.selectors, &self.mapping) {
+      (Some(selectors), _) => Source::Html(selectors),
+      (None, Some(mapping)) => Source::Json(mapping),
+      (None, None) => Source::Feed,
+    }
+  }
+
+  fn new(url: String, feed_type: FeedType) -> Self {
     let now = now();
 
     Self {
       id: None,
-      public_id: raw_feed.id,
-      feed_type: FeedType::from(raw_feed.feed_type),
+      public_id: String::new(),
+      feed_type,
       url,
-      title,
-      description,
+      aliases: vec![],
+      title: None,
+      description: None,
+      selectors: None,
+      mapping: None,
+      podcast: None,
+      // Validators are set on the first sync, which is the one storing the
+      // feed entries.
+      etag: None,
+      last_modified: None,
+      content_hash: None,
+      hints: FeedHints::default(),
+      next_sync_at: None,
+      websub: None,
+      retention: None,
+      id_strategy: IdStrategy::default(),
+      status: FeedStatus::Healthy,
+      consecutive_failures: 0,
+      last_error: None,
+      last_error_at: None,
+      locked_until: None,
+      locked_by: None,
       updated_at: now,
       created_at: now,
       synced_at: now,
@@ -63,123 +234,727 @@ impl Feed {
   }
 
   /// Fetch the last RSS Feed version and store it's entries in the database.
-  /// If the feed has new entries, update the related subscriptions.
+  /// If the feed has new entries, update the related subscriptions. Every
+  /// sync is recorded in the feed fetch history. The app only syncs due feeds,
+  /// this syncs the feed whether it is due or not.
+  #[cfg(test)]
   pub async fn sync(id: ObjectId) -> Result<(), Error> {
+    Self::sync_matching(id, doc! {}).await
+  }
+
+  /// Sync the feed if it is still due to be synced. Another instance could
+  /// have synced it since it was found due.
+  pub async fn sync_due(id: ObjectId) -> Result<(), Error> {
+    Self::sync_matching(id, Self::get_due_query()).await
+  }
+
+  /// Query matching the feeds due to be synced. Feeds without a next_sync_at
+  /// date were never synced. Dead feeds are not synced.
+  pub fn get_due_query() -> Document {
+    doc! {
+      "status": { "$ne": "dead" },
+      "$or": [
+        { "next_sync_at": null },
+        { "next_sync_at": { "$lte": now() } }
+      ]
+    }
+  }
+
+  /// Queue the next sync of the feed at its next_sync_at date. Dead feeds
+  /// are no longer synced.
+  pub async fn schedule_sync(id: &ObjectId) -> Result<(), Error> {
+    let feed = match Self::find_by_id(id).await? {
+      Some(feed) if feed.status != FeedStatus::Dead => feed,
+      _ => return Ok(()),
+    };
+
+    let run_at = feed
+      .next_sync_at
+      .map(|next_sync_at| next_sync_at.to_chrono())
+      .unwrap_or_else(Utc::now);
+    Job::enqueue(JobKind::SyncFeed { feed: *id }, run_at).await
+  }
+
+  /// Sync the feed as soon as possible, e.g. when its hub notifies an update.
+  /// The feed is made due and its sync is queued like the scheduled ones, dead
+  /// feeds are not synced.
+  pub async fn request_sync(id: ObjectId) -> Result<(), Error> {
+    let query = doc! { "_id": id, "status": { "$ne": "dead" } };
+    let update = doc! { "$set": { "next_sync_at": now() } };
+    let result = Self::update_one(query, update, None).await?;
+    if result.matched_count == 0 {
+      debug!("Skipping sync request, Feed {} is dead or not found", &id);
+      return Ok(());
+    }
+
+    Job::enqueue(JobKind::SyncFeed { feed: id }, Utc::now()).await
+  }
+
+  /// Lease the feed and sync it when it matches the given query. Feeds leased
+  /// by another instance are skipped, that instance is already syncing them.
+  async fn sync_matching(id: ObjectId, mut query: Document) -> Result<(), Error> {
     debug!("Syncing feed {}", &id);
     let start = Instant::now();
 
-    let feed = Self::find_by_id(&id).await?;
-    let feed = match feed {
+    query.insert("_id", id);
+    let feed = match Self::acquire_lease(query).await? {
       Some(feed) => feed,
+      None if Self::exists(doc! { "_id": id }).await? => {
+        debug!("Skipping sync, Feed {} is leased or not due", &id);
+        return Ok(());
+      }
       None => {
         error!("Failed to sync, Feed with ID {} not found", &id);
         return Err(Error::NotFound(NotFound::new("feed")));
       }
     };
 
-    let url = feed.url.clone();
-    let raw_feed = get_feed(url.clone()).await;
-    let raw_feed = match raw_feed {
-      Ok(raw_feed) => raw_feed,
+    let mut fetch = FeedFetch::new(id, feed.url.clone());
+    let result = feed.sync_entries(&mut fetch).await;
+    if let Err(err) = &result {
+      fetch.error.get_or_insert_with(|| err.to_string());
+    }
+
+    // The fetch history is informative, failing to record a fetch does not
+    // fail the sync.
+    if let Err(err) = FeedFetch::create(fetch).await {
+      error!("Failed to record fetch of Feed {}. Error: {}", &id, err);
+    }
+
+    // Leases expire, failing to release one only delays the next sync.
+    if let Err(err) = Self::release_lease(&id).await {
+      error!("Failed to release lease of Feed {}. Error: {}", &id, err);
+    }
+
+    let duration = start.elapsed();
+    debug!("Finished syncing feed {} elapsed={:.0?}", &id, duration);
+
+    result
+  }
+
+  /// Fetch the feed and sync its entries, recording what happened in the
+  /// given fetch.
+  async fn sync_entries(&self, fetch: &mut FeedFetch) -> Result<(), Error> {
+    let id = self.id.unwrap();
+    let url = self.url.clone();
+    let fetch_start = Instant::now();
+    let fetched = get_feed_if_modified(url.clone(), &self.validators(), self.get_source()).await;
+    fetch.latency = Some(fetch_start.elapsed().as_millis() as i64);
+
+    let Fetched {
+      content,
+      status,
+      bytes,
+      validators,
+      hints: response_hints,
+      redirects,
+    } = match fetched {
+      Ok(fetched) => fetched,
+      // Being rate limited is not a feed failure, the feed is synced once the
+      // host allows it.
+      Err(GetFeedError::RateLimited(retry_at)) => {
+        debug!("Feed {} host is rate limiting us", &id);
+        fetch.outcome = FetchOutcome::RateLimited;
+        self.set_next_sync_at(retry_at).await?;
+        return Err(Error::GetFeed(GetFeedError::RateLimited(retry_at)));
+      }
       Err(err) => {
         error!("Failed to get Feed {}. Error: {}", &id, err);
-        // TODO: Return a proper error type. This is not a not found error.
-        return Err(Error::NotFound(NotFound::new("feed")));
+        if let GetFeedError::HttpStatus(status) = err {
+          fetch.status = Some(i32::from(status));
+        }
+        self.set_failed(&err).await?;
+        return Err(Error::GetFeed(err));
+      }
+    };
+    fetch.status = Some(i32::from(status));
+    fetch.bytes = Some(bytes as i64);
+
+    // Follow the feed when it permanently moves to a new URL. If we already
+    // have a feed for the new URL, this feed is merged into it. Feeds from HTML
+    // pages and JSON APIs keep their URL, the URL and its mapping identify
+    // them.
+    let is_feed_source = matches!(self.get_source(), Source::Feed);
+    let moved_url =
+      get_permanent_url(&redirects).filter(|moved_url| moved_url != &url && is_feed_source);
+    if let Some(moved_url) = moved_url {
+      if let Some(target) = self.move_to(moved_url).await? {
+        debug!("Feed {} was merged into Feed {:?}", &id, target.id);
+        fetch.outcome = FetchOutcome::Merged;
+        return Ok(());
+      }
+    }
+
+    let (mut entries, hints) = match content {
+      Content::Modified(raw_feed, hints, hub, podcast) => {
+        self.set_hub(hub).await?;
+        self
+          .set_podcast(podcast.as_ref().map(|podcast| podcast.podcast.clone()))
+          .await?;
+
+        let entries = raw_feed
+          .entries
+          .into_iter()
+          .map(|raw_entry| {
+            let episode = podcast
+              .as_ref()
+              .and_then(|podcast| podcast.get_episode(&raw_entry));
+            let mut entry = Entry::from_raw_entry(id, raw_entry, self.id_strategy);
+            entry.podcast = episode;
+            entry
+          })
+          .collect::<Vec<Entry>>();
+        (entries, hints)
+      }
+      Content::Scraped(page) => {
+        let entries = page
+          .items
+          .into_iter()
+          .map(|item| Entry::from_scraped_item(id, item, self.id_strategy))
+          .collect::<Vec<Entry>>();
+        (entries, FeedHints::default())
+      }
+      Content::NotModified => {
+        debug!("Feed {} was not modified", &id);
+        fetch.outcome = FetchOutcome::NotModified;
+        self
+          .set_synced(&validators, &self.hints, &response_hints)
+          .await?;
+        return Ok(());
+      }
+      Content::Unchanged => {
+        debug!("Feed {} content did not change", &id);
+        fetch.outcome = FetchOutcome::Unchanged;
+        self
+          .set_synced(&validators, &self.hints, &response_hints)
+          .await?;
+        return Ok(());
+      }
+      Content::Invalid(err) => {
+        error!("Failed to parse Feed {}. Error: {}", &id, err);
+        fetch.outcome = FetchOutcome::Invalid;
+        self.set_failed(&err).await?;
+        return Err(Error::GetFeed(err));
       }
     };
+    fetch.outcome = FetchOutcome::Modified;
+    fetch.entries = Some(entries.len() as i64);
 
-    let has_entries = !raw_feed.entries.is_empty();
-    if !has_entries {
+    if entries.is_empty() {
       debug!("Feed {} has no entries", &id);
+      self
+        .set_synced(&validators, &hints, &response_hints)
+        .await?;
       return Ok(());
     }
 
-    let is_synced = feed.is_synced(&raw_feed).await?;
-    if is_synced {
+    let id_strategy = Entry::find_id_strategy(&id, &entries, self.id_strategy).await?;
+    if id_strategy != self.id_strategy {
+      self.set_id_strategy(id_strategy).await?;
+      for entry in entries.iter_mut() {
+        entry.set_public_id(id_strategy);
+      }
+    }
+
+    let synced = Entry::sync(&id, entries, self.get_retention()).await?;
+    fetch.inserted = Some(synced.inserted as i64);
+    fetch.updated = Some(synced.updated as i64);
+    fetch.removed = Some(synced.removed as i64);
+    // Validators are stored after the entries, otherwise a failed entries sync
+    // would make the next sync skip this feed content.
+    let synced_at = self
+      .set_synced(&validators, &hints, &response_hints)
+      .await?;
+
+    if synced.inserted == 0 && synced.updated == 0 {
       debug!("Feed {} is synced", &id);
       return Ok(());
     }
 
-    let entries = raw_feed
-      .entries
-      .into_iter()
-      // Feed entries are sorted from most recent to least recent. We handle
-      // entries sorted chronologically (From oldest to newest).
-      .rev()
-      .map(|raw_entry| Entry::from_raw_entry(id, raw_entry))
-      .collect::<Vec<Entry>>();
+    // Set the scheduled_at attribute and queue a notification job so the
+    // subscriptions notify the user with the new or updated entries.
+    Subscription::update_many(
+      doc! { "feed": &id },
+      doc! {
+          "$set": {
+            "synced_at": &synced_at,
+            "scheduled_at": now()
+          }
+      },
+      None,
+    )
+    .await?;
+    let subscriptions = <Subscription as ModelExt>::collection()
+      .distinct("_id", doc! { "feed": &id }, None)
+      .await?;
+    for subscription in subscriptions.iter().filter_map(Bson::as_object_id) {
+      let kind = JobKind::NotifySubscription { subscription };
+      Job::enqueue(kind, Utc::now()).await?;
+    }
+
+    debug!(
+      "Synced feed {} inserted={} updated={} unchanged={} removed={}",
+      &id, synced.inserted, synced.updated, synced.unchanged, synced.removed
+    );
+
+    Ok(())
+  }
+
+  /// Set the feed synced_at date, the validators and hints from the last
+  /// fetch and schedule the next sync. A successful sync makes the feed
+  /// healthy again. Returns the synced_at date.
+  async fn set_synced(
+    &self,
+    validators: &Validators,
+    hints: &FeedHints,
+    response_hints: &ResponseHints,
+  ) -> Result<Date, Error> {
+    let id = self.id.unwrap();
+    let now = Utc::now();
+    let synced_at: Date = now.into();
+    let has_active_websub = self.websub.as_ref().is_some_and(WebSub::is_active);
+    let next_sync_at: Date = if has_active_websub {
+      // The hub notifies us when the feed is updated.
+      (now + Duration::seconds(get_settings().feeds.max_sync_interval)).into()
+    } else {
+      get_next_sync_at(hints, response_hints, now).into()
+    };
+
+    Self::update_one(
+      doc! { "_id": &id },
+      doc! {
+        "$set": {
+          "synced_at": &synced_at,
+          "next_sync_at": next_sync_at,
+          "etag": validators.etag.clone(),
+          "last_modified": validators.last_modified.clone(),
+          "content_hash": validators.hash.clone(),
+          "hints": bson::to_bson(hints)?,
+          "status": bson::to_bson(&FeedStatus::Healthy)?,
+          "consecutive_failures": 0_i32,
+        }
+      },
+      None,
+    )
+    .await?;
+
+    if self.status != FeedStatus::Healthy {
+      Self::set_subscriptions_health(&id, &FeedStatus::Healthy, None).await?;
+    }
+
+    Ok(synced_at)
+  }
 
-    Entry::sync(&id, entries).await?;
-    let synced_at = now();
+  /// Record a failed sync and schedule a retry with an exponential backoff.
+  /// The feed is marked as dead after too many consecutive failures.
+  async fn set_failed(&self, err: &GetFeedError) -> Result<(), Error> {
+    let id = self.id.unwrap();
+    let now = Utc::now();
+    let consecutive_failures = self.consecutive_failures + 1;
+    let max_consecutive_failures = get_settings().feeds.max_consecutive_failures;
+
+    let status = if consecutive_failures >= max_consecutive_failures {
+      FeedStatus::Dead
+    } else {
+      FeedStatus::Degraded
+    };
+
+    let last_error = err.to_string();
+    let last_error_at: Date = now.into();
+    let next_sync_at: Date = get_retry_at(consecutive_failures, now).into();
 
     Self::update_one(
       doc! { "_id": &id },
       doc! {
         "$set": {
-          "synced_at": &synced_at
+          "status": bson::to_bson(&status)?,
+          "consecutive_failures": consecutive_failures,
+          "last_error": &last_error,
+          "last_error_at": last_error_at,
+          "next_sync_at": next_sync_at,
         }
       },
       None,
     )
     .await?;
 
-    // Set the scheduled_at attribute so the subscription scheduler picks up
-    // this subscription to notify the user with the new entries.
+    Self::set_subscriptions_health(&id, &status, Some(last_error)).await
+  }
+
+  async fn set_next_sync_at(&self, next_sync_at: DateTime<Utc>) -> Result<(), Error> {
+    let next_sync_at: Date = next_sync_at.into();
+    Self::update_one(
+      doc! { "_id": self.id.unwrap() },
+      doc! { "$set": { "next_sync_at": next_sync_at } },
+      None,
+    )
+    .await?;
+
+    Ok(())
+  }
+
+  /// Copy the feed health to the feed subscriptions, these are exposed in the
+  /// subscription responses.
+  async fn set_subscriptions_health(
+    id: &ObjectId,
+    status: &FeedStatus,
+    error: Option<String>,
+  ) -> Result<(), Error> {
     Subscription::update_many(
-      doc! { "feed": &id },
+      doc! { "feed": id },
       doc! {
-          "$set": {
-            "synced_at": &synced_at,
-            "scheduled_at": now()
-          }
+        "$set": {
+          "feed_status": bson::to_bson(status)?,
+          "feed_error": error,
+        }
       },
       None,
     )
     .await?;
 
-    let duration = start.elapsed();
-    debug!("Finished syncing feed {} elapsed={:.0?}", &id, duration);
+    Ok(())
+  }
+
+  /// Store the WebSub hub advertised by the feed. A new or different hub
+  /// requires a new subscription, which is sent by the WebSub scheduler.
+  async fn set_hub(&self, hub: Option<Hub>) -> Result<(), Error> {
+    let id = self.id.unwrap();
+    let update = match (hub, &self.websub) {
+      (Some(hub), Some(websub)) if websub.hub == hub.url && websub.topic == hub.topic => {
+        return Ok(());
+      }
+      (Some(hub), _) => doc! { "$set": { "websub": bson::to_bson(&WebSub::new(hub))? } },
+      (None, Some(_)) => doc! { "$unset": { "websub": 1_i32 } },
+      (None, None) => return Ok(()),
+    };
+
+    Self::update_one(doc! { "_id": &id }, update, None).await?;
+    Ok(())
+  }
+
+  async fn set_podcast(&self, podcast: Option<Podcast>) -> Result<(), Error> {
+    if self.podcast == podcast {
+      return Ok(());
+    }
+
+    let id = self.id.unwrap();
+    Self::update_one(
+      doc! { "_id": &id },
+      doc! { "$set": { "podcast": bson::to_bson(&podcast)? } },
+      None,
+    )
+    .await?;
+    Ok(())
+  }
+
+  /// Query matching the feeds with a WebSub subscription that was never
+  /// requested, was not verified by the hub or is about to expire. Requests
+  /// are retried after an hour to give the hub time to verify them.
+  pub fn get_websub_due_query() -> Document {
+    let now = Utc::now();
+    let retry_at: Date = (now - Duration::hours(WEBSUB_RETRY_HOURS)).into();
+    let renew_at: Date = (now + Duration::days(1)).into();
+
+    doc! {
+      "websub": { "$ne": null },
+      "status": { "$ne": "dead" },
+      "$or": [
+        { "websub.requested_at": null },
+        {
+          "websub.subscribed_at": null,
+          "websub.requested_at": { "$lte": retry_at }
+        },
+        {
+          "websub.expires_at": { "$lte": renew_at },
+          "websub.requested_at": { "$lte": retry_at }
+        }
+      ]
+    }
+  }
+
+  /// Request the WebSub subscription of the feed if it is still due. The
+  /// request date is set in the same update that checks it, so a feed is
+  /// requested once even when several instances queued it.
+  pub async fn renew_websub(id: ObjectId) -> Result<(), Error> {
+    let mut query = Self::get_websub_due_query();
+    query.insert("_id", id);
+    let update = doc! { "$set": { "websub.requested_at": now() } };
+    let feed = match <Self as ModelExt>::find_one_and_update(query, update).await? {
+      Some(feed) => feed,
+      None => {
+        debug!("Skipping WebSub request, Feed {} is not due", &id);
+        return Ok(());
+      }
+    };
+
+    feed.request_websub(Mode::Subscribe).await
+  }
+
+  /// Send a subscription request to the feed WebSub hub. The hub calls the
+  /// WebSub callback route to verify the request.
+  pub async fn request_websub(&self, mode: Mode) -> Result<(), Error> {
+    let settings = &get_settings().websub;
+    let (websub, callback_url) = match (&self.websub, &settings.callback_url) {
+      (Some(websub), Some(callback_url)) => (websub, callback_url),
+      _ => return Ok(()),
+    };
+
+    let id = self.id.unwrap();
+    let callback = format!("{}/{}", callback_url.trim_end_matches('/'), id.to_hex());
+
+    if let Mode::Subscribe = mode {
+      Self::update_one(
+        doc! { "_id": &id },
+        doc! { "$set": { "websub.requested_at": now() } },
+        None,
+      )
+      .await?;
+    }
+
+    websub::request(
+      &websub.hub,
+      mode,
+      &websub.topic,
+      &callback,
+      &websub.secret,
+      settings.lease_seconds,
+    )
+    .await?;
+
+    Ok(())
+  }
+
+  /// Set the WebSub subscription as verified by the hub for the given lease.
+  /// The lease comes from the hub, it is bounded by the lease we requested.
+  pub async fn set_websub_subscribed(
+    id: &ObjectId,
+    lease_seconds: Option<i64>,
+  ) -> Result<(), Error> {
+    let max_lease_seconds = get_settings().websub.lease_seconds.max(1);
+    let lease_seconds = lease_seconds
+      .unwrap_or(max_lease_seconds)
+      .clamp(1, max_lease_seconds);
+    let now = Utc::now();
+    let subscribed_at: Date = now.into();
+    let expires_at: Date = (now + Duration::seconds(lease_seconds)).into();
+
+    Self::update_one(
+      doc! { "_id": id },
+      doc! {
+        "$set": {
+          "websub.subscribed_at": subscribed_at,
+          "websub.expires_at": expires_at,
+        }
+      },
+      None,
+    )
+    .await?;
 
     Ok(())
   }
 
-  /// Check if the feed has new entries. We take the last entries from the feed
-  /// and compare them with the last entries stored in the database. If the
-  /// entries are the same, the feed is synced.
-  async fn is_synced(&self, raw_feed: &RawFeed) -> Result<bool, Error> {
-    let feed_id = self.id.unwrap();
-    let their_entries_ids = raw_feed
-      .entries
+  /// The hub denied or cancelled the WebSub subscription. The feed is polled
+  /// until the WebSub scheduler subscribes again.
+  pub async fn set_websub_denied(id: &ObjectId) -> Result<(), Error> {
+    Self::update_one(
+      doc! { "_id": id },
+      doc! {
+        "$set": {
+          "websub.subscribed_at": null,
+          "websub.expires_at": null,
+        }
+      },
+      None,
+    )
+    .await?;
+
+    Ok(())
+  }
+
+  /// Switch the strategy used to assign entry public IDs, the stored entries
+  /// get new public IDs.
+  async fn set_id_strategy(&self, id_strategy: IdStrategy) -> Result<(), Error> {
+    let id = self.id.unwrap();
+    debug!(
+      "Feed {} entry IDs are unstable, switching from {:?} to {:?} strategy",
+      &id, self.id_strategy, id_strategy
+    );
+
+    Entry::set_id_strategy(&id, id_strategy).await?;
+    Self::update_one(
+      doc! { "_id": &id },
+      doc! { "$set": { "id_strategy": bson::to_bson(&id_strategy)? } },
+      None,
+    )
+    .await?;
+
+    Ok(())
+  }
+
+  /// Find the feed for the given URL, matching equivalent URLs and the URLs
+  /// the feed is also known by.
+  pub async fn find_by_url(url: &str) -> Result<Option<Self>, Error> {
+    let variants = get_url_variants(url);
+    <Self as ModelExt>::find_one(
+      doc! {
+        "selectors": null,
+        "mapping": null,
+        "$or": [
+          { "url": { "$in": &variants } },
+          { "aliases": { "$in": &variants } }
+        ]
+      },
+      None,
+    )
+    .await
+  }
+
+  /// Find the feed created from the HTML page or JSON API with the given
+  /// selectors or JSON mapping.
+  pub async fn find_by_source(
+    url: &str,
+    selectors: Option<&Selectors>,
+    mapping: Option<&JsonMapping>,
+  ) -> Result<Option<Self>, Error> {
+    <Self as ModelExt>::find_one(
+      doc! {
+        "url": url,
+        "selectors": bson::to_bson(&selectors)?,
+        "mapping": bson::to_bson(&mapping)?,
+      },
+      None,
+    )
+    .await
+  }
+
+  /// Add the given URLs to the feed aliases.
+  pub async fn add_aliases(id: &ObjectId, urls: &[String]) -> Result<(), Error> {
+    let aliases = urls
       .iter()
-      // If the first 3 items are the same, we consider the feed to be in
-      // synced. We assume good behavior, and we do not process a new inserted
-      // entry that is not at the top.
-      .take(3)
-      .map(|raw_entry| raw_entry.id.clone());
-
-    let our_entries = <Entry as ModelExt>::find(
-      doc! { "feed": &feed_id },
-      FindOptions::builder()
-        .sort(doc! { "_id": -1_i32 })
-        .limit(3)
-        .build(),
+      .filter_map(|url| normalize_url(url).ok())
+      .collect::<Vec<String>>();
+
+    Self::update_one(
+      doc! { "_id": id },
+      doc! { "$addToSet": { "aliases": { "$each": aliases } } },
+      None,
     )
     .await?;
 
-    if our_entries.is_empty() {
-      return Ok(false);
+    Ok(())
+  }
+
+  /// Update the feed URL after a permanent redirect, keeping the previous URL
+  /// as an alias. When another feed already uses the new URL, this feed is
+  /// merged into it and the other feed is returned.
+  async fn move_to(&self, url: String) -> Result<Option<Feed>, Error> {
+    let id = self.id.unwrap();
+    let existing = Self::find_by_url(&url)
+      .await?
+      .filter(|existing| existing.id != self.id);
+
+    if let Some(existing) = existing {
+      self.merge_into(&existing).await?;
+      return Ok(Some(existing));
+    }
+
+    debug!("Feed {} moved from {} to {}", &id, &self.url, &url);
+    Self::update_one(doc! { "_id": &id }, doc! { "$set": { "url": &url } }, None).await?;
+    Self::add_aliases(&id, &[self.url.clone(), url]).await?;
+
+    Ok(None)
+  }
+
+  /// Merge this feed into the given duplicate feed. Subscriptions are moved to
+  /// the other feed and start from its last entry, to avoid sending entries
+  /// the subscriptions already received. This feed is removed.
+  pub async fn merge_into(&self, target: &Feed) -> Result<(), Error> {
+    let id = self.id.unwrap();
+    let target_id = target.id.unwrap();
+
+    let last_entry = <Entry as ModelExt>::find_one(
+      doc! { "feed": &target_id },
+      Some(
+        FindOneOptions::builder()
+          .sort(doc! { "_id": -1_i32 })
+          .build(),
+      ),
+    )
+    .await?;
+
+    Subscription::update_many(
+      doc! { "feed": &id },
+      doc! {
+        "$set": {
+          "feed": &target_id,
+          "last_notified_entry": last_entry.and_then(|entry| entry.id),
+          "feed_status": bson::to_bson(&target.status)?,
+          "feed_error": target.last_error.clone(),
+        }
+      },
+      None,
+    )
+    .await?;
+
+    let mut aliases = self.aliases.clone();
+    aliases.push(self.url.clone());
+    Self::add_aliases(&target_id, &aliases).await?;
+
+    Self::remove(&id).await
+  }
+
+  /// Give a dead feed another chance, used when a new subscription is
+  /// created for it. The feed is synced as soon as possible and it needs to
+  /// fail again the configured amount of times to be considered dead.
+  pub async fn revive(&self) -> Result<(), Error> {
+    if self.status != FeedStatus::Dead {
+      return Ok(());
     }
 
-    let our_entries_ids = our_entries.into_iter().map(|entry| entry.public_id);
-    let is_synced = their_entries_ids
-      .zip(our_entries_ids)
-      .all(|(their_entry_id, our_entry_id)| their_entry_id == our_entry_id);
+    let id = self.id.unwrap();
+    Self::update_one(
+      doc! { "_id": &id },
+      doc! {
+        "$set": {
+          "status": bson::to_bson(&FeedStatus::Degraded)?,
+          "consecutive_failures": 0_i32,
+          "next_sync_at": null,
+        }
+      },
+      None,
+    )
+    .await?;
+
+    let error = self.last_error.clone();
+    Self::set_subscriptions_health(&id, &FeedStatus::Degraded, error).await
+  }
 
-    Ok(is_synced)
+  fn get_retention(&self) -> &Retention {
+    self
+      .retention
+      .as_ref()
+      .unwrap_or(&get_settings().feeds.retention)
+  }
+
+  fn validators(&self) -> Validators {
+    Validators {
+      etag: self.etag.clone(),
+      last_modified: self.last_modified.clone(),
+      hash: self.content_hash.clone(),
+    }
   }
 
   /// Remove this feed and all its entries from the database.
   pub async fn remove(id: &ObjectId) -> Result<(), Error> {
+    if let Some(feed) = Self::find_by_id(id).await? {
+      if let Err(err) = feed.request_websub(Mode::Unsubscribe).await {
+        error!(
+          "Failed to unsubscribe Feed {} from its hub. Error: {}",
+          id, err
+        );
+      }
+    }
+
     <Entry as ModelExt>::delete_many(doc! { "feed": id }).await?;
     Feed::delete_one(doc! { "_id": id }).await?;
     Ok(())
@@ -205,6 +980,11 @@ pub enum FeedType {
   RSS0,
   RSS1,
   RSS2,
+  // HTML page scraped with CSS selectors.
+  Html,
+  // JSON API mapped with JSON pointers.
+  #[serde(rename = "json_api")]
+  JsonApi,
 }
 
 impl From<feed_rs::model::FeedType> for FeedType {
@@ -219,6 +999,58 @@ impl From<feed_rs::model::FeedType> for FeedType {
   }
 }
 
+/// WebSub subscription to the hub advertised by a feed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSub {
+  pub hub: String,
+  pub topic: String,
+  // Secret shared with the hub to sign the content notifications.
+  pub secret: String,
+  // Last subscription request sent to the hub.
+  pub requested_at: Option<Date>,
+  // Set when the hub verifies the subscription, the subscription needs to be
+  // renewed before it expires.
+  pub subscribed_at: Option<Date>,
+  pub expires_at: Option<Date>,
+}
+
+impl WebSub {
+  fn new(hub: Hub) -> Self {
+    Self {
+      hub: hub.url,
+      topic: hub.topic,
+      secret: create_random_string(32),
+      requested_at: None,
+      subscribed_at: None,
+      expires_at: None,
+    }
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.expires_at.is_some_and(|expires_at| expires_at > now())
+  }
+
+  /// Whether a subscription request was sent to the hub and the hub can still
+  /// verify it, requests are sent again after this window.
+  pub fn is_requested(&self) -> bool {
+    let retry_at: Date = (Utc::now() - Duration::hours(WEBSUB_RETRY_HOURS)).into();
+    self
+      .requested_at
+      .is_some_and(|requested_at| requested_at > retry_at)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedStatus {
+  #[default]
+  Healthy,
+  // The last sync failed, the feed is synced again with a backoff.
+  Degraded,
+  // The feed failed too many consecutive times and is no longer synced.
+  Dead,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PublicFeed {
   #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
@@ -227,6 +1059,19 @@ pub struct PublicFeed {
   pub feed_type: FeedType,
   pub url: String,
   pub title: Option<String>,
+  pub selectors: Option<Selectors>,
+  pub mapping: Option<JsonMapping>,
+  pub podcast: Option<Podcast>,
+  pub retention: Option<Retention>,
+  #[serde(default)]
+  pub id_strategy: IdStrategy,
+  #[serde(default)]
+  pub status: FeedStatus,
+  #[serde(default)]
+  pub consecutive_failures: i32,
+  pub last_error: Option<String>,
+  #[serde(serialize_with = "bson_datetime_option_as_rfc3339_string")]
+  pub last_error_at: Option<Date>,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
   pub updated_at: Date,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
@@ -241,6 +1086,15 @@ impl From<Feed> for PublicFeed {
       feed_type: feed.feed_type,
       url: feed.url,
       title: feed.title,
+      selectors: feed.selectors,
+      mapping: feed.mapping,
+      podcast: feed.podcast,
+      retention: feed.retention,
+      id_strategy: feed.id_strategy,
+      status: feed.status,
+      consecutive_failures: feed.consecutive_failures,
+      last_error: feed.last_error,
+      last_error_at: feed.last_error_at,
       updated_at: feed.updated_at,
       created_at: feed.created_at,
     }
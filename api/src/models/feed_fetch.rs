@@ -0,0 +1,129 @@
+use bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use bson::serde_helpers::serialize_object_id_as_hex_string;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model as WitherModel;
+
+use crate::settings::get_settings;
+use crate::utils::database_model::ModelExt;
+use crate::utils::date::{now, Date};
+
+impl ModelExt for FeedFetch {
+  type T = FeedFetch;
+}
+
+// This model records every attempt to sync a feed, it is used to explain what
+// happened with a feed entries. Fetches are removed by MongoDB once they
+// expire.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(index(keys = r#"doc!{ "feed": 1, "created_at": -1 }"#))]
+#[model(index(
+  keys = r#"doc!{ "expires_at": 1 }"#,
+  options = r#"doc!{ "expireAfterSeconds": 0 }"#
+))]
+pub struct FeedFetch {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  pub feed: ObjectId,
+  pub url: String,
+  pub outcome: FetchOutcome,
+  // Response status code, latency in milliseconds and body size in bytes.
+  // Fetches failing before getting a response do not have them.
+  pub status: Option<i32>,
+  pub latency: Option<i64>,
+  pub bytes: Option<i64>,
+  pub error: Option<String>,
+  // Entries found in the feed and what the sync did with them. Only fetches
+  // with modified content have them.
+  pub entries: Option<i64>,
+  pub inserted: Option<i64>,
+  pub updated: Option<i64>,
+  pub removed: Option<i64>,
+  pub expires_at: Date,
+  pub created_at: Date,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchOutcome {
+  /// The feed changed and its entries were synced.
+  Modified,
+  /// The server responded with a 304 Not Modified status code.
+  NotModified,
+  /// The server responded with the same body as the previous fetch.
+  Unchanged,
+  /// The feed changed but its content could not be parsed.
+  Invalid,
+  /// The feed permanently moved to a feed we already have and was merged
+  /// into it.
+  Merged,
+  /// The feed host is rate limiting us, the feed was not requested.
+  RateLimited,
+  Failed,
+}
+
+impl FeedFetch {
+  pub fn new(feed: ObjectId, url: String) -> Self {
+    let now = now();
+    let days = get_settings().feeds.fetch_history_days;
+
+    Self {
+      id: None,
+      feed,
+      url,
+      outcome: FetchOutcome::Failed,
+      status: None,
+      latency: None,
+      bytes: None,
+      error: None,
+      entries: None,
+      inserted: None,
+      updated: None,
+      removed: None,
+      expires_at: (Utc::now() + Duration::days(days)).into(),
+      created_at: now,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicFeedFetch {
+  #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+  pub id: ObjectId,
+  #[serde(serialize_with = "serialize_object_id_as_hex_string")]
+  pub feed: ObjectId,
+  pub url: String,
+  pub outcome: FetchOutcome,
+  pub status: Option<i32>,
+  pub latency: Option<i64>,
+  pub bytes: Option<i64>,
+  pub error: Option<String>,
+  pub entries: Option<i64>,
+  pub inserted: Option<i64>,
+  pub updated: Option<i64>,
+  pub removed: Option<i64>,
+  #[serde(with = "bson_datetime_as_rfc3339_string")]
+  pub created_at: Date,
+}
+
+impl From<FeedFetch> for PublicFeedFetch {
+  fn from(fetch: FeedFetch) -> Self {
+    Self {
+      id: fetch.id.unwrap(),
+      feed: fetch.feed,
+      url: fetch.url,
+      outcome: fetch.outcome,
+      status: fetch.status,
+      latency: fetch.latency,
+      bytes: fetch.bytes,
+      error: fetch.error,
+      entries: fetch.entries,
+      inserted: fetch.inserted,
+      updated: fetch.updated,
+      removed: fetch.removed,
+      created_at: fetch.created_at,
+    }
+  }
+}
@@ -0,0 +1,248 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use validator::Validate;
+use wither::bson::{doc, oid::ObjectId, Document};
+use wither::mongodb::error::{Error as MongoError, ErrorKind, WriteFailure};
+use wither::mongodb::options::{FindOneAndUpdateOptions, ReturnDocument, UpdateOptions};
+use wither::Model as WitherModel;
+
+use crate::errors::Error;
+use crate::settings::get_settings;
+use crate::utils::database_model::ModelExt;
+use crate::utils::date::{now, Date};
+use crate::utils::lease;
+
+impl ModelExt for Job {
+  type T = Job;
+}
+
+// This model is a unit of background work. Jobs are queued until their run_at
+// date, then claimed by a worker, which holds them for the visibility timeout.
+// Jobs not completed in time, e.g. because the instance running them crashed,
+// are claimed again. Completed jobs are removed, jobs failing too many times
+// are kept as dead letters.
+#[derive(Debug, Clone, Serialize, Deserialize, WitherModel, Validate)]
+#[model(index(keys = r#"doc!{ "status": 1, "priority": -1, "run_at": 1 }"#))]
+#[model(index(keys = r#"doc!{ "status": 1, "locked_until": 1 }"#))]
+#[model(index(
+  keys = r#"doc!{ "key": 1 }"#,
+  options = r#"doc!{ "unique": true, "partialFilterExpression": { "status": "queued" } }"#
+))]
+pub struct Job {
+  #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+  pub id: Option<ObjectId>,
+  // Identifies the work done by the job, a job is queued once per key.
+  pub key: String,
+  pub kind: JobKind,
+  pub status: JobStatus,
+  // Jobs with a higher priority run first, jobs with the same priority run in
+  // run_at order.
+  pub priority: i32,
+  pub run_at: Date,
+  pub attempts: i32,
+  pub locked_until: Option<Date>,
+  pub locked_by: Option<String>,
+  pub last_error: Option<String>,
+  pub updated_at: Date,
+  pub created_at: Date,
+}
+
+/// Work done by a job and its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobKind {
+  SyncFeed {
+    feed: ObjectId,
+  },
+  NotifySubscription {
+    subscription: ObjectId,
+  },
+  /// Send again a webhook that could not be delivered, the payload is the
+  /// JSON body of the webhook.
+  RetryWebhook {
+    webhook: ObjectId,
+    payload: String,
+  },
+  /// Request or renew the WebSub subscription of the feed.
+  RenewWebSub {
+    feed: ObjectId,
+  },
+  /// Remove the feed if it has no subscriptions left.
+  CleanupFeed {
+    feed: ObjectId,
+  },
+}
+
+impl JobKind {
+  fn key(&self) -> String {
+    match self {
+      JobKind::SyncFeed { feed } => format!("sync_feed:{}", feed),
+      JobKind::NotifySubscription { subscription } => {
+        format!("notify_subscription:{}", subscription)
+      }
+      JobKind::RetryWebhook { webhook, .. } => format!("retry_webhook:{}", webhook),
+      JobKind::RenewWebSub { feed } => format!("renew_websub:{}", feed),
+      JobKind::CleanupFeed { feed } => format!("cleanup_feed:{}", feed),
+    }
+  }
+
+  /// Subscribers wait for notifications, these run before feed syncs, which
+  /// are scheduled ahead of time anyway.
+  fn priority(&self) -> i32 {
+    match self {
+      JobKind::NotifySubscription { .. } => 30,
+      JobKind::RetryWebhook { .. } => 20,
+      JobKind::SyncFeed { .. } | JobKind::RenewWebSub { .. } => 10,
+      JobKind::CleanupFeed { .. } => 0,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+  Queued,
+  Running,
+  Dead,
+}
+
+impl Job {
+  /// Queue a job to run at the given date. When the same job is already
+  /// queued it is not queued again, the queued job runs at the earliest of
+  /// both dates instead.
+  pub async fn enqueue(kind: JobKind, run_at: DateTime<Utc>) -> Result<(), Error> {
+    let now = now();
+    let run_at: Date = run_at.into();
+    let key = kind.key();
+    let priority = kind.priority();
+
+    let query = doc! { "key": &key, "status": "queued" };
+    let update = doc! {
+      "$setOnInsert": {
+        "key": &key,
+        "kind": bson::to_bson(&kind)?,
+        "status": "queued",
+        "attempts": 0_i32,
+        "created_at": now,
+      },
+      "$min": { "run_at": run_at },
+      "$max": { "priority": priority },
+      "$set": { "updated_at": now },
+    };
+    let options = UpdateOptions::builder().upsert(true).build();
+
+    match Self::update_one(query, update, Some(options)).await {
+      Ok(_) => Ok(()),
+      // Another instance queued the same job at the same time.
+      Err(Error::Mongo(err)) if is_duplicate_key(&err) => Ok(()),
+      Err(err) => Err(err),
+    }
+  }
+
+  /// Claim the next job ready to run. Running jobs past their visibility
+  /// timeout are claimed again.
+  pub async fn claim() -> Result<Option<Self>, Error> {
+    let settings = &get_settings().jobs;
+    let now = Utc::now();
+    let locked_until: Date = (now + Duration::seconds(settings.visibility_timeout)).into();
+    let now: Date = now.into();
+
+    let query = doc! {
+      "$or": [
+        { "status": "queued", "run_at": { "$lte": now } },
+        { "status": "running", "locked_until": { "$lte": now } }
+      ]
+    };
+    let update = doc! {
+      "$set": {
+        "status": "running",
+        "locked_until": locked_until,
+        "locked_by": lease::get_owner(),
+        "updated_at": now,
+      },
+      "$inc": { "attempts": 1_i32 }
+    };
+    let options = FindOneAndUpdateOptions::builder()
+      .sort(doc! { "priority": -1_i32, "run_at": 1_i32 })
+      .return_document(ReturnDocument::After)
+      .build();
+
+    <Self as ModelExt>::collection()
+      .find_one_and_update(query, update, options)
+      .await
+      .map_err(Error::Mongo)
+  }
+
+  /// Remove the completed job. Jobs claimed again by another worker after
+  /// their visibility timeout are left to that worker.
+  pub async fn complete(&self) -> Result<(), Error> {
+    let result = Self::delete_one(self.get_claim_query()).await?;
+    if result.deleted_count == 0 {
+      warn!("Job {} lost its lease, not removing it", self.id.unwrap());
+    }
+    Ok(())
+  }
+
+  /// Record the failed attempt and queue the job again with an exponential
+  /// backoff. Jobs failing too many times are dead and no longer run.
+  pub async fn fail(&self, error: &str) -> Result<(), Error> {
+    let settings = &get_settings().jobs;
+    let now = Utc::now();
+
+    let mut set = doc! {
+      "last_error": error,
+      "updated_at": Date::from(now),
+    };
+    if self.attempts >= settings.max_attempts {
+      set.insert("status", "dead");
+    } else {
+      let exponent = (self.attempts - 1).clamp(0, 32) as u32;
+      let delay = settings
+        .retry_delay
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(settings.max_retry_delay);
+      let run_at: Date = (now + Duration::seconds(delay)).into();
+      set.insert("status", "queued");
+      set.insert("run_at", run_at);
+    }
+
+    let update = doc! {
+      "$set": set,
+      "$unset": { "locked_until": 1_i32, "locked_by": 1_i32 }
+    };
+    match Self::update_one(self.get_claim_query(), update, None).await {
+      Ok(result) if result.matched_count == 0 => {
+        warn!(
+          "Job {} lost its lease, not recording the failure",
+          self.id.unwrap()
+        );
+        Ok(())
+      }
+      Ok(_) => Ok(()),
+      // The same job was queued again while this one was running, the queued
+      // job retries it.
+      Err(Error::Mongo(err)) if is_duplicate_key(&err) => self.complete().await,
+      Err(err) => Err(err),
+    }
+  }
+
+  /// Query matching the job while it is held by this claim. Every claim
+  /// increases the attempts, a job claimed again after its visibility timeout,
+  /// even by the same instance, no longer matches.
+  fn get_claim_query(&self) -> Document {
+    doc! {
+      "_id": self.id.unwrap(),
+      "status": "running",
+      "locked_by": &self.locked_by,
+      "attempts": self.attempts,
+    }
+  }
+}
+
+fn is_duplicate_key(err: &MongoError) -> bool {
+  matches!(
+    err.kind.as_ref(),
+    ErrorKind::Write(WriteFailure::WriteError(error)) if error.code == 11000
+  )
+}
@@ -2,6 +2,8 @@ pub mod application;
 pub mod endpoint;
 pub mod entry;
 pub mod feed;
+pub mod feed_fetch;
+pub mod job;
 pub mod key;
 pub mod subscription;
 pub mod user;
@@ -15,6 +17,8 @@ pub async fn sync_indexes() -> Result<(), Error> {
   endpoint::Endpoint::sync_indexes().await?;
   entry::Entry::sync_indexes().await?;
   feed::Feed::sync_indexes().await?;
+  feed_fetch::FeedFetch::sync_indexes().await?;
+  job::Job::sync_indexes().await?;
   key::Key::sync_indexes().await?;
   subscription::Subscription::sync_indexes().await?;
   user::User::sync_indexes().await?;
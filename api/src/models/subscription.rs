@@ -1,8 +1,9 @@
 use bson::serde_helpers::bson_datetime_as_rfc3339_string;
 use bson::serde_helpers::serialize_object_id_as_hex_string;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as Json;
-use tracing::debug;
+use tracing::{debug, error};
 use validator::Validate;
 use wither::bson::{doc, oid::ObjectId};
 use wither::mongodb::options::FindOptions;
@@ -10,10 +11,12 @@ use wither::Model as WitherModel;
 
 use crate::errors::Error;
 use crate::models::endpoint::Endpoint;
-use crate::models::entry::Entry;
-use crate::models::feed::Feed;
+use crate::models::entry::{Entry, EntryFormat, PublicEntry};
+use crate::models::feed::{Feed, FeedStatus};
+use crate::models::job::{Job, JobKind};
 use crate::utils::database_model::ModelExt;
 use crate::utils::date::{now, Date};
+use crate::utils::get_feed::Error as GetFeedError;
 
 impl ModelExt for Subscription {
   type T = Subscription;
@@ -34,12 +37,31 @@ pub struct Subscription {
   pub feed: ObjectId,
   pub endpoint: ObjectId,
   pub metadata: Option<Json>,
+  // Tags given by the application to organize its subscriptions, OPML
+  // imports use the outline folders and categories.
+  #[serde(default)]
+  pub tags: Vec<String>,
 
   // Last time the subscription was notified and the last feed entry sent. The
   // last entry is required to calculate what entries needs to be sent next.
   pub last_notified_entry: Option<ObjectId>,
   pub notified_at: Option<Date>,
 
+  // Opt-in to receive entries that changed after being sent. The update date
+  // and ID of the last updated entry sent are used to calculate what updates
+  // needs to be sent next, many entries can share the same update date.
+  #[serde(default)]
+  pub notify_updates: bool,
+  pub last_notified_update: Option<Date>,
+  pub last_notified_update_entry: Option<ObjectId>,
+
+  // Representation of the entries description and content sent in webhooks.
+  #[serde(default)]
+  pub format: EntryFormat,
+  // Opt-in to receive the main article of the entries page as their content.
+  #[serde(default)]
+  pub full_content: bool,
+
   // This attribute is used by the subscription scheduler to determine if the
   // subscription needs to be notified. When subscription is notified, this
   // attribute is set to None.
@@ -49,6 +71,18 @@ pub struct Subscription {
   pub scheduled_at: Option<Date>,
 
   pub synced_at: Option<Date>,
+
+  // Health of the subscription feed, copied from the feed on every change so
+  // it can be included in the subscription responses.
+  #[serde(default)]
+  pub feed_status: FeedStatus,
+  pub feed_error: Option<String>,
+
+  // Lease of the instance notifying the subscription, notify must not run
+  // more than once at the same time per subscription.
+  pub locked_until: Option<Date>,
+  pub locked_by: Option<String>,
+
   pub created_at: Date,
 }
 
@@ -68,10 +102,20 @@ impl Subscription {
       feed,
       endpoint,
       metadata,
+      tags: vec![],
       last_notified_entry: None,
       notified_at: None,
+      notify_updates: false,
+      last_notified_update: None,
+      last_notified_update_entry: None,
+      format: EntryFormat::Raw,
+      full_content: false,
       synced_at: None,
       scheduled_at: None,
+      feed_status: FeedStatus::Healthy,
+      feed_error: None,
+      locked_until: None,
+      locked_by: None,
       created_at: now,
     }
   }
@@ -84,33 +128,71 @@ impl Subscription {
 
     debug!("Notifying subscription {} !", &id);
 
-    let (entries, has_more_entries) = find_entries(self).await?;
-    if entries.is_empty() {
+    let (mut entries, has_more_entries) = find_entries(self).await?;
+    if self.full_content {
+      for entry in entries.iter_mut() {
+        match entry.fetch_full_content().await {
+          Ok(_) => {}
+          // The subscription stays scheduled and is notified once the host
+          // allows us to fetch the remaining pages.
+          Err(Error::GetFeed(GetFeedError::RateLimited(retry_at))) => {
+            debug!(
+              "Postponing subscription {} until {}, its entries host is rate limiting us",
+              &id, retry_at
+            );
+            return Ok(());
+          }
+          Err(err) => return Err(err),
+        }
+      }
+    }
+
+    let (updated, has_more_updates) = match self.notify_updates {
+      true => find_updated_entries(self).await?,
+      false => (vec![], false),
+    };
+
+    if entries.is_empty() && updated.is_empty() {
       debug!("No new entries found for subscription {}", &id);
+      Self::update_one(
+        doc! { "_id": &id },
+        doc! { "$unset": { "scheduled_at": 1_i32 } },
+        None,
+      )
+      .await?;
       return Ok(());
     }
 
+    let to_public = |entries: &[Entry]| {
+      entries
+        .iter()
+        .cloned()
+        .map(|entry| PublicEntry::from_entry(entry, self.format, self.full_content))
+        .collect::<Vec<PublicEntry>>()
+    };
+
     let webhook = Endpoint::send_webhook(
       self.endpoint,
       self.application,
       id,
       self.feed,
-      entries.clone(),
+      to_public(&entries),
+      to_public(&updated),
       self.metadata.clone(),
     )
     .await?;
 
-    let last_entry = entries.last().unwrap();
-    let last_entry_id = last_entry.id.unwrap();
-
-    let mut update = doc! {
-      "$set": {
-        "last_notified_entry": last_entry_id,
-        "notified_at": webhook.created_at,
-      },
-    };
+    let mut set = doc! { "notified_at": webhook.created_at };
+    if let Some(last_entry) = entries.last() {
+      set.insert("last_notified_entry", last_entry.id.unwrap());
+    }
+    if let Some(last_update) = updated.last() {
+      set.insert("last_notified_update", last_update.updated_at);
+      set.insert("last_notified_update_entry", last_update.id.unwrap());
+    }
 
-    if !has_more_entries {
+    let mut update = doc! { "$set": set };
+    if !has_more_entries && !has_more_updates {
       update.insert("$unset", doc! { "scheduled_at": 1_i32 });
     }
 
@@ -124,7 +206,13 @@ impl Subscription {
     let feed_id = self.feed;
 
     Subscription::delete_one(doc! { "_id": subscription_id }).await?;
-    Feed::cleanup(&feed_id).await?;
+
+    // The subscription is already removed, the feed cleanup is retried later
+    // when it fails.
+    if let Err(err) = Feed::cleanup(&feed_id).await {
+      error!("Failed to cleanup Feed {}. Error: {}", &feed_id, err);
+      Job::enqueue(JobKind::CleanupFeed { feed: feed_id }, Utc::now()).await?;
+    }
 
     Ok(())
   }
@@ -140,6 +228,17 @@ pub struct PublicSubscription {
   #[serde(serialize_with = "serialize_object_id_as_hex_string")]
   pub endpoint: ObjectId,
   pub metadata: Option<Json>,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub notify_updates: bool,
+  #[serde(default)]
+  pub format: EntryFormat,
+  #[serde(default)]
+  pub full_content: bool,
+  #[serde(default)]
+  pub feed_status: FeedStatus,
+  pub feed_error: Option<String>,
   #[serde(with = "bson_datetime_as_rfc3339_string")]
   pub created_at: Date,
 }
@@ -152,6 +251,12 @@ impl From<Subscription> for PublicSubscription {
       url: subscription.url.clone(),
       endpoint: subscription.endpoint,
       metadata: subscription.metadata,
+      tags: subscription.tags,
+      notify_updates: subscription.notify_updates,
+      format: subscription.format,
+      full_content: subscription.full_content,
+      feed_status: subscription.feed_status,
+      feed_error: subscription.feed_error,
       created_at: subscription.created_at,
     }
   }
@@ -185,3 +290,52 @@ async fn find_entries(subscription: &Subscription) -> Result<(Vec<Entry>, bool),
 
   Ok((entries, has_more))
 }
+
+/// Find entries already sent to the subscription that changed after the last
+/// notified update. Only updates after the subscription was created are sent.
+async fn find_updated_entries(subscription: &Subscription) -> Result<(Vec<Entry>, bool), Error> {
+  let limit = 30;
+
+  let last_notified_entry = match subscription.last_notified_entry {
+    Some(last_notified_entry) => last_notified_entry,
+    None => return Ok((vec![], false)),
+  };
+
+  let options = FindOptions::builder()
+    .sort(doc! { "updated_at": 1_i32, "_id": 1_i32 })
+    .limit(limit + 1)
+    .build();
+
+  let last_notified_update = subscription
+    .last_notified_update
+    .unwrap_or(subscription.created_at);
+  let mut query = doc! {
+    "feed": subscription.feed,
+    "_id": { "$lte": last_notified_entry },
+  };
+  // Entries are paged in (updated_at, _id) order. Many entries can be updated
+  // at the same date, the ones updated at the date of the last update sent
+  // are sent when their ID is greater.
+  match subscription.last_notified_update_entry {
+    Some(last_notified_update_entry) => query.insert(
+      "$or",
+      vec![
+        doc! { "updated_at": { "$gt": last_notified_update } },
+        doc! {
+          "updated_at": last_notified_update,
+          "_id": { "$gt": last_notified_update_entry }
+        },
+      ],
+    ),
+    None => query.insert("updated_at", doc! { "$gt": last_notified_update }),
+  };
+
+  let mut entries = <Entry as ModelExt>::find(query, options).await?;
+  let has_more = entries.len() as i64 > limit;
+
+  if has_more {
+    entries.pop();
+  }
+
+  Ok((entries, has_more))
+}
@@ -95,5 +95,9 @@ pub struct WebhookSendPayload {
   #[serde(alias = "_id", serialize_with = "serialize_object_id_as_hex_string")]
   pub endpoint: ObjectId,
   pub entries: Vec<PublicEntry>,
+  // Entries already sent that changed since, only sent to subscriptions that
+  // opted in to receive updates.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub updated: Vec<PublicEntry>,
   pub metadata: Option<Json>,
 }
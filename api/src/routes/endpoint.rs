@@ -18,6 +18,7 @@ use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder};
 use crate::utils::database_model::ModelExt;
 use crate::utils::date::from_iso;
 use crate::utils::date::{now, Date};
+use crate::utils::outbound::check_url;
 use crate::utils::pagination::PaginationBuilder;
 use crate::utils::request_query::RequestQuery;
 use crate::utils::to_object_id::to_object_id;
@@ -36,6 +37,7 @@ async fn create_endpoint(
   Extension(application): Extension<Application>,
 ) -> Result<CustomResponse<PublicEndpoint>, Error> {
   let application_id = application.id.unwrap();
+  check_endpoint_url(&payload.url).await?;
 
   let endpoint = Endpoint::new(application_id, payload.url, payload.title);
   let endpoint = Endpoint::create(endpoint).await?;
@@ -119,6 +121,7 @@ async fn update_endpoint_by_id(
 
   let endpoint_id = params.get("id").unwrap().to_owned();
   let endpoint_id = to_object_id(endpoint_id)?;
+  check_endpoint_url(&payload.url).await?;
 
   let update = UpdateEndpoint::new(payload.title, payload.url);
   let update = bson::to_document(&update).unwrap();
@@ -167,6 +170,15 @@ async fn remove_endpoint_by_id(
   Ok(res)
 }
 
+/// Check the endpoint URL is allowed by the outbound policy. The URL is
+/// checked again before sending each webhook, the host could resolve to a
+/// different address.
+async fn check_endpoint_url(url: &str) -> Result<(), Error> {
+  check_url(url)
+    .await
+    .map_err(|err| Error::BadRequest(BadRequest::new("url", err.to_string())))
+}
+
 #[derive(Deserialize)]
 struct CreateEndpoint {
   url: String,
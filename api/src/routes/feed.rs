@@ -1,46 +1,135 @@
-use axum::{extract::Query, routing::get, Json, Router};
+use axum::{
+  extract::Query,
+  routing::{get, post},
+  Json, Router,
+};
 use bson::doc;
+use bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 use tracing::debug;
 
-use crate::errors::Error;
-use crate::models::entry::PublicEntry;
+use crate::errors::{BadRequest, Error};
+use crate::models::entry::{Entry, IdStrategy, PublicEntry};
 use crate::models::feed::FeedType;
-use crate::utils::get_feed::get_feed;
+use crate::utils::discover::DiscoveredFeed;
+use crate::utils::get_feed::{fetch_items, resolve_feed, Resolved, Source};
+use crate::utils::map_json::JsonMapping;
+use crate::utils::podcast::Podcast;
+use crate::utils::scrape::{ScrapedPage, Selectors};
 use crate::utils::to_url::to_url;
 
 pub fn create_router() -> Router {
-  Router::new().route("/feeds", get(get_feed_by_url))
+  Router::new()
+    .route("/feeds", get(get_feed_by_url))
+    .route("/feeds/preview", post(preview_source_feed))
 }
 
 async fn get_feed_by_url(query: Query<GetFeedQuery>) -> Result<Json<FeedResponse>, Error> {
   let url = query.url.clone();
   let url = to_url(url)?;
-  let raw_feed = get_feed(url.to_string()).await?;
-  let feed = FeedResponse::from_raw_feed(raw_feed);
+  let resolved = resolve_feed(url.to_string()).await?;
+  let feed = FeedResponse::from_resolved(resolved);
 
   debug!("Returning feed");
   Ok(Json(feed))
 }
 
+/// Show the entries the selectors or JSON mapping find in the HTML page or
+/// JSON API, before subscribing to it.
+async fn preview_source_feed(
+  Json(payload): Json<PreviewSourceFeed>,
+) -> Result<Json<FeedResponse>, Error> {
+  let url = to_url(payload.url)?.to_string();
+  let source = Source::from_options(payload.selectors.as_ref(), payload.mapping.as_ref())?;
+  let feed_type = match source {
+    Source::Feed => {
+      return Err(Error::BadRequest(BadRequest::new(
+        "selectors",
+        "Selectors or a JSON mapping are required",
+      )))
+    }
+    Source::Html(_) => FeedType::Html,
+    Source::Json(_) => FeedType::JsonApi,
+  };
+
+  let (scraped, url) = fetch_items(url, source).await?;
+  let feed = FeedResponse::from_scraped(url, feed_type, scraped);
+
+  debug!("Returning source feed preview");
+  Ok(Json(feed))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct FeedResponse {
+  // URL of the feed, which is different from the requested URL when it
+  // points to a website.
+  pub url: String,
   pub feed_type: FeedType,
   pub title: Option<String>,
   pub description: Option<String>,
+  // iTunes and Podcasting 2.0 metadata of podcast feeds, the episodes
+  // metadata is in the entries.
+  pub podcast: Option<Podcast>,
   pub entries: Vec<PublicEntry>,
+  // Feeds advertised by the website when the requested URL points to one.
+  #[serde(default)]
+  pub discovered: Vec<DiscoveredFeed>,
 }
 
 impl FeedResponse {
-  pub fn from_raw_feed(feed: feed_rs::model::Feed) -> Self {
+  pub fn from_resolved(resolved: Resolved) -> Self {
+    let Resolved {
+      url,
+      feed,
+      podcast,
+      discovered,
+      ..
+    } = resolved;
+
+    let entries = feed
+      .entries
+      .into_iter()
+      .map(|entry| {
+        let episode = podcast
+          .as_ref()
+          .and_then(|podcast| podcast.get_episode(&entry));
+        let mut entry = PublicEntry::from(entry);
+        entry.podcast = episode;
+        entry
+      })
+      .collect();
+    let podcast = podcast.map(|podcast| podcast.podcast);
+
     FeedResponse {
+      url,
       feed_type: feed.feed_type.into(),
       title: feed.title.clone().map(|title| title.content),
       description: feed
         .description
         .clone()
         .map(|description| description.content),
-      entries: feed.entries.into_iter().map(Into::into).collect(),
+      podcast,
+      entries,
+      discovered,
+    }
+  }
+
+  pub fn from_scraped(url: String, feed_type: FeedType, scraped: ScrapedPage) -> Self {
+    // The entries are not stored, the feed ID is not used.
+    let feed_id = ObjectId::new();
+
+    FeedResponse {
+      url,
+      feed_type,
+      title: scraped.title,
+      description: None,
+      podcast: None,
+      entries: scraped
+        .items
+        .into_iter()
+        .map(|item| Entry::from_scraped_item(feed_id, item, IdStrategy::default()).into())
+        .collect(),
+      discovered: vec![],
     }
   }
 }
@@ -49,3 +138,10 @@ impl FeedResponse {
 struct GetFeedQuery {
   url: String,
 }
+
+#[derive(Deserialize)]
+struct PreviewSourceFeed {
+  url: String,
+  selectors: Option<Selectors>,
+  mapping: Option<JsonMapping>,
+}
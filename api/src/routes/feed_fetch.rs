@@ -0,0 +1,79 @@
+use axum::{
+  extract::{Extension, Path, Query},
+  routing::get,
+  Router,
+};
+use bson::doc;
+use std::collections::HashMap;
+use tracing::debug;
+use wither::mongodb::options::FindOptions;
+
+use crate::errors::Error;
+use crate::errors::NotFound;
+use crate::models::application::Application;
+use crate::models::feed_fetch::{FeedFetch, PublicFeedFetch};
+use crate::models::subscription::Subscription;
+use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder};
+use crate::utils::database_model::ModelExt;
+use crate::utils::date::to_date;
+use crate::utils::pagination::PaginationBuilder;
+use crate::utils::request_query::RequestQuery;
+use crate::utils::to_object_id::to_object_id;
+
+pub fn create_router() -> Router {
+  Router::new().route("/subscriptions/:id/fetches", get(query_feed_fetches))
+}
+
+/// Fetch history of the feed the subscription points at, newest first.
+async fn query_feed_fetches(
+  Extension(application): Extension<Application>,
+  Path(params): Path<HashMap<String, String>>,
+  Query(query): Query<RequestQuery>,
+) -> Result<CustomResponse<Vec<PublicFeedFetch>>, Error> {
+  let application_id = application.id.unwrap();
+  let subscription_id = params.get("id").unwrap().to_owned();
+  let subscription_id = to_object_id(subscription_id)?;
+
+  let subscription = Subscription::find_one(
+    doc! { "_id": subscription_id, "application": &application_id },
+    None,
+  )
+  .await?;
+
+  let subscription = match subscription {
+    Some(subscription) => subscription,
+    None => {
+      debug!("subscription not found, returning 404 status code");
+      return Err(Error::NotFound(NotFound::new("subscription")));
+    }
+  };
+
+  let from = query.from.clone();
+  let pagination = PaginationBuilder::from_request_query(query);
+
+  let options = FindOptions::builder()
+    .sort(doc! { "created_at": -1_i32 })
+    .skip(pagination.offset)
+    .limit(pagination.limit as i64)
+    .build();
+
+  let mut query = doc! { "feed": subscription.feed };
+  if let Some(from) = from {
+    query.insert("created_at", doc! { "$gte": to_date(from)? });
+  }
+
+  let (fetches, count) = FeedFetch::find_and_count(query, Some(options)).await?;
+
+  let fetches = fetches
+    .into_iter()
+    .map(Into::into)
+    .collect::<Vec<PublicFeedFetch>>();
+
+  let res = CustomResponseBuilder::new()
+    .body(fetches)
+    .pagination(pagination.count(count).build())
+    .build();
+
+  debug!("Returning feed fetches");
+  Ok(res)
+}
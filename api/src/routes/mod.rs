@@ -1,10 +1,12 @@
 pub mod application;
 pub mod endpoint;
 pub mod feed;
+pub mod feed_fetch;
 pub mod key;
 pub mod subscription;
 pub mod user;
 pub mod webhook;
+pub mod websub;
 
 use axum::middleware::from_extractor;
 use axum::Router;
@@ -17,6 +19,9 @@ pub fn create_router() -> Router {
   Router::new()
     // User routes, no authentication required.
     .merge(user::create_router())
+    // WebSub callback routes, called by the feed hubs. Notifications are
+    // authenticated with the subscription secret.
+    .merge(websub::create_router())
     // Public API routes using API Keys to authenticate the user and
     // application.
     .merge(
@@ -42,6 +47,7 @@ pub fn create_router() -> Router {
                 "/:application_id",
                 Router::new()
                   .merge(endpoint::create_router())
+                  .merge(feed_fetch::create_router())
                   .merge(key::create_router())
                   .merge(subscription::create_router())
                   .merge(webhook::create_router()),
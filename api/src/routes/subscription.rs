@@ -1,14 +1,19 @@
+use axum::http::header::CONTENT_TYPE;
 use axum::http::StatusCode;
 use axum::{
   extract::{Extension, Path, Query},
+  response::IntoResponse,
   routing::{delete, get, post},
   Json, Router,
 };
 use bson::doc;
-use serde::Deserialize;
+use bson::oid::ObjectId;
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
-use tracing::debug;
+use tracing::{debug, error};
 use wither::mongodb::options::FindOptions;
 
 use crate::errors::BadRequest;
@@ -16,19 +21,32 @@ use crate::errors::Error;
 use crate::errors::NotFound;
 use crate::models::application::Application;
 use crate::models::endpoint::Endpoint;
-use crate::models::feed::Feed;
+use crate::models::entry::EntryFormat;
+use crate::models::feed::{Feed, FeedStatus};
 use crate::models::subscription::{PublicSubscription, Subscription};
 use crate::utils::custom_response::{CustomResponse, CustomResponseBuilder};
 use crate::utils::database_model::ModelExt;
-use crate::utils::date::from_iso;
+use crate::utils::date::to_date;
+use crate::utils::get_feed::Source;
+use crate::utils::map_json::JsonMapping;
+use crate::utils::normalize_url::normalize_url;
+use crate::utils::opml::{self, Outline};
 use crate::utils::pagination::PaginationBuilder;
 use crate::utils::request_query::RequestQuery;
+use crate::utils::scrape::Selectors;
 use crate::utils::to_object_id::to_object_id;
+use crate::utils::websub::Mode;
+
+// Feeds subscribed at the same time when importing an OPML document.
+const IMPORT_CONCURRENCY: usize = 5;
+const MAX_IMPORTED_FEEDS: usize = 1_000;
 
 pub fn create_router() -> Router {
   Router::new()
     .route("/subscriptions", post(create_subscription))
     .route("/subscriptions", get(query_subscriptions))
+    .route("/subscriptions/import", post(import_subscriptions))
+    .route("/subscriptions/export", get(export_subscriptions))
     .route("/subscriptions/:id", get(get_subscription_by_id))
     .route("/subscriptions/:id", delete(remove_subscription_by_id))
 }
@@ -37,42 +55,126 @@ async fn create_subscription(
   Json(payload): Json<CreateSubscription>,
   Extension(application): Extension<Application>,
 ) -> Result<CustomResponse<PublicSubscription>, Error> {
-  let endpoint_id = to_object_id(payload.endpoint)?;
   let application_id = application.id.unwrap();
+  let endpoint_id = find_endpoint(&application_id, payload.endpoint).await?;
 
-  let endpoint = Endpoint::find_one(
-    doc! { "application": &application_id, "_id": endpoint_id },
-    None,
-  )
-  .await?;
+  let subscription = subscribe(application_id, endpoint_id, payload.url, payload.options).await?;
+  let res = PublicSubscription::from(subscription);
 
-  if endpoint.is_none() {
-    return Err(Error::NotFound(NotFound::new("endpoint")));
+  let res = CustomResponseBuilder::new()
+    .body(res)
+    .status_code(StatusCode::CREATED)
+    .build();
+
+  Ok(res)
+}
+
+/// Create subscriptions for the feeds of the OPML document sent as the request
+/// body. Outline folders and categories become subscription tags. Feeds are
+/// subscribed independently, the response reports the result of each one.
+async fn import_subscriptions(
+  Extension(application): Extension<Application>,
+  Query(query): Query<ImportSubscriptions>,
+  body: Bytes,
+) -> Result<Json<ImportResponse>, Error> {
+  let application_id = application.id.unwrap();
+  let endpoint_id = find_endpoint(&application_id, query.endpoint).await?;
+
+  let outlines = opml::parse(&body).map_err(|err| BadRequest::new("opml", err.to_string()))?;
+  if outlines.is_empty() {
+    return Err(Error::BadRequest(BadRequest::new(
+      "opml",
+      "The OPML document does not have any feed",
+    )));
+  }
+  if outlines.len() > MAX_IMPORTED_FEEDS {
+    return Err(Error::BadRequest(BadRequest::new(
+      "opml",
+      format!("OPML documents can have up to {} feeds", MAX_IMPORTED_FEEDS),
+    )));
   }
 
-  // Feeds are global, not attached to any user
-  let feed = Feed::find_one(doc! { "url": &payload.url }, None).await?;
+  let feeds = stream::iter(outlines)
+    .map(|outline| {
+      let options = SubscriptionOptions {
+        metadata: None,
+        tags: Some(outline.tags),
+        notify_updates: query.notify_updates,
+        format: query.format,
+        full_content: query.full_content,
+        selectors: None,
+        mapping: None,
+      };
 
-  let feed = match feed {
-    Some(feed) => feed,
-    None => {
-      let feed = Feed::from_url(payload.url.clone()).await;
-      Feed::create(feed).await?
-    }
+      async move {
+        let url = outline.url;
+        match subscribe(application_id, endpoint_id, url.clone(), options).await {
+          Ok(subscription) => ImportedFeed {
+            url,
+            subscription: Some(subscription.into()),
+            error: None,
+          },
+          Err(err) => {
+            debug!("Failed to import feed {}. Error: {}", &url, err);
+            ImportedFeed {
+              url,
+              subscription: None,
+              error: Some(ImportError {
+                code: err.get_codes().1,
+                message: err.to_string(),
+              }),
+            }
+          }
+        }
+      }
+    })
+    .buffered(IMPORT_CONCURRENCY)
+    .collect::<Vec<ImportedFeed>>()
+    .await;
+
+  let failed = feeds.iter().filter(|feed| feed.error.is_some()).count();
+  let res = ImportResponse {
+    created: feeds.len() - failed,
+    failed,
+    feeds,
   };
 
-  let feed_id = feed.id.unwrap();
-  let metadata = payload.metadata;
-  let subscription = Subscription::new(application_id, feed_id, endpoint_id, payload.url, metadata);
-  let subscription = Subscription::create(subscription).await?;
-  let res = PublicSubscription::from(subscription);
+  debug!("Returning imported subscriptions");
+  Ok(Json(res))
+}
 
-  let res = CustomResponseBuilder::new()
-    .body(res)
-    .status_code(StatusCode::CREATED)
+/// Export every subscription of the application as an OPML document.
+async fn export_subscriptions(
+  Extension(application): Extension<Application>,
+) -> Result<impl IntoResponse, Error> {
+  let application_id = application.id.unwrap();
+  let options = FindOptions::builder()
+    .sort(doc! { "created_at": 1_i32 })
     .build();
+  let subscriptions = Subscription::find(doc! { "application": &application_id }, options).await?;
 
-  Ok(res)
+  let feed_ids = subscriptions
+    .iter()
+    .map(|subscription| subscription.feed)
+    .collect::<Vec<ObjectId>>();
+  let titles = Feed::find(doc! { "_id": { "$in": feed_ids } }, None)
+    .await?
+    .into_iter()
+    .filter_map(|feed| Some((feed.id?, feed.title?)))
+    .collect::<HashMap<ObjectId, String>>();
+
+  let outlines = subscriptions
+    .into_iter()
+    .map(|subscription| Outline {
+      title: titles.get(&subscription.feed).cloned(),
+      url: subscription.url,
+      tags: subscription.tags,
+    })
+    .collect::<Vec<Outline>>();
+  let document = opml::render(&application.name, &outlines);
+
+  debug!("Returning exported subscriptions");
+  Ok(([(CONTENT_TYPE, "text/x-opml; charset=utf-8")], document))
 }
 
 async fn query_subscriptions(
@@ -172,16 +274,137 @@ async fn remove_subscription_by_id(
   Ok(res)
 }
 
+/// Subscribe the endpoint to the feed of the given URL, creating the feed when
+/// we do not have it yet. The endpoint must belong to the application.
+async fn subscribe(
+  application_id: ObjectId,
+  endpoint_id: ObjectId,
+  url: String,
+  options: SubscriptionOptions,
+) -> Result<Subscription, Error> {
+  // Feeds are global, not attached to any user
+  let url = normalize_url(&url)?;
+  let source = Source::from_options(options.selectors.as_ref(), options.mapping.as_ref())?;
+  let feed = match source {
+    Source::Feed => match Feed::find_by_url(&url).await? {
+      Some(feed) => feed,
+      None => create_feed(url.clone()).await?,
+    },
+    _ => {
+      let (selectors, mapping) = (options.selectors.as_ref(), options.mapping.as_ref());
+      match Feed::find_by_source(&url, selectors, mapping).await? {
+        Some(feed) => feed,
+        None => {
+          let feed = Feed::from_source(url.clone(), options.selectors, options.mapping).await?;
+          Feed::create(feed).await?
+        }
+      }
+    }
+  };
+  feed.revive().await?;
+
+  let feed_id = feed.id.unwrap();
+  Feed::schedule_sync(&feed_id).await?;
+  let metadata = options.metadata;
+  let mut subscription = Subscription::new(application_id, feed_id, endpoint_id, url, metadata);
+  subscription.tags = options.tags.unwrap_or_default();
+  subscription.notify_updates = options.notify_updates.unwrap_or(false);
+  subscription.format = options.format.unwrap_or_default();
+  subscription.full_content = options.full_content.unwrap_or(false);
+  if feed.status != FeedStatus::Healthy {
+    subscription.feed_status = FeedStatus::Degraded;
+    subscription.feed_error = feed.last_error;
+  }
+
+  Subscription::create(subscription).await
+}
+
+async fn find_endpoint(application_id: &ObjectId, endpoint: String) -> Result<ObjectId, Error> {
+  let endpoint_id = to_object_id(endpoint)?;
+  let endpoint = Endpoint::find_one(
+    doc! { "application": application_id, "_id": endpoint_id },
+    None,
+  )
+  .await?;
+
+  match endpoint {
+    Some(_) => Ok(endpoint_id),
+    None => Err(Error::NotFound(NotFound::new("endpoint"))),
+  }
+}
+
+/// Create the feed for the given URL. The URL can point to a website or
+/// redirect to another URL, in which case the resolved feed could already
+/// exist and it is now also known by the given URL.
+async fn create_feed(url: String) -> Result<Feed, Error> {
+  let feed = Feed::from_url(url).await?;
+  if let Some(existing) = Feed::find_by_url(&feed.url).await? {
+    Feed::add_aliases(existing.id.as_ref().unwrap(), &feed.aliases).await?;
+    existing.revive().await?;
+    return Ok(existing);
+  }
+
+  let feed = Feed::create(feed).await?;
+
+  // Failing to subscribe to the feed hub is not critical, the feed is still
+  // polled and the WebSub scheduler retries the subscription.
+  if let Err(err) = feed.request_websub(Mode::Subscribe).await {
+    error!(
+      "Failed to subscribe Feed {:?} to its hub. Error: {}",
+      feed.id, err
+    );
+  }
+
+  Ok(feed)
+}
+
 #[derive(Deserialize)]
 struct CreateSubscription {
   url: String,
   endpoint: String,
+  #[serde(flatten)]
+  options: SubscriptionOptions,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionOptions {
   metadata: Option<JsonValue>,
+  tags: Option<Vec<String>>,
+  notify_updates: Option<bool>,
+  format: Option<EntryFormat>,
+  full_content: Option<bool>,
+  // Selectors or JSON mapping used to create a feed from an HTML page or a
+  // JSON API, the URL points to the page or API instead of a feed.
+  selectors: Option<Selectors>,
+  mapping: Option<JsonMapping>,
+}
+
+#[derive(Deserialize)]
+struct ImportSubscriptions {
+  endpoint: String,
+  notify_updates: Option<bool>,
+  format: Option<EntryFormat>,
+  full_content: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportResponse {
+  pub created: usize,
+  pub failed: usize,
+  pub feeds: Vec<ImportedFeed>,
+}
+
+/// Result of subscribing to one of the imported feeds, the error has the same
+/// shape as the API error responses.
+#[derive(Serialize, Deserialize)]
+pub struct ImportedFeed {
+  pub url: String,
+  pub subscription: Option<PublicSubscription>,
+  pub error: Option<ImportError>,
 }
 
-fn to_date<A>(iso: A) -> Result<chrono::DateTime<chrono::Utc>, BadRequest>
-where
-  A: AsRef<str>,
-{
-  from_iso(iso.as_ref()).map_err(|_e| BadRequest::new("from", "Invalid ISO string date"))
+#[derive(Serialize, Deserialize)]
+pub struct ImportError {
+  pub code: u16,
+  pub message: String,
 }
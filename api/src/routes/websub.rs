@@ -0,0 +1,127 @@
+use axum::http::{HeaderMap, StatusCode};
+use axum::{
+  extract::{Path, Query},
+  routing::get,
+  Router,
+};
+use bytes::Bytes;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{debug, warn};
+
+use crate::errors::BadRequest;
+use crate::errors::Error;
+use crate::errors::NotFound;
+use crate::models::feed::Feed;
+use crate::utils::database_model::ModelExt;
+use crate::utils::to_object_id::to_object_id;
+use crate::utils::websub::verify_signature;
+
+pub fn create_router() -> Router {
+  Router::new().route(
+    "/websub/:feed_id",
+    get(verify_intent).post(receive_notification),
+  )
+}
+
+/// Hubs call this route to verify our subscription requests. The challenge is
+/// echoed back to confirm the request.
+async fn verify_intent(
+  Path(params): Path<HashMap<String, String>>,
+  Query(query): Query<VerifyIntentQuery>,
+) -> Result<String, Error> {
+  let feed_id = params.get("feed_id").unwrap().to_owned();
+  let feed_id = to_object_id(feed_id)?;
+
+  let feed = Feed::find_by_id(&feed_id).await?;
+  let websub = feed
+    .and_then(|feed| feed.websub)
+    .filter(|websub| websub.topic == query.topic);
+
+  match (query.mode.as_str(), websub) {
+    // Only the subscriptions we requested are verified, otherwise anyone
+    // knowing the topic could stop the feed from being polled.
+    ("subscribe", Some(websub)) if websub.is_requested() => {
+      let challenge = get_challenge(query.challenge)?;
+      Feed::set_websub_subscribed(&feed_id, query.lease_seconds).await?;
+
+      debug!("WebSub subscription verified for Feed {}", &feed_id);
+      Ok(challenge)
+    }
+    // We only unsubscribe from feeds we no longer have.
+    ("unsubscribe", None) => get_challenge(query.challenge),
+    ("denied", Some(_)) => {
+      warn!(
+        "WebSub subscription denied for Feed {}. Reason: {:?}",
+        &feed_id, query.reason
+      );
+      Feed::set_websub_denied(&feed_id).await?;
+      Ok(String::new())
+    }
+    _ => {
+      debug!("WebSub verification does not match any subscription, returning 404 status code");
+      Err(Error::NotFound(NotFound::new("subscription")))
+    }
+  }
+}
+
+/// Hubs call this route with the updated feed content. The feed is synced in
+/// the background, the hub only needs to know the notification was received.
+async fn receive_notification(
+  Path(params): Path<HashMap<String, String>>,
+  headers: HeaderMap,
+  body: Bytes,
+) -> Result<StatusCode, Error> {
+  let feed_id = params.get("feed_id").unwrap().to_owned();
+  let feed_id = to_object_id(feed_id)?;
+
+  let websub = Feed::find_by_id(&feed_id)
+    .await?
+    .and_then(|feed| feed.websub);
+
+  let websub = match websub {
+    Some(websub) => websub,
+    None => {
+      debug!("WebSub subscription not found, returning 404 status code");
+      return Err(Error::NotFound(NotFound::new("subscription")));
+    }
+  };
+
+  let signature = headers
+    .get("x-hub-signature")
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default();
+
+  // Notifications with an invalid signature must be acknowledged and ignored.
+  if !verify_signature(&websub.secret, signature, &body) {
+    warn!(
+      "Ignoring WebSub notification with an invalid signature for Feed {}",
+      &feed_id
+    );
+    return Ok(StatusCode::ACCEPTED);
+  }
+
+  // The sync is queued, it runs on a worker holding the feed lease like the
+  // scheduled syncs. Hubs retry the notifications that fail.
+  Feed::request_sync(feed_id).await?;
+
+  Ok(StatusCode::ACCEPTED)
+}
+
+fn get_challenge(challenge: Option<String>) -> Result<String, Error> {
+  challenge.ok_or_else(|| Error::BadRequest(BadRequest::new("hub.challenge", "Missing challenge")))
+}
+
+#[derive(Deserialize)]
+struct VerifyIntentQuery {
+  #[serde(rename = "hub.mode")]
+  mode: String,
+  #[serde(rename = "hub.topic")]
+  topic: String,
+  #[serde(rename = "hub.challenge")]
+  challenge: Option<String>,
+  #[serde(rename = "hub.lease_seconds")]
+  lease_seconds: Option<i64>,
+  #[serde(rename = "hub.reason")]
+  reason: Option<String>,
+}
@@ -0,0 +1,209 @@
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+use futures::StreamExt;
+use std::time::Instant;
+use tracing::{debug, error, info};
+use wither::WitherError;
+
+use crate::errors::Error;
+use crate::models::endpoint::Endpoint;
+use crate::models::feed::Feed;
+use crate::models::job::{Job, JobKind};
+use crate::models::subscription::Subscription;
+use crate::settings::get_settings;
+use crate::utils::database_model::ModelExt;
+use crate::utils::shutdown;
+
+pub fn start() {
+  // Queueing is idempotent, it is not waited for on shutdown.
+  tokio::spawn(run_scheduler());
+
+  for _ in 0..get_settings().jobs.concurrency {
+    shutdown::spawn(run_worker());
+  }
+}
+
+/// Queue the pending work every minute. Feeds are synced and subscriptions
+/// are notified by jobs that queue the next ones, this queues the first ones
+/// and the ones whose job was lost, e.g. because it is dead.
+async fn run_scheduler() {
+  while !shutdown::is_shutting_down() {
+    queue_pending_work().await;
+    shutdown::sleep(Duration::seconds(60).to_std().unwrap()).await;
+  }
+}
+
+/// Queue the sync of the feeds that are due and the notification of the
+/// subscriptions with entries to send. Jobs already queued are not queued
+/// again.
+async fn queue_pending_work() {
+  info!("Queueing pending jobs");
+  let start = Instant::now();
+
+  let feeds = match Feed::cursor(Feed::get_due_query(), None).await {
+    Ok(feeds) => feeds,
+    Err(err) => {
+      error!("Failed to fetch feeds cursor: {}", err);
+      return;
+    }
+  };
+  feeds
+    .take_until(shutdown::wait())
+    .filter_map(parse)
+    .for_each_concurrent(16, |feed| async move {
+      let kind = JobKind::SyncFeed {
+        feed: feed.id.unwrap(),
+      };
+      if let Err(err) = Job::enqueue(kind, Utc::now()).await {
+        error!("Failed to queue sync job. Error: {}", err);
+      }
+    })
+    .await;
+
+  let subscriptions =
+    match Subscription::cursor(doc! { "scheduled_at": { "$exists": true } }, None).await {
+      Ok(subscriptions) => subscriptions,
+      Err(err) => {
+        error!("Failed to fetch subscriptions cursor: {}", err);
+        return;
+      }
+    };
+  subscriptions
+    .take_until(shutdown::wait())
+    .filter_map(parse)
+    .for_each_concurrent(16, |subscription| async move {
+      let kind = JobKind::NotifySubscription {
+        subscription: subscription.id.unwrap(),
+      };
+      if let Err(err) = Job::enqueue(kind, Utc::now()).await {
+        error!("Failed to queue notification job. Error: {}", err);
+      }
+    })
+    .await;
+
+  let duration = start.elapsed();
+  info!("Finished queueing pending jobs elapsed={:.0?}", duration);
+}
+
+/// Run jobs until the shutdown starts. The job running when it starts is
+/// completed, no new job is claimed.
+async fn run_worker() {
+  while !shutdown::is_shutting_down() {
+    let job = match Job::claim().await {
+      Ok(Some(job)) => job,
+      Ok(None) => {
+        // Nothing to run, check again in a bit.
+        shutdown::sleep(Duration::seconds(1).to_std().unwrap()).await;
+        continue;
+      }
+      Err(err) => {
+        error!("Failed to claim job: {}", err);
+        // Something went wrong try again in a bit.
+        shutdown::sleep(Duration::seconds(1).to_std().unwrap()).await;
+        continue;
+      }
+    };
+
+    run_job(job).await;
+  }
+}
+
+async fn run_job(job: Job) {
+  let id = job.id.unwrap();
+  debug!("Running job {} key={}", &id, &job.key);
+
+  // The job was claimed again after its visibility timeout as many times as
+  // it can be attempted, it probably makes its worker crash.
+  let result = if job.attempts > get_settings().jobs.max_attempts {
+    Err("Job did not complete before its visibility timeout".to_owned())
+  } else {
+    perform(&job.kind).await
+  };
+
+  let result = match result {
+    Ok(_) => job.complete().await,
+    Err(err) => {
+      error!("Job {} key={} failed. Error: {}", &id, &job.key, &err);
+      job.fail(&err).await
+    }
+  };
+  if let Err(err) = result {
+    error!("Failed to update job {}. Error: {}", &id, err);
+  }
+}
+
+/// Do the work of the job. Failed jobs are retried.
+async fn perform(kind: &JobKind) -> Result<(), String> {
+  let result = match kind {
+    JobKind::SyncFeed { feed } => sync_feed(*feed).await,
+    JobKind::NotifySubscription { subscription } => notify_subscription(*subscription).await,
+    JobKind::RetryWebhook { webhook, payload } => {
+      match Endpoint::retry_webhook(*webhook, payload.clone()).await {
+        Ok(true) => Ok(()),
+        Ok(false) => return Err("Failed to deliver the webhook".to_owned()),
+        Err(err) => Err(err),
+      }
+    }
+    JobKind::RenewWebSub { feed } => Feed::renew_websub(*feed).await,
+    JobKind::CleanupFeed { feed } => Feed::cleanup(feed).await,
+  };
+
+  result.map_err(|err| err.to_string())
+}
+
+/// Sync the feed and queue its next sync. Failed syncs are recorded in the
+/// feed, which schedules the next attempt, so the job itself does not fail.
+async fn sync_feed(id: ObjectId) -> Result<(), Error> {
+  if let Err(err) = Feed::sync_due(id).await {
+    error!("Failed to sync Feed {:?}. Error: {}", id, err);
+  }
+
+  Feed::schedule_sync(&id).await
+}
+
+/// Notify the subscription and queue another notification while it still has
+/// entries to send.
+async fn notify_subscription(id: ObjectId) -> Result<(), Error> {
+  // The subscription is leased, notifications of the same subscription must
+  // not run at the same time. This also reads it again in case it was
+  // notified since the job was queued.
+  let query = doc! { "_id": id, "scheduled_at": { "$exists": true } };
+  let subscription = match Subscription::acquire_lease(query).await? {
+    Some(subscription) => subscription,
+    None => {
+      debug!("Skipping subscription {}, it is leased or notified", id);
+      return Ok(());
+    }
+  };
+
+  let result = subscription.notify().await;
+
+  if let Err(err) = Subscription::release_lease(&id).await {
+    error!(
+      "Failed to release lease of subscription {}. Error: {}",
+      id, err
+    );
+  }
+  result?;
+
+  // Entries are sent in batches, the remaining entries are sent by the next
+  // notification.
+  let query = doc! { "_id": id, "scheduled_at": { "$exists": true } };
+  if Subscription::exists(query).await? {
+    let kind = JobKind::NotifySubscription { subscription: id };
+    Job::enqueue(kind, Utc::now() + Duration::seconds(10)).await?;
+  }
+
+  Ok(())
+}
+
+async fn parse<T>(model: Result<T, WitherError>) -> Option<T> {
+  match model {
+    Ok(model) => Some(model),
+    Err(err) => {
+      error!("Failed to parse MongoDB document into model: {:?}", err);
+      None
+    }
+  }
+}
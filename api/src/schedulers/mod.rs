@@ -1,2 +1,2 @@
-pub mod feed;
-pub mod subscription;
+pub mod jobs;
+pub mod websub;
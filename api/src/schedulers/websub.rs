@@ -0,0 +1,83 @@
+use chrono::{Duration, Utc};
+use futures::StreamExt;
+use std::time::Instant;
+use tracing::error;
+use tracing::info;
+use wither::ModelCursor as Cursor;
+use wither::WitherError;
+
+use crate::errors::Error;
+use crate::models::feed::Feed;
+use crate::models::job::{Job, JobKind};
+use crate::settings::get_settings;
+use crate::utils::database_model::ModelExt;
+use crate::utils::shutdown;
+
+pub fn start() {
+  // WebSub is disabled when hubs have no way to reach us.
+  if get_settings().websub.callback_url.is_none() {
+    return;
+  }
+
+  // Queueing is idempotent, it is not waited for on shutdown.
+  tokio::spawn(run_job());
+}
+
+async fn run_job() {
+  while !shutdown::is_shutting_down() {
+    info!("Running WebSub scheduler");
+
+    let start = Instant::now();
+    let concurrency = 4;
+    let feeds = match find_feeds().await {
+      Ok(feeds) => feeds,
+      Err(error) => {
+        error!("Failed to fetch feeds cursor: {}", error);
+        // Something went wrong try again in a bit.
+        shutdown::sleep(Duration::seconds(1).to_std().unwrap()).await;
+        continue;
+      }
+    };
+
+    feeds
+      .take_until(shutdown::wait())
+      .filter_map(parse)
+      .for_each_concurrent(concurrency, queue_renewal)
+      .await;
+
+    let duration = start.elapsed();
+    info!("Finished running WebSub scheduler elapsed={:.0?}", duration);
+
+    shutdown::sleep(Duration::seconds(60).to_std().unwrap()).await;
+  }
+}
+
+async fn find_feeds() -> Result<Cursor<Feed>, Error> {
+  Feed::cursor(Feed::get_websub_due_query(), None).await
+}
+
+/// Queue the WebSub request of the feed. Every instance runs this scheduler,
+/// the job is queued once and the feed is requested once by the worker that
+/// runs it.
+async fn queue_renewal(feed: Feed) {
+  let id = feed.id.unwrap();
+  if let Err(err) = Job::enqueue(JobKind::RenewWebSub { feed: id }, Utc::now()).await {
+    error!(
+      "Failed to queue WebSub request of Feed {:?}. Error: {}",
+      id, err
+    );
+  }
+}
+
+async fn parse(feed: Result<Feed, WitherError>) -> Option<Feed> {
+  match feed {
+    Ok(feed) => Some(feed),
+    Err(err) => {
+      error!(
+        "Failed to parse MongoDB document into Feed model: {:?}",
+        err
+      );
+      None
+    }
+  }
+}
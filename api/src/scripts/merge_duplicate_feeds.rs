@@ -0,0 +1,66 @@
+use bson::doc;
+use futures::StreamExt;
+use std::collections::HashMap;
+use tracing::{error, info};
+use wither::bson::oid::ObjectId;
+use wither::mongodb::options::FindOptions;
+
+use crate::models::feed::Feed;
+use crate::utils::database_model::ModelExt;
+use crate::utils::normalize_url::normalize_url;
+
+pub async fn run() {
+  let options = FindOptions::builder()
+    .sort(doc! { "created_at": 1_i32 })
+    .build();
+  let feeds = Feed::cursor(doc! {}, Some(options)).await.unwrap();
+
+  // Feeds are grouped by their normalized URL without the scheme and their
+  // selectors and mapping, the oldest feed of each group is kept. Feeds
+  // created from the same page with different selectors or mapping are
+  // different feeds.
+  let mut canonical: HashMap<(String, String, String), Feed> = HashMap::new();
+  let mut duplicates: Vec<(Feed, ObjectId)> = vec![];
+
+  let feeds = feeds.map(|feed| feed.unwrap()).collect::<Vec<Feed>>().await;
+  for feed in feeds {
+    let url = match normalize_url(&feed.url) {
+      Ok(url) => url
+        .split_once("://")
+        .map(|(_, rest)| rest.to_owned())
+        .unwrap_or(url),
+      Err(_) => continue,
+    };
+    let key = (
+      url,
+      serde_json::to_string(&feed.selectors).unwrap_or_default(),
+      serde_json::to_string(&feed.mapping).unwrap_or_default(),
+    );
+
+    match canonical.get(&key) {
+      Some(target) => duplicates.push((feed, target.id.unwrap())),
+      None => {
+        canonical.insert(key, feed);
+      }
+    }
+  }
+
+  for (feed, target_id) in duplicates {
+    let id = feed.id.unwrap();
+    info!("Merging feed {} into feed {}", &id, &target_id);
+
+    // The target is fetched again, previous merges updated its aliases.
+    let target = match Feed::find_by_id(&target_id).await {
+      Ok(Some(target)) => target,
+      Ok(None) => continue,
+      Err(err) => {
+        error!("Failed to find Feed {:?}. Error: {}", target_id, err);
+        continue;
+      }
+    };
+
+    if let Err(err) = feed.merge_into(&target).await {
+      error!("Failed to merge Feed {:?}. Error: {}", id, err);
+    }
+  }
+}
@@ -1,2 +1,3 @@
 pub mod cleanup_feeds;
 pub mod create_subscriptions;
+pub mod merge_duplicate_feeds;
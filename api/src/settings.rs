@@ -1,6 +1,7 @@
 use config::{Config, ConfigError, Environment, File, FileFormat};
+use ipnet::IpNet;
 use lazy_static::lazy_static;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{env, fmt};
 
 lazy_static! {
@@ -19,6 +20,9 @@ pub fn get_settings() -> &'static Settings {
 #[derive(Debug, Clone, Deserialize)]
 pub struct Server {
   pub port: u16,
+  // Seconds given to in-flight requests, feed syncs and webhooks to complete
+  // on shutdown.
+  pub shutdown_grace_period: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -37,6 +41,86 @@ pub struct Auth {
   pub secret: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct Feeds {
+  // Bounds, in seconds, for the time between two syncs of the same feed.
+  pub min_sync_interval: i64,
+  pub max_sync_interval: i64,
+  // Fraction of the sync interval randomly added or removed.
+  pub sync_jitter: f64,
+  // Amount of consecutive failed syncs after which a feed is considered dead
+  // and is no longer synced.
+  pub max_consecutive_failures: i32,
+  // Default retention of feed entries, feeds can override it.
+  pub retention: Retention,
+  // Days the fetch history of a feed is kept.
+  pub fetch_history_days: i64,
+}
+
+/// How long feed entries are kept. Entries exceeding the amount of entries or
+/// older than the maximum age, in days, are removed. Entries are kept forever
+/// when neither is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Retention {
+  pub max_entries: Option<i64>,
+  pub max_age_days: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hosts {
+  // Requests sent to the same host are limited with a token bucket, refilled
+  // at this rate and holding up to burst requests.
+  pub requests_per_minute: f64,
+  pub burst: f64,
+  // Maximum amount of concurrent requests to the same host.
+  pub max_concurrency: usize,
+  // Seconds without requests to a host that responds with a 429 status code
+  // without a Retry-After header.
+  pub default_retry_after: i64,
+  pub respect_robots_txt: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebSub {
+  // Public URL of the WebSub callback route, hubs need to reach it to verify
+  // and notify our subscriptions. WebSub is disabled when it is not set.
+  pub callback_url: Option<String>,
+  pub lease_seconds: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Outbound {
+  // User-Agent of our requests, it should include a contact URL for website
+  // owners.
+  pub user_agent: String,
+  // Ports allowed in URLs we send requests to, E.g. feed and endpoint URLs.
+  pub allowed_ports: Vec<u16>,
+  // Hosts and networks allowed even if they are private. Self-hosted
+  // deployments use this to reach services in their network.
+  pub allowed_hosts: Vec<String>,
+  pub allowed_networks: Vec<IpNet>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Schedulers {
+  // Seconds a scheduler instance holds a feed or subscription while working
+  // on it. Leases of crashed instances are taken over once they expire.
+  pub lease_seconds: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Jobs {
+  // Amount of jobs run at the same time by each instance.
+  pub concurrency: usize,
+  // Seconds a worker holds a job, jobs not completed in time are run again.
+  pub visibility_timeout: i64,
+  // Attempts after which a failing job is dead and no longer retried.
+  pub max_attempts: i32,
+  // Bounds, in seconds, of the exponential backoff between attempts.
+  pub retry_delay: i64,
+  pub max_retry_delay: i64,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Settings {
   pub environment: String,
@@ -44,6 +128,12 @@ pub struct Settings {
   pub logger: Logger,
   pub database: Database,
   pub auth: Auth,
+  pub feeds: Feeds,
+  pub websub: WebSub,
+  pub outbound: Outbound,
+  pub hosts: Hosts,
+  pub schedulers: Schedulers,
+  pub jobs: Jobs,
 }
 
 impl Settings {
@@ -1,4 +1,8 @@
 mod models;
+mod outbound;
+mod podcast;
+mod robots;
 mod routes;
 mod setup;
+mod shutdown;
 mod utils;
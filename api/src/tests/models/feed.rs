@@ -0,0 +1,1344 @@
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::{Datelike, Duration, Timelike, Utc, Weekday};
+use lazy_static::lazy_static;
+use mockito::mock;
+use wither::mongodb::options::FindOptions;
+
+use crate::errors::Error;
+use crate::models::entry::{Entry, IdStrategy};
+use crate::models::feed::{Feed, FeedStatus};
+use crate::models::feed_fetch::{FeedFetch, FetchOutcome};
+use crate::models::job::{Job, JobKind};
+use crate::models::subscription::Subscription;
+use crate::settings::get_settings;
+use crate::tests::setup::with_app;
+use crate::tests::utils::create_feed;
+use crate::utils::database_model::ModelExt;
+use crate::utils::date::Date;
+use crate::utils::map_json::JsonMapping;
+use crate::utils::scrape::Selectors;
+
+lazy_static! {
+  static ref FIXTURE: &'static str = include_str!("../fixture/reddit_atom.xml");
+}
+
+#[test]
+fn sync_stores_the_feed_validators() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_header("etag", "\"v1\"")
+    .with_header("last-modified", "Sun, 24 May 2020 21:51:16 GMT")
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.etag, Some("\"v1\"".to_string()));
+    assert_eq!(
+      feed.last_modified,
+      Some("Sun, 24 May 2020 21:51:16 GMT".to_string())
+    );
+    assert!(feed.content_hash.is_some());
+
+    let count = Entry::count(doc! { "feed": &feed_id }).await.unwrap();
+    assert_eq!(count, 1, "Should have stored the feed entry");
+  });
+}
+
+#[test]
+fn sync_sends_conditional_headers_and_skips_not_modified_feeds() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_header("etag", "\"v1\"")
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    // Remove the stored entries, a not modified feed should not be processed
+    // again.
+    Entry::delete_many(doc! { "feed": &feed_id }).await.unwrap();
+
+    let not_modified_mock = mock("GET", "/")
+      .match_header("if-none-match", "\"v1\"")
+      .with_status(304)
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    not_modified_mock.assert();
+
+    let count = Entry::count(doc! { "feed": &feed_id }).await.unwrap();
+    assert_eq!(count, 0, "Should not process a not modified feed");
+  });
+}
+
+#[test]
+fn sync_skips_feeds_with_unchanged_content() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .expect(2)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    Entry::delete_many(doc! { "feed": &feed_id }).await.unwrap();
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let count = Entry::count(doc! { "feed": &feed_id }).await.unwrap();
+    assert_eq!(count, 0, "Should not process a feed with the same content");
+  });
+}
+
+#[test]
+fn sync_schedules_the_next_sync_using_the_feed_hints() {
+  // The feed publishes every 10 minutes, it would be synced every 5 minutes
+  // without hints.
+  let now = Utc::now();
+  let body = format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/">
+  <channel>
+    <title>Hints</title>
+    <link>https://example.com</link>
+    <ttl>180</ttl>
+    <sy:updatePeriod>hourly</sy:updatePeriod>
+    <sy:updateFrequency>2</sy:updateFrequency>
+    <item>
+      <guid>https://example.com/2</guid>
+      <title>Entry 2</title>
+      <pubDate>{}</pubDate>
+    </item>
+    <item>
+      <guid>https://example.com/1</guid>
+      <title>Entry 1</title>
+      <pubDate>{}</pubDate>
+    </item>
+  </channel>
+</rss>"#,
+    now.to_rfc2822(),
+    (now - Duration::minutes(10)).to_rfc2822()
+  );
+
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_header("cache-control", "public, max-age=21600")
+    .with_body(body)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.hints.publish_interval, Some(10 * 60));
+    assert_eq!(feed.hints.ttl, Some(180 * 60));
+    assert_eq!(feed.hints.update_period, Some(30 * 60));
+
+    // The Cache-Control max-age is the longest hint, the feed is synced every
+    // 6 hours, plus or minus the jitter, instead of every 3 hours as the ttl
+    // asks.
+    let synced_at = feed.synced_at.timestamp_millis();
+    let next_sync_at = feed.next_sync_at.unwrap().timestamp_millis();
+    let interval = (next_sync_at - synced_at) / 1000;
+    assert!(
+      interval >= (21_600_f64 * 0.9) as i64,
+      "Should respect the Cache-Control max-age"
+    );
+    assert!(interval <= (21_600_f64 * 1.1) as i64 + 1);
+  });
+}
+
+#[test]
+fn sync_schedules_the_next_sync_out_of_the_skipped_hours_and_days() {
+  // Every day but one and every hour but noon are skipped, the next sync is
+  // at noon of that day.
+  let day = (Utc::now() + Duration::days(3)).weekday();
+  let skip_days = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+  ]
+  .into_iter()
+  .filter(|skipped| *skipped != day)
+  .map(|skipped| format!("<day>{:?}</day>", skipped))
+  .collect::<String>();
+  let skip_hours = (0..24)
+    .filter(|hour| *hour != 12)
+    .map(|hour| format!("<hour>{}</hour>", hour))
+    .collect::<String>();
+  let body = format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Hints</title>
+    <link>https://example.com</link>
+    <skipHours>{}</skipHours>
+    <skipDays>{}</skipDays>
+    <item>
+      <guid>https://example.com/1</guid>
+      <title>Entry</title>
+      <pubDate>Sun, 24 May 2020 21:51:16 GMT</pubDate>
+    </item>
+  </channel>
+</rss>"#,
+    skip_hours, skip_days
+  );
+
+  let request_feed_mock = mock("GET", "/").with_status(200).with_body(body).create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.hints.skip_hours.len(), 23);
+    assert_eq!(feed.hints.skip_days.len(), 6);
+
+    let next_sync_at = feed.next_sync_at.unwrap().to_chrono();
+    assert_eq!(next_sync_at.weekday(), day, "Should skip the days");
+    assert_eq!(next_sync_at.hour(), 12, "Should skip the hours");
+    assert_eq!(next_sync_at.minute(), 0);
+  });
+}
+
+#[test]
+fn sync_bounds_the_retry_after_by_the_maximum_interval() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_header("retry-after", "2592000")
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    // The server asks to come back in 30 days, the feed is synced again
+    // within the maximum interval, plus the jitter.
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    let synced_at = feed.synced_at.timestamp_millis();
+    let next_sync_at = feed.next_sync_at.unwrap().timestamp_millis();
+    let interval = (next_sync_at - synced_at) / 1000;
+    let max_sync_interval = get_settings().feeds.max_sync_interval as f64;
+    assert!(
+      interval <= (max_sync_interval * 1.1) as i64 + 1,
+      "Should not wait longer than the maximum interval"
+    );
+  });
+}
+
+#[test]
+fn sync_records_failures_and_marks_feeds_as_dead() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(500)
+    .with_body("Internal Server Error")
+    .expect(2)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let subscription = Subscription::new(
+      ObjectId::new(),
+      feed_id,
+      ObjectId::new(),
+      feed.url.clone(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+    let subscription_id = subscription.id.unwrap();
+
+    let result = Feed::sync(feed_id).await;
+    assert!(matches!(result, Err(Error::GetFeed(_))));
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.status, FeedStatus::Degraded);
+    assert_eq!(feed.consecutive_failures, 1);
+    assert!(feed.last_error.is_some());
+    assert!(feed.last_error_at.is_some());
+    assert!(feed.next_sync_at.unwrap() > feed.last_error_at.unwrap());
+
+    let subscription = Subscription::find_by_id(&subscription_id)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.feed_status, FeedStatus::Degraded);
+    assert_eq!(subscription.feed_error, feed.last_error);
+
+    // Fail the last allowed time.
+    let max_consecutive_failures = get_settings().feeds.max_consecutive_failures;
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "consecutive_failures": max_consecutive_failures - 1 } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    Feed::sync(feed_id).await.unwrap_err();
+    request_feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.status, FeedStatus::Dead);
+    assert_eq!(feed.consecutive_failures, max_consecutive_failures);
+
+    let subscription = Subscription::find_by_id(&subscription_id)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.feed_status, FeedStatus::Dead);
+  });
+}
+
+#[test]
+fn sync_makes_degraded_feeds_healthy_again() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! {
+        "$set": {
+          "status": "degraded",
+          "consecutive_failures": 3_i32,
+          "last_error": "Timeout"
+        }
+      },
+      None,
+    )
+    .await
+    .unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.status, FeedStatus::Healthy);
+    assert_eq!(feed.consecutive_failures, 0);
+  });
+}
+
+#[test]
+fn sync_follows_permanent_redirects() {
+  let moved_url = format!("{}/moved", mockito::server_url());
+
+  let redirect_mock = mock("GET", "/")
+    .with_status(301)
+    .with_header("location", &moved_url)
+    .create();
+
+  let request_feed_mock = mock("GET", "/moved")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    redirect_mock.assert();
+    request_feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.url, moved_url, "Should update the feed URL");
+    assert!(
+      feed
+        .aliases
+        .contains(&"https://www.reddit.com/r/rust/.rss".to_string()),
+      "Should keep the previous URL as an alias"
+    );
+
+    let found = Feed::find_by_url("https://www.reddit.com/r/rust/.rss")
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(found.id, Some(feed_id));
+  });
+}
+
+#[test]
+fn sync_merges_feeds_that_move_to_an_existing_feed() {
+  let moved_url = format!("{}/moved", mockito::server_url());
+
+  let redirect_mock = mock("GET", "/")
+    .with_status(301)
+    .with_header("location", &moved_url)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let existing = Feed {
+      id: None,
+      url: moved_url.clone(),
+      ..feed.clone()
+    };
+    let existing = Feed::create(existing).await.unwrap();
+    let existing_id = existing.id.unwrap();
+
+    let subscription = Subscription::new(
+      ObjectId::new(),
+      feed_id,
+      ObjectId::new(),
+      feed.url.clone(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    redirect_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap();
+    assert!(feed.is_none(), "Should remove the duplicated feed");
+
+    let subscription = Subscription::find_by_id(&subscription.id.unwrap())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.feed, existing_id);
+
+    let existing = Feed::find_by_id(&existing_id).await.unwrap().unwrap();
+    assert!(existing
+      .aliases
+      .contains(&"https://www.reddit.com/r/rust/.rss".to_string()));
+  });
+}
+
+#[test]
+fn sync_detects_updated_entries() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let entry = Entry::find_one(doc! { "feed": &feed_id }, None)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(entry.revision, 0);
+    assert!(entry.updated_at.is_none());
+
+    let subscription = Subscription::new(
+      ObjectId::new(),
+      feed_id,
+      ObjectId::new(),
+      feed.url.clone(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    let updated_fixture = FIXTURE.replace(
+      "Hey Rustaceans! Got an easy question? Ask here (21/2020)!",
+      "Hey Rustaceans! Got a question? Ask here (21/2020)!",
+    );
+    let updated_feed_mock = mock("GET", "/")
+      .with_status(200)
+      .with_body(updated_fixture)
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    updated_feed_mock.assert();
+
+    let entry = Entry::find_by_id(&entry.id.unwrap())
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(entry.revision, 1, "Should increment the entry revision");
+    assert!(entry.updated_at.is_some());
+    assert_eq!(
+      entry.title,
+      Some("Hey Rustaceans! Got a question? Ask here (21/2020)!".to_string())
+    );
+
+    let count = Entry::count(doc! { "feed": &feed_id }).await.unwrap();
+    assert_eq!(count, 1, "Should not store the updated entry twice");
+
+    let subscription = Subscription::find_by_id(&subscription.id.unwrap())
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(
+      subscription.scheduled_at.is_some(),
+      "Should schedule the subscription to notify the update"
+    );
+  });
+}
+
+fn create_rss(items: &[(&str, &str)]) -> String {
+  let items = items
+    .iter()
+    .map(|(id, date)| {
+      format!(
+        "<item><guid>{}</guid><title>{}</title><pubDate>{}</pubDate></item>",
+        id, id, date
+      )
+    })
+    .collect::<String>();
+
+  format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0"><channel><title>Feed</title>{}</channel></rss>"#,
+    items
+  )
+}
+
+#[test]
+fn sync_inserts_every_new_entry_ordered_by_date() {
+  // Oldest entries first.
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(create_rss(&[
+      ("first", "Mon, 18 May 2020 10:00:00 GMT"),
+      ("third", "Wed, 20 May 2020 10:00:00 GMT"),
+    ]))
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    // New entries that are not at the top of the feed.
+    let updated_feed_mock = mock("GET", "/")
+      .with_status(200)
+      .with_body(create_rss(&[
+        ("first", "Mon, 18 May 2020 10:00:00 GMT"),
+        ("second", "Tue, 19 May 2020 10:00:00 GMT"),
+        ("fifth", "Fri, 22 May 2020 10:00:00 GMT"),
+        ("third", "Wed, 20 May 2020 10:00:00 GMT"),
+        ("fourth", "Thu, 21 May 2020 10:00:00 GMT"),
+      ]))
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    updated_feed_mock.assert();
+
+    let entries = Entry::find(
+      doc! { "feed": &feed_id },
+      FindOptions::builder().sort(doc! { "_id": 1_i32 }).build(),
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|entry| entry.public_id)
+    .collect::<Vec<String>>();
+
+    assert_eq!(
+      entries,
+      vec!["first", "third", "second", "fourth", "fifth"],
+      "Should insert every new entry once, ordered by date"
+    );
+  });
+}
+
+#[test]
+fn sync_switches_the_id_strategy_of_feeds_with_unstable_ids() {
+  let create_item_feed = |guid: &str| {
+    format!(
+      r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0"><channel><title>Feed</title><item><guid>{}</guid><title>Entry</title><link>https://example.com/entry</link><pubDate>Mon, 18 May 2020 10:00:00 GMT</pubDate></item></channel></rss>"#,
+      guid
+    )
+  };
+
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(create_item_feed("session-1"))
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    // Same entry with a different guid.
+    let updated_feed_mock = mock("GET", "/")
+      .with_status(200)
+      .with_body(create_item_feed("session-2"))
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    updated_feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.id_strategy, IdStrategy::Link);
+
+    let entries = Entry::find(doc! { "feed": &feed_id }, None).await.unwrap();
+    assert_eq!(entries.len(), 1, "Should not store the entry twice");
+    assert_eq!(entries[0].public_id, "https://example.com/entry");
+  });
+}
+
+#[test]
+fn sync_stops_requesting_hosts_that_rate_limit_us() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(429)
+    .with_header("retry-after", "120")
+    .expect(1)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    // The host is rate limited for every test, use a host only used here.
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "url": "https://rate-limited.example.com/feed.xml" } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    let err = Feed::sync(feed_id).await.unwrap_err();
+    assert!(matches!(err, Error::GetFeed(_)));
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(
+      feed.consecutive_failures, 0,
+      "Should not count as a failure"
+    );
+    assert_eq!(feed.status, FeedStatus::Healthy);
+    let next_sync_at = feed.next_sync_at.unwrap().timestamp_millis();
+    let expected = chrono::Utc::now().timestamp_millis() + 120_000;
+    assert!((next_sync_at - expected).abs() < 5_000);
+
+    // The host is not requested again until the retry date.
+    Feed::sync(feed_id).await.unwrap_err();
+    request_feed_mock.assert();
+  });
+}
+
+#[test]
+fn sync_keeps_entries_not_yet_sent_to_subscriptions() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(create_rss(&[
+      ("c", "Wed, 20 May 2020 10:00:00 GMT"),
+      ("b", "Tue, 19 May 2020 10:00:00 GMT"),
+      ("a", "Mon, 18 May 2020 10:00:00 GMT"),
+    ]))
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "retention": { "max_entries": 1_i64, "max_age_days": null } } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let find_public_ids = || async {
+      Entry::find(
+        doc! { "feed": &feed_id },
+        FindOptions::builder().sort(doc! { "_id": 1_i32 }).build(),
+      )
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|entry| entry.public_id)
+      .collect::<Vec<String>>()
+    };
+
+    let entry_a = Entry::find_one(doc! { "public_id": "a" }, None)
+      .await
+      .unwrap()
+      .unwrap();
+    let mut subscription = Subscription::new(
+      ObjectId::new(),
+      feed_id,
+      ObjectId::new(),
+      feed.url.clone(),
+      None,
+    );
+    subscription.last_notified_entry = entry_a.id;
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    // Entries b and c were not sent to the subscription yet.
+    let second_feed_mock = mock("GET", "/")
+      .with_status(200)
+      .with_body(create_rss(&[("d", "Thu, 21 May 2020 10:00:00 GMT")]))
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    second_feed_mock.assert();
+    assert_eq!(find_public_ids().await, vec!["a", "b", "c", "d"]);
+
+    // Entry c is the last entry sent to the subscription.
+    let entry_c = Entry::find_one(doc! { "public_id": "c" }, None)
+      .await
+      .unwrap()
+      .unwrap();
+    Subscription::update_one(
+      doc! { "_id": subscription.id.unwrap() },
+      doc! { "$set": { "last_notified_entry": entry_c.id.unwrap() } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    let third_feed_mock = mock("GET", "/")
+      .with_status(200)
+      .with_body(create_rss(&[
+        ("e", "Fri, 22 May 2020 10:00:00 GMT"),
+        ("d", "Thu, 21 May 2020 10:00:00 GMT"),
+      ]))
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    third_feed_mock.assert();
+    assert_eq!(
+      find_public_ids().await,
+      vec!["c", "d", "e"],
+      "Should remove the entries already sent"
+    );
+  });
+}
+
+#[test]
+fn sync_keeps_entries_while_a_subscription_was_never_notified() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(create_rss(&[
+      ("b", "Tue, 19 May 2020 10:00:00 GMT"),
+      ("a", "Mon, 18 May 2020 10:00:00 GMT"),
+    ]))
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "retention": { "max_entries": 1_i64, "max_age_days": null } } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    request_feed_mock.assert();
+
+    let find_public_ids = || async {
+      Entry::find(
+        doc! { "feed": &feed_id },
+        FindOptions::builder().sort(doc! { "_id": 1_i32 }).build(),
+      )
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|entry| entry.public_id)
+      .collect::<Vec<String>>()
+    };
+
+    // Subscriptions never notified are sent every entry of the feed.
+    let subscription = Subscription::new(
+      ObjectId::new(),
+      feed_id,
+      ObjectId::new(),
+      feed.url.clone(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    let second_feed_mock = mock("GET", "/")
+      .with_status(200)
+      .with_body(create_rss(&[("d", "Thu, 21 May 2020 10:00:00 GMT")]))
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    second_feed_mock.assert();
+    assert_eq!(
+      find_public_ids().await,
+      vec!["a", "b", "d"],
+      "Should keep the entries not sent to the subscription"
+    );
+
+    // Entry b is the last entry sent to the subscription.
+    let entry_b = Entry::find_one(doc! { "public_id": "b" }, None)
+      .await
+      .unwrap()
+      .unwrap();
+    Subscription::update_one(
+      doc! { "_id": subscription.id.unwrap() },
+      doc! { "$set": { "last_notified_entry": entry_b.id.unwrap() } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    let third_feed_mock = mock("GET", "/")
+      .with_status(200)
+      .with_body(create_rss(&[
+        ("e", "Fri, 22 May 2020 10:00:00 GMT"),
+        ("d", "Thu, 21 May 2020 10:00:00 GMT"),
+      ]))
+      .create();
+
+    Feed::sync(feed_id).await.unwrap();
+    third_feed_mock.assert();
+    assert_eq!(
+      find_public_ids().await,
+      vec!["b", "d", "e"],
+      "Should remove the entries already sent"
+    );
+  });
+}
+
+#[test]
+fn entries_sync_keeps_the_feed_position_of_undated_entries() {
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    let retention = &get_settings().feeds.retention;
+
+    // Newest entries first, the dated entries are not in order and the
+    // undated entry is older than the newest one.
+    let rss = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Feed</title>
+    <item><guid>third</guid><pubDate>Wed, 20 May 2020 10:00:00 GMT</pubDate></item>
+    <item><guid>undated</guid><title>Undated</title></item>
+    <item><guid>first</guid><pubDate>Mon, 18 May 2020 10:00:00 GMT</pubDate></item>
+    <item><guid>second</guid><pubDate>Tue, 19 May 2020 10:00:00 GMT</pubDate></item>
+  </channel>
+</rss>"#;
+    let entries = feed_rs::parser::parse(rss.as_bytes())
+      .unwrap()
+      .entries
+      .into_iter()
+      .map(|entry| Entry::from_raw_entry(feed_id, entry, IdStrategy::Guid))
+      .collect::<Vec<Entry>>();
+    Entry::sync(&feed_id, entries, retention).await.unwrap();
+
+    let public_ids = Entry::find(
+      doc! { "feed": &feed_id },
+      FindOptions::builder().sort(doc! { "_id": 1_i32 }).build(),
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|entry| entry.public_id)
+    .collect::<Vec<String>>();
+    assert_eq!(
+      public_ids,
+      vec!["first", "second", "undated", "third"],
+      "Should sort the dated entries around the undated entry"
+    );
+  });
+}
+
+#[test]
+fn entries_sync_counts_inserted_updated_and_unchanged_entries() {
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    let retention = &get_settings().feeds.retention;
+
+    let parse_entries = |rss: String| {
+      feed_rs::parser::parse(rss.as_bytes())
+        .unwrap()
+        .entries
+        .into_iter()
+        .map(|entry| Entry::from_raw_entry(feed_id, entry, IdStrategy::Guid))
+        .collect::<Vec<Entry>>()
+    };
+
+    let entries = parse_entries(create_rss(&[
+      ("second", "Tue, 19 May 2020 10:00:00 GMT"),
+      ("first", "Mon, 18 May 2020 10:00:00 GMT"),
+    ]));
+    let synced = Entry::sync(&feed_id, entries, retention).await.unwrap();
+    assert_eq!(
+      (synced.inserted, synced.updated, synced.unchanged),
+      (2, 0, 0)
+    );
+
+    let entries = parse_entries(
+      create_rss(&[
+        ("third", "Wed, 20 May 2020 10:00:00 GMT"),
+        ("second", "Tue, 19 May 2020 10:00:00 GMT"),
+        ("first", "Mon, 18 May 2020 10:00:00 GMT"),
+      ])
+      .replace("<title>second</title>", "<title>Second edited</title>"),
+    );
+    let synced = Entry::sync(&feed_id, entries, retention).await.unwrap();
+    assert_eq!(
+      (synced.inserted, synced.updated, synced.unchanged),
+      (1, 1, 1)
+    );
+
+    let public_ids = Entry::find(
+      doc! { "feed": &feed_id },
+      FindOptions::builder().sort(doc! { "_id": 1_i32 }).build(),
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|entry| entry.public_id)
+    .collect::<Vec<String>>();
+    assert_eq!(public_ids, vec!["first", "second", "third"]);
+  });
+}
+
+#[test]
+fn sync_stores_the_entries_of_page_feeds() {
+  let page_mock = mock("GET", "/")
+    .with_status(200)
+    .with_header("content-type", "text/html")
+    .with_body(include_str!("../fixture/blog.html"))
+    .expect(2)
+    .create();
+
+  with_app(async move {
+    let selectors = Selectors {
+      item: ".news-item".to_string(),
+      title: "h2".to_string(),
+      link: None,
+      date: Some("time, .date".to_string()),
+      summary: Some(".summary".to_string()),
+    };
+    let feed = Feed::from_source(
+      "https://example.com/news".to_string(),
+      Some(selectors),
+      None,
+    )
+    .await
+    .unwrap();
+    let feed = Feed::create(feed).await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    page_mock.assert();
+
+    let entries = Entry::find(
+      doc! { "feed": &feed_id },
+      FindOptions::builder().sort(doc! { "_id": 1_i32 }).build(),
+    )
+    .await
+    .unwrap();
+    let public_ids = entries
+      .iter()
+      .map(|entry| entry.public_id.as_str())
+      .collect::<Vec<&str>>();
+    assert_eq!(
+      public_ids,
+      vec![
+        "https://example.com/news/first-release",
+        "https://example.com/news/second-release"
+      ],
+      "Should insert the oldest entry first"
+    );
+    assert_eq!(
+      entries[0].description_text,
+      Some("Our first release.".to_string())
+    );
+  });
+}
+
+#[test]
+fn sync_stores_the_entries_of_json_api_feeds() {
+  let api_mock = mock("GET", "/")
+    .with_status(200)
+    .with_header("content-type", "application/json")
+    .with_body(include_str!("../fixture/releases.json"))
+    .expect(2)
+    .create();
+
+  with_app(async move {
+    let mapping = JsonMapping {
+      items: "/data/releases".to_string(),
+      id: Some("/id".to_string()),
+      title: Some("/name".to_string()),
+      url: Some("/html_url".to_string()),
+      date: Some("/published_at".to_string()),
+      summary: Some("/body".to_string()),
+    };
+    let url = "https://api.example.com/releases".to_string();
+    let feed = Feed::from_source(url, None, Some(mapping)).await.unwrap();
+    let feed = Feed::create(feed).await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    api_mock.assert();
+
+    let entries = Entry::find(
+      doc! { "feed": &feed_id },
+      FindOptions::builder().sort(doc! { "_id": 1_i32 }).build(),
+    )
+    .await
+    .unwrap();
+    let public_ids = entries
+      .iter()
+      .map(|entry| entry.public_id.as_str())
+      .collect::<Vec<&str>>();
+    assert_eq!(
+      public_ids,
+      vec!["1", "2"],
+      "Should insert the oldest entry first"
+    );
+    assert_eq!(
+      entries[0].url,
+      Some("https://api.example.com/releases/v1.0.0".to_string())
+    );
+  });
+}
+
+#[test]
+fn sync_stores_the_podcast_metadata_of_podcast_feeds() {
+  let feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(include_str!("../fixture/podcast_rss.xml"))
+    .expect(2)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    let podcast = feed.podcast.unwrap();
+    assert_eq!(podcast.author, Some("Jane Doe".to_string()));
+    assert_eq!(podcast.explicit, Some(false));
+    assert_eq!(podcast.categories, vec!["Technology", "Software How-To"]);
+
+    let find_episode = |public_id: &'static str| async move {
+      Entry::find_one(doc! { "feed": &feed_id, "public_id": public_id }, None)
+        .await
+        .unwrap()
+        .unwrap()
+    };
+
+    let entry = find_episode("https://podcast.example.com/episodes/1").await;
+    let episode = entry.podcast.unwrap();
+    assert_eq!(episode.duration, Some(3723));
+    assert_eq!(episode.episode, Some(1));
+    assert_eq!(episode.season, Some(2));
+    assert_eq!(episode.explicit, Some(true));
+    assert_eq!(episode.transcripts.len(), 2);
+    assert_eq!(
+      episode.chapters.unwrap().url,
+      "https://podcast.example.com/episodes/1/chapters.json"
+    );
+
+    let entry = find_episode("https://podcast.example.com/episodes/0").await;
+    let episode = entry.podcast.unwrap();
+    assert_eq!(episode.episode_type, Some("trailer".to_string()));
+    assert_eq!(episode.enclosure.unwrap().length, Some(2048));
+
+    // Entries stored before the podcast metadata was parsed get it on the
+    // next sync without being considered updated.
+    Entry::update_many(
+      doc! { "feed": &feed_id },
+      doc! { "$unset": { "podcast": 1_i32 } },
+      None,
+    )
+    .await
+    .unwrap();
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$unset": { "content_hash": 1_i32 } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    feed_mock.assert();
+
+    let entry = find_episode("https://podcast.example.com/episodes/1").await;
+    assert_eq!(entry.podcast.unwrap().duration, Some(3723));
+    assert_eq!(entry.revision, 0);
+  });
+}
+
+#[test]
+fn sync_matches_the_podcast_metadata_by_namespace_and_entry() {
+  // The namespaces use other prefixes, the itunes prefix is bound to another
+  // namespace and the first entry is not an episode.
+  let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:it="http://www.itunes.com/dtds/podcast-1.0.dtd" xmlns:pi="https://podcastindex.org/namespace/1.0" xmlns:itunes="https://example.com/not-itunes">
+  <channel>
+    <title>Rust Podcast</title>
+    <link>https://podcast.example.com</link>
+    <it:author>Jane Doe</it:author>
+    <item>
+      <guid>https://podcast.example.com/news/1</guid>
+      <title>News</title>
+      <link>https://podcast.example.com/news/1</link>
+      <itunes:duration>60</itunes:duration>
+    </item>
+    <item>
+      <guid>https://podcast.example.com/episodes/1</guid>
+      <title>Episode 1</title>
+      <link>https://podcast.example.com/episodes/1</link>
+      <it:duration>1:02:03</it:duration>
+      <pi:season>2</pi:season>
+    </item>
+    <item>
+      <title>Episode 2</title>
+      <link>https://podcast.example.com/episodes/2</link>
+      <it:duration>95</it:duration>
+    </item>
+  </channel>
+</rss>"#;
+
+  let feed_mock = mock("GET", "/").with_status(200).with_body(body).create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.podcast.unwrap().author, Some("Jane Doe".to_string()));
+
+    let find_entry = |url: &'static str| async move {
+      Entry::find_one(doc! { "feed": &feed_id, "url": url }, None)
+        .await
+        .unwrap()
+        .unwrap()
+    };
+
+    let entry = find_entry("https://podcast.example.com/news/1").await;
+    assert!(entry.podcast.is_none(), "Should ignore other namespaces");
+
+    let entry = find_entry("https://podcast.example.com/episodes/1").await;
+    let episode = entry.podcast.unwrap();
+    assert_eq!(episode.duration, Some(3723));
+    assert_eq!(episode.season, Some(2));
+
+    // Entries without a guid are matched by their link.
+    let entry = find_entry("https://podcast.example.com/episodes/2").await;
+    assert_eq!(entry.podcast.unwrap().duration, Some(95));
+  });
+}
+
+#[test]
+fn sync_records_failed_fetches() {
+  let feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body("This is not a feed")
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let result = Feed::sync(feed_id).await;
+    assert!(result.is_err());
+    feed_mock.assert();
+
+    let fetch = FeedFetch::find_one(doc! { "feed": &feed_id }, None)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(fetch.outcome, FetchOutcome::Invalid);
+    assert_eq!(fetch.status, Some(200));
+    assert_eq!(fetch.bytes, Some(18));
+    assert_eq!(
+      fetch.error,
+      Some("Feed URL does not point to a valid RSS, Atom or JSON feed".to_string())
+    );
+    assert!(fetch.entries.is_none());
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.consecutive_failures, 1);
+  });
+}
+
+#[test]
+fn sync_skips_feeds_leased_by_another_instance() {
+  let feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .expect(0)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let locked_until: Date = (Utc::now() + Duration::minutes(5)).into();
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "locked_until": locked_until, "locked_by": "another-instance" } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert_eq!(feed.locked_by, Some("another-instance".to_string()));
+    let count = FeedFetch::count(doc! { "feed": &feed_id }).await.unwrap();
+    assert_eq!(count, 0, "Should not record a fetch of a leased feed");
+  });
+}
+
+#[test]
+fn sync_takes_over_expired_leases_and_releases_them() {
+  let feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    // The instance holding the lease crashed before releasing it.
+    let locked_until: Date = (Utc::now() - Duration::minutes(1)).into();
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "locked_until": locked_until, "locked_by": "crashed-instance" } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    feed_mock.assert();
+
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert!(feed.locked_until.is_none());
+    assert!(feed.locked_by.is_none());
+    let count = Entry::count(doc! { "feed": &feed_id }).await.unwrap();
+    assert_eq!(count, 1, "Should have stored the feed entry");
+  });
+}
+
+#[test]
+fn sync_queues_the_notification_of_the_feed_subscriptions() {
+  let feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let subscription = Subscription::new(
+      ObjectId::new(),
+      feed_id,
+      ObjectId::new(),
+      feed.url.clone(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+    let subscription_id = subscription.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    feed_mock.assert();
+
+    let job = Job::find_one(doc! {}, None).await.unwrap().unwrap();
+    assert_eq!(
+      job.kind,
+      JobKind::NotifySubscription {
+        subscription: subscription_id
+      }
+    );
+
+    // Jobs sync feeds and queue their next sync.
+    Feed::schedule_sync(&feed_id).await.unwrap();
+    let count = Job::count(doc! { "kind.type": "sync_feed", "kind.feed": &feed_id })
+      .await
+      .unwrap();
+    assert_eq!(count, 1);
+  });
+}
+
+#[test]
+fn sync_stores_entries_larger_than_a_mongodb_command() {
+  // Entry descriptions are stored raw, as HTML and as text, these entries
+  // take more than the 16MB a MongoDB command can hold.
+  let description = "Rust ".repeat(300_000);
+  let items = (1..=5)
+    .map(|id| {
+      format!(
+        "<item><guid>{}</guid><title>{}</title><description>{}</description></item>",
+        id, id, description
+      )
+    })
+    .collect::<String>();
+  let body = format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0"><channel><title>Feed</title>{}</channel></rss>"#,
+    items
+  );
+  let feed_mock = mock("GET", "/").with_status(200).with_body(body).create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    feed_mock.assert();
+
+    let count = Entry::count(doc! { "feed": &feed_id }).await.unwrap();
+    assert_eq!(count, 5, "Should have stored every entry");
+  });
+}
@@ -0,0 +1,186 @@
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+
+use crate::models::job::{Job, JobKind, JobStatus};
+use crate::settings::get_settings;
+use crate::tests::setup::with_app;
+use crate::utils::database_model::ModelExt;
+use crate::utils::date::Date;
+
+#[test]
+fn enqueue_queues_a_job_once_at_the_earliest_date() {
+  with_app(async move {
+    let feed_id = ObjectId::new();
+    let kind = JobKind::SyncFeed { feed: feed_id };
+    let now = Utc::now();
+
+    Job::enqueue(kind.clone(), now + Duration::hours(1))
+      .await
+      .unwrap();
+    Job::enqueue(kind.clone(), now + Duration::minutes(5))
+      .await
+      .unwrap();
+    Job::enqueue(kind.clone(), now + Duration::hours(2))
+      .await
+      .unwrap();
+
+    let jobs = Job::find(doc! {}, None).await.unwrap();
+    assert_eq!(jobs.len(), 1, "Should queue the job once");
+
+    let job = &jobs[0];
+    assert_eq!(job.kind, kind);
+    assert_eq!(job.status, JobStatus::Queued);
+    assert_eq!(job.attempts, 0);
+    let run_at: Date = (now + Duration::minutes(5)).into();
+    assert_eq!(job.run_at.timestamp_millis(), run_at.timestamp_millis());
+  });
+}
+
+#[test]
+fn claim_runs_ready_jobs_by_priority() {
+  with_app(async move {
+    let now = Utc::now();
+    let sync = JobKind::SyncFeed {
+      feed: ObjectId::new(),
+    };
+    let notify = JobKind::NotifySubscription {
+      subscription: ObjectId::new(),
+    };
+    let later = JobKind::NotifySubscription {
+      subscription: ObjectId::new(),
+    };
+    Job::enqueue(sync.clone(), now - Duration::minutes(5))
+      .await
+      .unwrap();
+    Job::enqueue(notify.clone(), now).await.unwrap();
+    Job::enqueue(later, now + Duration::hours(1)).await.unwrap();
+
+    let job = Job::claim().await.unwrap().unwrap();
+    assert_eq!(
+      job.kind, notify,
+      "Should claim the job with higher priority"
+    );
+    assert_eq!(job.status, JobStatus::Running);
+    assert_eq!(job.attempts, 1);
+    assert!(job.locked_until.is_some());
+
+    let job = Job::claim().await.unwrap().unwrap();
+    assert_eq!(job.kind, sync);
+
+    let job = Job::claim().await.unwrap();
+    assert!(job.is_none(), "Should not claim jobs before their run date");
+  });
+}
+
+#[test]
+fn claim_takes_over_jobs_past_their_visibility_timeout() {
+  with_app(async move {
+    let kind = JobKind::CleanupFeed {
+      feed: ObjectId::new(),
+    };
+    Job::enqueue(kind.clone(), Utc::now()).await.unwrap();
+    let job = Job::claim().await.unwrap().unwrap();
+    assert!(Job::claim().await.unwrap().is_none());
+
+    // The instance running the job crashed.
+    let locked_until: Date = (Utc::now() - Duration::seconds(1)).into();
+    Job::update_one(
+      doc! { "_id": job.id.unwrap() },
+      doc! { "$set": { "locked_until": locked_until } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    let job = Job::claim().await.unwrap().unwrap();
+    assert_eq!(job.kind, kind);
+    assert_eq!(job.attempts, 2);
+
+    job.complete().await.unwrap();
+    let count = Job::count(doc! {}).await.unwrap();
+    assert_eq!(count, 0, "Should remove completed jobs");
+  });
+}
+
+#[test]
+fn complete_and_fail_leave_jobs_claimed_again_alone() {
+  with_app(async move {
+    let kind = JobKind::CleanupFeed {
+      feed: ObjectId::new(),
+    };
+    Job::enqueue(kind, Utc::now()).await.unwrap();
+    let stale = Job::claim().await.unwrap().unwrap();
+
+    // The job took longer than its visibility timeout, another worker claimed
+    // it again.
+    let locked_until: Date = (Utc::now() - Duration::seconds(1)).into();
+    Job::update_one(
+      doc! { "_id": stale.id.unwrap() },
+      doc! { "$set": { "locked_until": locked_until } },
+      None,
+    )
+    .await
+    .unwrap();
+    let job = Job::claim().await.unwrap().unwrap();
+    assert_eq!(job.attempts, 2);
+
+    stale.complete().await.unwrap();
+    stale.fail("Something went wrong").await.unwrap();
+
+    let found = Job::find_by_id(&job.id.unwrap()).await.unwrap().unwrap();
+    assert_eq!(
+      found.status,
+      JobStatus::Running,
+      "Should keep the new claim"
+    );
+    assert_eq!(found.attempts, 2);
+    assert!(found.last_error.is_none());
+
+    job.complete().await.unwrap();
+    let count = Job::count(doc! {}).await.unwrap();
+    assert_eq!(count, 0, "Should remove the job completed by its worker");
+  });
+}
+
+#[test]
+fn fail_retries_jobs_with_a_backoff_until_they_are_dead() {
+  with_app(async move {
+    let settings = &get_settings().jobs;
+    let kind = JobKind::SyncFeed {
+      feed: ObjectId::new(),
+    };
+    Job::enqueue(kind, Utc::now()).await.unwrap();
+
+    let job = Job::claim().await.unwrap().unwrap();
+    job.fail("Something went wrong").await.unwrap();
+
+    let job = Job::find_by_id(&job.id.unwrap()).await.unwrap().unwrap();
+    assert_eq!(job.status, JobStatus::Queued);
+    assert_eq!(job.last_error, Some("Something went wrong".to_string()));
+    assert!(job.locked_until.is_none());
+    let delay = job.run_at.timestamp_millis() - Utc::now().timestamp_millis();
+    assert!(delay > (settings.retry_delay - 5) * 1000);
+    assert!(delay <= settings.retry_delay * 1000);
+
+    // Last attempt.
+    Job::update_one(
+      doc! { "_id": job.id.unwrap() },
+      doc! {
+        "$set": {
+          "attempts": settings.max_attempts - 1,
+          "run_at": Date::from(Utc::now())
+        }
+      },
+      None,
+    )
+    .await
+    .unwrap();
+    let job = Job::claim().await.unwrap().unwrap();
+    job.fail("Something went wrong again").await.unwrap();
+
+    let job = Job::find_by_id(&job.id.unwrap()).await.unwrap().unwrap();
+    assert_eq!(job.status, JobStatus::Dead);
+    assert!(Job::claim().await.unwrap().is_none());
+  });
+}
@@ -1 +1,3 @@
+mod feed;
+mod job;
 mod subscription;
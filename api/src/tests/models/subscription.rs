@@ -1,11 +1,20 @@
 use bson::doc;
 use bson::oid::ObjectId;
 use bson::Document;
+use chrono::{Duration, Utc};
+use mockito::{mock, Matcher};
+use serde_json::json;
 
 use crate::database::get_connection;
+use crate::models::endpoint::Endpoint;
+use crate::models::entry::{Entry, EntryFormat, IdStrategy};
+use crate::models::job::{Job, JobKind};
 use crate::models::subscription::Subscription;
+use crate::models::webhook::{Status, Webhook};
 use crate::tests::setup::with_app;
+use crate::tests::utils::create_feed;
 use crate::utils::database_model::ModelExt;
+use crate::utils::date::now;
 
 #[test]
 fn when_creating_a_subscription_scheduled_at_should_not_be_defined_on_the_database() {
@@ -31,3 +40,404 @@ fn when_creating_a_subscription_scheduled_at_should_not_be_defined_on_the_databa
     assert_eq!(subscription.get("scheduled_at"), None);
   });
 }
+
+#[test]
+fn subscriptions_are_leased_once_until_the_lease_is_released() {
+  with_app(async move {
+    let subscription = Subscription::new(
+      ObjectId::new(),
+      ObjectId::new(),
+      ObjectId::new(),
+      "http://example.com/".to_string(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+    let subscription_id = subscription.id.unwrap();
+
+    let leased = Subscription::acquire_lease(doc! { "_id": &subscription_id })
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(leased.locked_until.is_some());
+    assert!(leased.locked_by.is_some());
+
+    let leased_again = Subscription::acquire_lease(doc! { "_id": &subscription_id })
+      .await
+      .unwrap();
+    assert!(
+      leased_again.is_none(),
+      "Should not lease a leased subscription"
+    );
+
+    Subscription::release_lease(&subscription_id).await.unwrap();
+    let leased = Subscription::acquire_lease(doc! { "_id": &subscription_id })
+      .await
+      .unwrap();
+    assert!(leased.is_some(), "Should lease a released subscription");
+  });
+}
+
+#[test]
+fn notify_sends_updated_entries_to_subscriptions_that_opted_in() {
+  let endpoint_mock = mock("POST", "/endpoint")
+    .match_body(Matcher::PartialJson(json!({
+      "entries": [],
+      "updated": [{ "title": "Updated title", "revision": 1 }],
+    })))
+    .with_status(200)
+    .create();
+
+  with_app(async move {
+    let application_id = ObjectId::new();
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let url = format!("{}/endpoint", mockito::server_url());
+    let endpoint = Endpoint::new(application_id, url, "Test Endpoint");
+    let endpoint = Endpoint::create(endpoint).await.unwrap();
+
+    let entry = Entry {
+      id: None,
+      feed: feed_id,
+      public_id: "ENTRY_ID".to_string(),
+      guid: Some("ENTRY_ID".to_string()),
+      fingerprint: None,
+      url: None,
+      title: Some("Updated title".to_string()),
+      description: None,
+      published_at: None,
+      content: None,
+      authors: vec![],
+      categories: vec![],
+      links: vec![],
+      enclosures: vec![],
+      thumbnails: vec![],
+      language: None,
+      modified_at: None,
+      podcast: None,
+      description_html: None,
+      description_text: None,
+      content_html: None,
+      content_text: None,
+      excerpt: None,
+      full_content: None,
+      full_content_fetched_at: None,
+      content_hash: None,
+      revision: 1,
+      updated_at: None,
+      created_at: now(),
+    };
+    let entry = Entry::create(entry).await.unwrap();
+
+    let mut subscription = Subscription::new(
+      application_id,
+      feed_id,
+      endpoint.id.unwrap(),
+      feed.url.clone(),
+      None,
+    );
+    subscription.notify_updates = true;
+    subscription.last_notified_entry = entry.id;
+    subscription.last_notified_update = Some((Utc::now() - Duration::minutes(1)).into());
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    // The entry changed after the subscription received it.
+    Entry::update_one(
+      doc! { "_id": entry.id.unwrap() },
+      doc! { "$set": { "updated_at": now() } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    subscription.notify().await.unwrap();
+    endpoint_mock.assert();
+
+    let subscription = Subscription::find_by_id(&subscription.id.unwrap())
+      .await
+      .unwrap()
+      .unwrap();
+    assert!(subscription.last_notified_update.is_some());
+    assert_eq!(subscription.last_notified_entry, entry.id);
+  });
+}
+
+#[test]
+fn notify_pages_updated_entries_sharing_the_same_update_date() {
+  // 35 updated entries are sent in two batches of 30 and 5 entries.
+  let endpoint_mock = mock("POST", "/endpoint")
+    .with_status(200)
+    .expect(2)
+    .create();
+
+  with_app(async move {
+    let application_id = ObjectId::new();
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let url = format!("{}/endpoint", mockito::server_url());
+    let endpoint = Endpoint::new(application_id, url, "Test Endpoint");
+    let endpoint = Endpoint::create(endpoint).await.unwrap();
+
+    let template = Entry {
+      id: None,
+      feed: feed_id,
+      public_id: "ENTRY_ID".to_string(),
+      guid: None,
+      fingerprint: None,
+      url: None,
+      title: Some("Updated title".to_string()),
+      description: None,
+      published_at: None,
+      content: None,
+      authors: vec![],
+      categories: vec![],
+      links: vec![],
+      enclosures: vec![],
+      thumbnails: vec![],
+      language: None,
+      modified_at: None,
+      podcast: None,
+      description_html: None,
+      description_text: None,
+      content_html: None,
+      content_text: None,
+      excerpt: None,
+      full_content: None,
+      full_content_fetched_at: None,
+      content_hash: None,
+      revision: 1,
+      updated_at: None,
+      created_at: now(),
+    };
+    let mut entries = vec![];
+    for index in 0..35 {
+      let mut entry = template.clone();
+      entry.public_id = format!("ENTRY_ID_{}", index);
+      entries.push(Entry::create(entry).await.unwrap());
+    }
+
+    let mut subscription = Subscription::new(
+      application_id,
+      feed_id,
+      endpoint.id.unwrap(),
+      feed.url.clone(),
+      None,
+    );
+    subscription.notify_updates = true;
+    subscription.last_notified_entry = entries.last().unwrap().id;
+    subscription.last_notified_update = Some((Utc::now() - Duration::minutes(1)).into());
+    let subscription = Subscription::create(subscription).await.unwrap();
+    let subscription_id = subscription.id.unwrap();
+
+    // Every entry changed at the same date after the subscription received
+    // them.
+    Entry::update_many(
+      doc! { "feed": &feed_id },
+      doc! { "$set": { "updated_at": now() } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    subscription.notify().await.unwrap();
+    let subscription = Subscription::find_by_id(&subscription_id)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(subscription.last_notified_update_entry, entries[29].id);
+
+    subscription.notify().await.unwrap();
+    let subscription = Subscription::find_by_id(&subscription_id)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(
+      subscription.last_notified_update_entry, entries[34].id,
+      "Should send the entries updated at the same date as the first batch"
+    );
+
+    // Every update was sent.
+    subscription.notify().await.unwrap();
+    endpoint_mock.assert();
+  });
+}
+
+#[test]
+fn notify_sends_entries_in_the_subscription_format() {
+  let endpoint_mock = mock("POST", "/endpoint")
+    .match_body(Matcher::PartialJson(json!({
+      "entries": [{
+        "description": "Hello world\nSecond paragraph",
+        "excerpt": "Hello world Second paragraph",
+      }],
+    })))
+    .with_status(200)
+    .create();
+
+  with_app(async move {
+    let application_id = ObjectId::new();
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let url = format!("{}/endpoint", mockito::server_url());
+    let endpoint = Endpoint::new(application_id, url, "Test Endpoint");
+    let endpoint = Endpoint::create(endpoint).await.unwrap();
+
+    let rss = r#"<?xml version="1.0" encoding="UTF-8"?>
+      <rss version="2.0"><channel><title>Feed</title><item>
+        <guid>ENTRY_ID</guid>
+        <description><![CDATA[<p>Hello <b>world</b></p><script>alert(1)</script><p>Second paragraph</p>]]></description>
+      </item></channel></rss>"#;
+    let raw_entry = feed_rs::parser::parse(rss.as_bytes())
+      .unwrap()
+      .entries
+      .remove(0);
+    let entry = Entry::from_raw_entry(feed_id, raw_entry, IdStrategy::Guid);
+    assert_eq!(
+      entry.description_html,
+      Some("<p>Hello <b>world</b></p><p>Second paragraph</p>".to_string())
+    );
+    Entry::create(entry).await.unwrap();
+
+    let mut subscription = Subscription::new(
+      application_id,
+      feed_id,
+      endpoint.id.unwrap(),
+      feed.url.clone(),
+      None,
+    );
+    subscription.format = EntryFormat::Text;
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    subscription.notify().await.unwrap();
+    endpoint_mock.assert();
+  });
+}
+
+#[test]
+fn notify_fails_webhooks_redirected_by_the_endpoint() {
+  let endpoint_mock = mock("POST", "/endpoint")
+    .with_status(301)
+    .with_header("location", "https://localhost/endpoint")
+    .create();
+
+  with_app(async move {
+    let application_id = ObjectId::new();
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let url = format!("{}/endpoint", mockito::server_url());
+    let endpoint = Endpoint::new(application_id, url, "Test Endpoint");
+    let endpoint = Endpoint::create(endpoint).await.unwrap();
+
+    let rss = r#"<?xml version="1.0" encoding="UTF-8"?>
+      <rss version="2.0"><channel><title>Feed</title><item>
+        <guid>ENTRY_ID</guid>
+      </item></channel></rss>"#;
+    let raw_entry = feed_rs::parser::parse(rss.as_bytes())
+      .unwrap()
+      .entries
+      .remove(0);
+    let entry = Entry::from_raw_entry(feed_id, raw_entry, IdStrategy::Guid);
+    Entry::create(entry).await.unwrap();
+
+    let subscription = Subscription::new(
+      application_id,
+      feed_id,
+      endpoint.id.unwrap(),
+      feed.url.clone(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    subscription.notify().await.unwrap();
+    endpoint_mock.assert();
+
+    let webhook = Webhook::find_one(doc! {}, None).await.unwrap().unwrap();
+    assert!(
+      matches!(webhook.status, Status::Failed),
+      "Should not record the redirected webhook as sent"
+    );
+
+    let job = Job::find_one(doc! {}, None).await.unwrap().unwrap();
+    assert!(
+      matches!(job.kind, JobKind::RetryWebhook { webhook: id, .. } if id == webhook.id.unwrap()),
+      "Should queue the webhook to be sent again"
+    );
+  });
+}
+
+#[test]
+fn notify_sends_the_full_content_of_entries_to_subscriptions_that_opted_in() {
+  let page_mock = mock("GET", "/article")
+    .with_status(200)
+    .with_header("content-type", "text/html")
+    .with_body(include_str!("../fixture/article.html"))
+    .expect(1)
+    .create();
+  let endpoint_mock = mock("POST", "/endpoint")
+    .match_body(Matcher::Regex(
+      "Feeds are one of the oldest ways".to_string(),
+    ))
+    .with_status(200)
+    .create();
+
+  with_app(async move {
+    let application_id = ObjectId::new();
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let url = format!("{}/endpoint", mockito::server_url());
+    let endpoint = Endpoint::new(application_id, url, "Test Endpoint");
+    let endpoint = Endpoint::create(endpoint).await.unwrap();
+
+    let rss = format!(
+      r#"<?xml version="1.0" encoding="UTF-8"?>
+      <rss version="2.0"><channel><title>Feed</title><item>
+        <guid>ENTRY_ID</guid>
+        <link>{}/article</link>
+        <description>Why feeds still matter.</description>
+      </item></channel></rss>"#,
+      mockito::server_url()
+    );
+    let raw_entry = feed_rs::parser::parse(rss.as_bytes())
+      .unwrap()
+      .entries
+      .remove(0);
+    let entry = Entry::from_raw_entry(feed_id, raw_entry, IdStrategy::Guid);
+    let entry = Entry::create(entry).await.unwrap();
+
+    let mut subscription = Subscription::new(
+      application_id,
+      feed_id,
+      endpoint.id.unwrap(),
+      feed.url.clone(),
+      None,
+    );
+    subscription.full_content = true;
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    subscription.notify().await.unwrap();
+    endpoint_mock.assert();
+
+    let mut entry = Entry::find_by_id(&entry.id.unwrap())
+      .await
+      .unwrap()
+      .unwrap();
+    let full_content = entry.full_content.clone().unwrap();
+    assert!(full_content.contains("Publishers keep control over their content"));
+    assert!(
+      !full_content.contains("newsletter"),
+      "Should skip the sidebar"
+    );
+    assert!(
+      !full_content.contains("Great post"),
+      "Should skip the comments"
+    );
+
+    // The page is only fetched once.
+    entry.fetch_full_content().await.unwrap();
+    page_mock.assert();
+  });
+}
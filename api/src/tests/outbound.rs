@@ -0,0 +1,38 @@
+use std::net::IpAddr;
+
+use crate::tests::setup::with_app;
+use crate::utils::outbound::{check_url, resolve, Error};
+
+#[test]
+fn resolve_rejects_hosts_resolving_to_disallowed_addresses() {
+  with_app(async move {
+    // Loopback addresses other than the allowlisted 127.0.0.1.
+    let result = resolve("127.0.0.2").await;
+    let address: IpAddr = "127.0.0.2".parse().unwrap();
+    assert!(matches!(result, Err(Error::Address(actual)) if actual == address));
+
+    let result = resolve("169.254.169.254").await;
+    assert!(
+      matches!(result, Err(Error::Address(_))),
+      "Should not resolve to the cloud metadata address"
+    );
+
+    // Allowlisted hosts resolve to any address.
+    let addresses = resolve("127.0.0.1").await.unwrap();
+    assert!(!addresses.is_empty());
+  });
+}
+
+#[test]
+fn check_url_rejects_disallowed_addresses() {
+  with_app(async move {
+    let result = check_url("http://127.0.0.2:8080/feed").await;
+    assert!(matches!(result, Err(Error::Address(_))));
+
+    let result = check_url("http://[::1]:8080/feed").await;
+    assert!(matches!(result, Err(Error::Address(_))));
+
+    let result = check_url("http://127.0.0.1:8080/feed").await;
+    assert!(result.is_ok());
+  });
+}
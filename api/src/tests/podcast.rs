@@ -0,0 +1,59 @@
+use crate::utils::podcast;
+use crate::utils::xml::Element;
+
+fn parse(content: &str) -> (podcast::PodcastFeed, Vec<feed_rs::model::Entry>) {
+  let document = Element::parse(content.as_bytes()).unwrap();
+  let podcast = podcast::parse(&document).unwrap();
+  let entries = feed_rs::parser::parse(content.as_bytes()).unwrap().entries;
+  (podcast, entries)
+}
+
+#[test]
+fn parse_ignores_durations_too_large() {
+  let (podcast, entries) = parse(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Rust Podcast</title>
+    <item><guid>1</guid><itunes:duration>inf:00</itunes:duration></item>
+    <item><guid>2</guid><itunes:duration>99999999999999999999:0</itunes:duration></item>
+    <item><guid>3</guid><itunes:duration>3000000000000000000:00:00</itunes:duration></item>
+    <item><guid>4</guid><itunes:duration>1:02:03</itunes:duration></item>
+  </channel>
+</rss>"#,
+  );
+
+  let durations = entries
+    .iter()
+    .map(|entry| {
+      podcast
+        .get_episode(entry)
+        .and_then(|episode| episode.duration)
+    })
+    .collect::<Vec<Option<i64>>>();
+  assert_eq!(durations, vec![None, None, None, Some(3723)]);
+}
+
+#[test]
+fn parse_removes_categories_repeated_under_other_parents() {
+  let (podcast, _) = parse(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Rust Podcast</title>
+    <itunes:author>Jane Doe</itunes:author>
+    <itunes:category text="Technology">
+      <itunes:category text="Software How-To"/>
+    </itunes:category>
+    <itunes:category text="Education">
+      <itunes:category text="Software How-To"/>
+    </itunes:category>
+  </channel>
+</rss>"#,
+  );
+
+  assert_eq!(
+    podcast.podcast.categories,
+    vec!["Technology", "Software How-To", "Education"]
+  );
+}
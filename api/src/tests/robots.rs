@@ -0,0 +1,70 @@
+use crate::utils::robots::Rules;
+
+const PRODUCT: &str = "therssproject";
+
+#[test]
+fn rules_match_path_prefixes() {
+  let rules = Rules::parse("User-agent: *\nDisallow: /private\n", PRODUCT);
+
+  assert!(!rules.is_allowed("/private"));
+  assert!(!rules.is_allowed("/private/feed.xml"));
+  assert!(!rules.is_allowed("/private.xml"));
+  assert!(rules.is_allowed("/feed.xml"));
+  assert!(rules.is_allowed("/public/private"));
+}
+
+#[test]
+fn rules_match_wildcards_in_any_position() {
+  let rules = Rules::parse(
+    "User-agent: *\nDisallow: /*.php\nDisallow: /blog/*/drafts\nDisallow: /tmp*\n",
+    PRODUCT,
+  );
+
+  assert!(!rules.is_allowed("/index.php"));
+  assert!(!rules.is_allowed("/feeds/rss.php?id=1"));
+  assert!(!rules.is_allowed("/blog/2022/drafts/feed.xml"));
+  assert!(!rules.is_allowed("/tmp/feed.xml"));
+  assert!(rules.is_allowed("/blog/drafts"));
+  assert!(rules.is_allowed("/index.html"));
+}
+
+#[test]
+fn rules_match_the_end_of_the_path() {
+  let rules = Rules::parse(
+    "User-agent: *\nDisallow: /*.xml$\nDisallow: /feed$\n",
+    PRODUCT,
+  );
+
+  assert!(!rules.is_allowed("/feed.xml"));
+  assert!(!rules.is_allowed("/blog/atom.xml"));
+  assert!(!rules.is_allowed("/feed"));
+  assert!(rules.is_allowed("/feed.xml?page=2"));
+  assert!(rules.is_allowed("/feed/"));
+  assert!(rules.is_allowed("/feed.json"));
+}
+
+#[test]
+fn rules_use_the_longest_match_and_allow_wins_ties() {
+  let rules = Rules::parse(
+    "User-agent: *\nDisallow: /blog\nAllow: /blog/feed\nAllow: /shop\nDisallow: /shop\nAllow: /*.xml$\nDisallow: /private/\n",
+    PRODUCT,
+  );
+
+  assert!(!rules.is_allowed("/blog/post"));
+  assert!(rules.is_allowed("/blog/feed.xml"));
+  assert!(rules.is_allowed("/shop"), "Should allow on ties");
+  // The disallow rule is longer than the allow rule.
+  assert!(!rules.is_allowed("/private/feed.xml"));
+}
+
+#[test]
+fn rules_use_the_group_of_our_user_agent() {
+  let content = "User-agent: *\nDisallow: /\n\nUser-agent: Googlebot\nUser-agent: therssproject\nDisallow: /private\n";
+  let rules = Rules::parse(content, PRODUCT);
+
+  assert!(rules.is_allowed("/feed.xml"));
+  assert!(!rules.is_allowed("/private/feed.xml"));
+
+  let rules = Rules::parse(content, "otherbot");
+  assert!(!rules.is_allowed("/feed.xml"));
+}
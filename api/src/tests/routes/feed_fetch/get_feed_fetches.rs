@@ -0,0 +1,106 @@
+use mockito::mock;
+use reqwest::StatusCode;
+
+use crate::models::feed::Feed;
+use crate::models::feed_fetch::{FetchOutcome, PublicFeedFetch};
+use crate::models::subscription::Subscription;
+use crate::tests::setup::with_app;
+use crate::tests::utils::create_feed;
+use crate::tests::utils::create_user;
+use crate::tests::utils::create_user_token;
+use crate::tests::utils::setup_application;
+use crate::utils::database_model::ModelExt;
+
+#[test]
+fn get_feed_fetches_with_valid_authentication_header() {
+  let subscription_url = "https://www.reddit.com/r/rust/.rss";
+
+  let feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(include_str!("../../fixture/reddit_atom.xml"))
+    .expect(2)
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let token = create_user_token(user.clone()).await.unwrap();
+    let (application, _, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+
+    let subscription = Subscription::new(
+      application.id.unwrap(),
+      feed_id,
+      endpoint.id.unwrap(),
+      subscription_url.to_string(),
+      None,
+    );
+    let subscription = Subscription::create(subscription).await.unwrap();
+
+    Feed::sync(feed_id).await.unwrap();
+    Feed::sync(feed_id).await.unwrap();
+    feed_mock.assert();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .get(format!(
+        "http://localhost:8088/applications/{}/subscriptions/{}/fetches",
+        application.id.unwrap(),
+        subscription.id.unwrap()
+      ))
+      .header("Authorization", format!("Bearer {}", token))
+      .send()
+      .await
+      .unwrap();
+
+    // Status code:
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Response pagination headers:
+    let headers = res.headers();
+    assert_eq!(headers.get("X-Pagination-Count").unwrap(), "2");
+
+    // Response body, newest fetch first:
+    let body = res.json::<Vec<PublicFeedFetch>>().await.unwrap();
+    let outcomes = body
+      .iter()
+      .map(|fetch| fetch.outcome)
+      .collect::<Vec<FetchOutcome>>();
+    assert_eq!(
+      outcomes,
+      vec![FetchOutcome::Unchanged, FetchOutcome::Modified]
+    );
+
+    let fetch = &body[1];
+    assert_eq!(fetch.feed, feed_id);
+    assert_eq!(fetch.status, Some(200));
+    assert!(fetch.bytes.unwrap() > 0);
+    assert!(fetch.latency.is_some());
+    assert_eq!(fetch.entries, Some(1));
+    assert_eq!(fetch.inserted, Some(1));
+    assert_eq!(fetch.removed, Some(0));
+  });
+}
+
+#[test]
+fn get_feed_fetches_of_a_subscription_from_another_application() {
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let token = create_user_token(user.clone()).await.unwrap();
+    let (application, _, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .get(format!(
+        "http://localhost:8088/applications/{}/subscriptions/{}/fetches",
+        application.id.unwrap(),
+        bson::oid::ObjectId::new()
+      ))
+      .header("Authorization", format!("Bearer {}", token))
+      .send()
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+  });
+}
@@ -1,5 +1,7 @@
 mod application;
+mod feed_fetch;
 mod public_api;
 mod subscription;
 mod user;
 mod webhook;
+mod websub;
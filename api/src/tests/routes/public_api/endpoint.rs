@@ -0,0 +1,67 @@
+use bson::doc;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use crate::models::endpoint::Endpoint;
+use crate::tests::setup::with_app;
+use crate::tests::utils::create_user;
+use crate::tests::utils::setup_application;
+use crate::utils::database_model::ModelExt;
+
+#[test]
+fn post_endpoints_with_a_private_address() {
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let urls = [
+      "http://169.254.169.254/latest/meta-data",
+      "http://10.0.0.1/webhooks",
+      "http://[::1]/webhooks",
+      "http://[::ffff:127.0.0.1]/webhooks",
+      "http://localhost:22/webhooks",
+      "ftp://example.com/webhooks",
+    ];
+
+    let client = reqwest::Client::new();
+    for url in urls {
+      let body = json!({ "url": url, "title": "Private endpoint" });
+      let res = client
+        .post("http://localhost:8088/v1/endpoints")
+        .header("Authorization", &key)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+      assert_eq!(res.status(), StatusCode::BAD_REQUEST, "URL {}", url);
+      let body = res.json::<Value>().await.unwrap();
+      let message = body["message"].as_str().unwrap();
+      assert!(message.contains("not allowed"), "URL {}", url);
+    }
+
+    // Endpoint created by setup_application.
+    let count = Endpoint::count(doc! {}).await.unwrap();
+    assert_eq!(count, 1, "Should not create the endpoints");
+  });
+}
+
+#[test]
+fn post_endpoints_with_an_allowed_host() {
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let body = json!({ "url": "http://localhost:8080/webhooks", "title": "Local endpoint" });
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/endpoints")
+      .header("Authorization", &key)
+      .json(&body)
+      .send()
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::CREATED);
+  });
+}
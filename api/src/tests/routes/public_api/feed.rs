@@ -3,7 +3,9 @@ use lazy_static::lazy_static;
 use mockito::mock;
 use reqwest;
 use reqwest::StatusCode;
+use serde_json::json;
 
+use crate::models::feed::FeedType;
 use crate::routes::feed::FeedResponse;
 use crate::tests::setup::with_app;
 use crate::tests::utils::create_user;
@@ -94,3 +96,289 @@ fn get_feeds_with_valid_authentication_header() {
     assert_eq!(body.entries.len(), 1);
   });
 }
+
+#[test]
+fn get_feeds_with_a_website_url_without_advertised_feeds() {
+  let website_url = format!("{}/blog", mockito::server_url());
+
+  let request_website_mock = mock("GET", "/blog")
+    .with_status(200)
+    .with_body("<!DOCTYPE html><html><head></head><body>Rust</body></html>")
+    .create();
+
+  // First well known feed path.
+  let request_feed_mock = mock("GET", "/feed")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .get("http://localhost:8088/v1/feeds")
+      .header("Authorization", key)
+      .query(&[("url", &website_url)])
+      .send()
+      .await
+      .unwrap();
+
+    request_website_mock.assert();
+    request_feed_mock.assert();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::OK;
+    assert_eq!(actual, expected);
+
+    // Body:
+    let body = res.json::<FeedResponse>().await.unwrap();
+    assert_eq!(body.url, format!("{}/feed", mockito::server_url()));
+    assert!(body.discovered.is_empty());
+    assert_eq!(body.entries.len(), 1);
+  });
+}
+
+#[test]
+fn get_feeds_with_a_host_rate_limiting_us() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(429)
+    .with_header("retry-after", "120")
+    .expect(1)
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    // The host is rate limited for every test, use a host only used here.
+    let client = reqwest::Client::new();
+    let res = client
+      .get("http://localhost:8088/v1/feeds")
+      .header("Authorization", key)
+      .query(&[("url", "https://rate-limited-preview.example.com/feed.xml")])
+      .send()
+      .await
+      .unwrap();
+
+    request_feed_mock.assert();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::TOO_MANY_REQUESTS;
+    assert_eq!(actual, expected);
+
+    // Retry-After header:
+    let retry_after = res
+      .headers()
+      .get("retry-after")
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| value.parse::<i64>().ok())
+      .unwrap();
+    assert!(retry_after > 110 && retry_after <= 120);
+
+    // Body:
+    let body = res.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["code"], json!(40024));
+  });
+}
+
+#[test]
+fn get_feeds_returns_the_entries_metadata() {
+  let feed_url = format!("{}/podcast.xml", mockito::server_url());
+
+  let request_feed_mock = mock("GET", "/podcast.xml")
+    .with_status(200)
+    .with_body(include_str!("../../fixture/podcast_rss.xml"))
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .get("http://localhost:8088/v1/feeds")
+      .header("Authorization", key)
+      .query(&[("url", &feed_url)])
+      .send()
+      .await
+      .unwrap();
+
+    request_feed_mock.assert();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.json::<FeedResponse>().await.unwrap();
+    let entry = body.entries.first().unwrap();
+    assert_eq!(
+      entry.content,
+      Some("<p>The full episode notes.</p>".to_string())
+    );
+    assert_eq!(entry.categories, vec!["Rust", "Programming"]);
+    assert_eq!(entry.authors.len(), 1);
+    assert!(entry.published_at.is_some());
+
+    let enclosure = entry.enclosures.first().unwrap();
+    assert_eq!(enclosure.url, "https://podcast.example.com/episodes/1.mp3");
+    assert_eq!(enclosure.content_type, Some("audio/mpeg".to_string()));
+
+    let thumbnail = entry.thumbnails.first().unwrap();
+    assert_eq!(thumbnail.url, "https://podcast.example.com/episodes/1.jpg");
+    assert_eq!(thumbnail.width, Some(640));
+
+    let podcast = body.podcast.unwrap();
+    assert_eq!(
+      podcast.image,
+      Some("https://podcast.example.com/cover.jpg".to_string())
+    );
+    assert_eq!(podcast.podcast_type, Some("serial".to_string()));
+
+    let episode = entry.podcast.as_ref().unwrap();
+    assert_eq!(episode.duration, Some(3723));
+    assert_eq!(episode.episode, Some(1));
+    let transcript = episode.transcripts.first().unwrap();
+    assert_eq!(transcript.content_type, Some("text/vtt".to_string()));
+    assert_eq!(transcript.rel, Some("captions".to_string()));
+
+    let episode = body.entries[1].podcast.as_ref().unwrap();
+    assert_eq!(
+      episode.image,
+      Some("https://podcast.example.com/episodes/0.jpg".to_string())
+    );
+  });
+}
+
+#[test]
+fn preview_page_feeds_returns_the_entries_found_by_the_selectors() {
+  let page_url = format!("{}/news", mockito::server_url());
+
+  let request_page_mock = mock("GET", "/news")
+    .with_status(200)
+    .with_header("content-type", "text/html")
+    .with_body(include_str!("../../fixture/blog.html"))
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/feeds/preview")
+      .header("Authorization", key)
+      .json(&json!({
+        "url": &page_url,
+        "selectors": {
+          "item": ".news-item",
+          "title": "h2",
+          "date": "time, .date",
+          "summary": ".summary",
+        }
+      }))
+      .send()
+      .await
+      .unwrap();
+
+    request_page_mock.assert();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.json::<FeedResponse>().await.unwrap();
+    assert!(matches!(body.feed_type, FeedType::Html));
+    assert_eq!(body.title, Some("Company News".to_string()));
+    assert_eq!(body.entries.len(), 2, "Should skip empty items");
+
+    let entry = body.entries.first().unwrap();
+    assert_eq!(entry.title, Some("Second release".to_string()));
+    assert_eq!(
+      entry.url,
+      Some(format!("{}/news/second-release", mockito::server_url()))
+    );
+    assert_eq!(
+      entry.description,
+      Some("<p>We shipped <b>more</b> features.</p>".to_string())
+    );
+    assert!(entry.published_at.is_some());
+    assert!(body.entries[1].published_at.is_some());
+  });
+}
+
+#[test]
+fn preview_page_feeds_with_invalid_selectors() {
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/feeds/preview")
+      .header("Authorization", key)
+      .json(&json!({
+        "url": "https://example.com/news",
+        "selectors": { "item": "li[", "title": "h2" }
+      }))
+      .send()
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  });
+}
+
+#[test]
+fn preview_json_api_feeds_returns_the_entries_found_by_the_mapping() {
+  let api_url = format!("{}/releases", mockito::server_url());
+
+  let request_api_mock = mock("GET", "/releases")
+    .with_status(200)
+    .with_header("content-type", "application/json")
+    .with_body(include_str!("../../fixture/releases.json"))
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, _) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/feeds/preview")
+      .header("Authorization", key)
+      .json(&json!({
+        "url": &api_url,
+        "mapping": {
+          "items": "/data/releases",
+          "id": "/id",
+          "title": "/name",
+          "url": "/html_url",
+          "date": "/published_at",
+          "summary": "/body",
+        }
+      }))
+      .send()
+      .await
+      .unwrap();
+
+    request_api_mock.assert();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.json::<FeedResponse>().await.unwrap();
+    assert!(matches!(body.feed_type, FeedType::JsonApi));
+    assert_eq!(body.entries.len(), 2, "Should skip items without values");
+
+    let entry = body.entries.first().unwrap();
+    assert_eq!(entry.title, Some("v1.1.0".to_string()));
+    assert_eq!(
+      entry.url,
+      Some(format!("{}/releases/v1.1.0", mockito::server_url()))
+    );
+    assert_eq!(entry.description, Some("Second release.".to_string()));
+    assert!(entry.published_at.is_some());
+    assert!(
+      body.entries[1].published_at.is_some(),
+      "Should parse UNIX timestamps"
+    );
+  });
+}
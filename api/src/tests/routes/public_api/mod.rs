@@ -1,2 +1,3 @@
+pub mod endpoint;
 pub mod feed;
 pub mod subscription;
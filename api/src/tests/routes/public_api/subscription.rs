@@ -9,10 +9,13 @@ use serde_json::json;
 use crate::models::feed::Feed;
 use crate::models::subscription::PublicSubscription;
 use crate::models::subscription::Subscription;
+use crate::routes::subscription::ImportResponse;
 use crate::tests::setup::with_app;
+use crate::tests::utils::create_feed;
 use crate::tests::utils::create_user;
 use crate::tests::utils::setup_application;
 use crate::utils::database_model::ModelExt;
+use crate::utils::opml::{self, Outline};
 
 lazy_static! {
   static ref FIXTURE: &'static str = include_str!("../../fixture/reddit_atom.xml");
@@ -130,3 +133,451 @@ fn post_subscriptions_with_valid_authentication_header() {
     assert_eq!(count, 1, "Should have create one subscription");
   });
 }
+
+#[test]
+fn post_subscriptions_with_a_feed_url_that_responds_with_an_error_status() {
+  let subscription_url = "https://www.reddit.com/r/rust/.rss";
+
+  let request_feed_mock = mock("GET", "/").with_status(404).create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let body = json!({
+      "url": subscription_url,
+      "endpoint": endpoint.id.unwrap().clone().to_string(),
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/subscriptions")
+      .header("Authorization", key)
+      .json(&body)
+      .send()
+      .await
+      .unwrap();
+
+    request_feed_mock.assert();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::BAD_REQUEST;
+    assert_eq!(actual, expected);
+
+    // Body:
+    let body = res.json::<serde_json::Value>().await.unwrap();
+    assert_json_eq!(
+      body,
+      json!({
+        "code": 40015,
+        "message": "Feed server responded with a 404 status code"
+      })
+    );
+
+    // Feed from database:
+    let count = Feed::count(doc! {}).await.unwrap();
+    assert_eq!(count, 0, "Should not create a feed");
+  });
+}
+
+#[test]
+fn post_subscriptions_with_a_website_url_without_feeds() {
+  let subscription_url = "https://www.reddit.com/r/rust";
+
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_header("content-type", "text/html; charset=utf-8")
+    .with_body("<!DOCTYPE html><html><head></head><body>Rust</body></html>")
+    // The website and the well known feed paths.
+    .expect(8)
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let body = json!({
+      "url": subscription_url,
+      "endpoint": endpoint.id.unwrap().clone().to_string(),
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/subscriptions")
+      .header("Authorization", key)
+      .json(&body)
+      .send()
+      .await
+      .unwrap();
+
+    request_feed_mock.assert();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::BAD_REQUEST;
+    assert_eq!(actual, expected);
+
+    // Body:
+    let body = res.json::<serde_json::Value>().await.unwrap();
+    assert_json_eq!(
+      body,
+      json!({
+        "code": 40020,
+        "message": "Website does not advertise any valid RSS, Atom or JSON feed"
+      })
+    );
+
+    // Subscription from database:
+    let count = Subscription::count(doc! {}).await.unwrap();
+    assert_eq!(count, 0, "Should not create a subscription");
+  });
+}
+
+#[test]
+fn post_subscriptions_with_a_website_url_advertising_a_feed() {
+  let website_url = format!("{}/blog", mockito::server_url());
+  let feed_url = format!("{}/blog/feed.xml", mockito::server_url());
+
+  let request_website_mock = mock("GET", "/blog")
+    .with_status(200)
+    .with_header("content-type", "text/html")
+    .with_body(
+      r#"<!DOCTYPE html>
+<html>
+  <head>
+    <link rel="stylesheet" href="/style.css">
+    <link rel="alternate" type="application/atom+xml" title="Blog" href="/blog/feed.xml">
+  </head>
+  <body>Rust</body>
+</html>"#,
+    )
+    .create();
+
+  let request_feed_mock = mock("GET", "/blog/feed.xml")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let body = json!({
+      "url": &website_url,
+      "endpoint": endpoint.id.unwrap().clone().to_string(),
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/subscriptions")
+      .header("Authorization", key)
+      .json(&body)
+      .send()
+      .await
+      .unwrap();
+
+    request_website_mock.assert();
+    request_feed_mock.assert();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::CREATED;
+    assert_eq!(actual, expected);
+
+    // Body:
+    let body = res.json::<PublicSubscription>().await.unwrap();
+    assert_eq!(body.url, website_url);
+
+    // Feed from database:
+    let feed = Feed::find_one(doc! {}, None).await.unwrap().unwrap();
+    assert_eq!(feed.url, feed_url, "Should create the discovered feed");
+  });
+}
+
+#[test]
+fn post_subscriptions_with_a_website_url_with_a_feed_in_a_well_known_path() {
+  let website_url = format!("{}/blog", mockito::server_url());
+  let feed_url = format!("{}/feed.json", mockito::server_url());
+
+  let request_website_mock = mock("GET", "/blog")
+    .with_status(200)
+    .with_header("content-type", "text/html")
+    .with_body("<!DOCTYPE html><html><head></head><body>Rust</body></html>")
+    .create();
+
+  // The feed is in the last well known path checked.
+  let request_feed_mock = mock("GET", "/feed.json")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let body = json!({
+      "url": &website_url,
+      "endpoint": endpoint.id.unwrap().clone().to_string(),
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/subscriptions")
+      .header("Authorization", key)
+      .json(&body)
+      .send()
+      .await
+      .unwrap();
+
+    request_website_mock.assert();
+    request_feed_mock.assert();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::CREATED;
+    assert_eq!(actual, expected);
+
+    // Feed from database:
+    let feed = Feed::find_one(doc! {}, None).await.unwrap().unwrap();
+    assert_eq!(feed.url, feed_url, "Should create the well known feed");
+  });
+}
+
+#[test]
+fn post_subscriptions_with_website_urls_of_the_same_host_at_the_same_time() {
+  let website_mocks = ["first", "second"]
+    .iter()
+    .map(|name| {
+      mock("GET", format!("/{}", name).as_str())
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(format!(
+          r#"<!DOCTYPE html>
+<html>
+  <head>
+    <link rel="alternate" type="application/atom+xml" href="/{}/feed.xml">
+  </head>
+  <body>Rust</body>
+</html>"#,
+          name
+        ))
+        .create()
+    })
+    .collect::<Vec<_>>();
+  let feed_mocks = ["first", "second"]
+    .iter()
+    .map(|name| {
+      mock("GET", format!("/{}/feed.xml", name).as_str())
+        .with_status(200)
+        .with_body(FIXTURE.clone())
+        .create()
+    })
+    .collect::<Vec<_>>();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    // The test settings allow 2 concurrent requests per host, both websites
+    // are resolved at the same time and request their feed from the same
+    // host.
+    let client = reqwest::Client::new();
+    let subscribe = |name: &str| {
+      let body = json!({
+        "url": format!("{}/{}", mockito::server_url(), name),
+        "endpoint": endpoint.id.unwrap().to_string(),
+      });
+      client
+        .post("http://localhost:8088/v1/subscriptions")
+        .header("Authorization", key.clone())
+        .timeout(std::time::Duration::from_secs(10))
+        .json(&body)
+        .send()
+    };
+    let (first, second) = tokio::join!(subscribe("first"), subscribe("second"));
+
+    assert_eq!(first.unwrap().status(), StatusCode::CREATED);
+    assert_eq!(second.unwrap().status(), StatusCode::CREATED);
+    for mock in website_mocks.iter().chain(feed_mocks.iter()) {
+      mock.assert();
+    }
+
+    let count = Feed::count(doc! {}).await.unwrap();
+    assert_eq!(count, 2, "Should create both discovered feeds");
+  });
+}
+
+#[test]
+fn post_subscriptions_with_an_equivalent_url_reuses_the_feed() {
+  let feed_url = format!("{}/feed.xml", mockito::server_url());
+
+  let request_feed_mock = mock("GET", "/feed.xml")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .expect(1)
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+    let endpoint_id = endpoint.id.unwrap().to_string();
+
+    let urls = [
+      format!("{}?utm_source=newsletter", &feed_url),
+      format!("{}/#latest", &feed_url),
+    ];
+
+    let client = reqwest::Client::new();
+    for url in urls {
+      let body = json!({
+        "url": url,
+        "endpoint": &endpoint_id,
+      });
+
+      let res = client
+        .post("http://localhost:8088/v1/subscriptions")
+        .header("Authorization", &key)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+      assert_eq!(res.status(), StatusCode::CREATED);
+
+      let body = res.json::<PublicSubscription>().await.unwrap();
+      assert_eq!(body.url, feed_url, "Should store the normalized URL");
+    }
+
+    request_feed_mock.assert();
+
+    // Feeds from database:
+    let count = Feed::count(doc! {}).await.unwrap();
+    assert_eq!(count, 1, "Should create a single feed");
+  });
+}
+
+#[test]
+fn import_subscriptions_from_an_opml_document() {
+  let rust_mock = mock("GET", "/rust.xml")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+  let missing_mock = mock("GET", "/missing.xml").with_status(404).create();
+
+  let opml = format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+    <opml version="2.0">
+      <head><title>Exported from another reader</title></head>
+      <body>
+        <outline text="Programming">
+          <outline text="Rust" type="rss" xmlUrl="{server}/rust.xml" category="/Weekly"/>
+        </outline>
+        <outline text="Missing" type="rss" xmlUrl="{server}/missing.xml"/>
+      </body>
+    </opml>"#,
+    server = mockito::server_url()
+  );
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+    let endpoint_id = endpoint.id.unwrap().to_hex();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/subscriptions/import")
+      .header("Authorization", key)
+      .query(&[("endpoint", &endpoint_id)])
+      .body(opml)
+      .send()
+      .await
+      .unwrap();
+
+    rust_mock.assert();
+    missing_mock.assert();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = res.json::<ImportResponse>().await.unwrap();
+    assert_eq!(body.created, 1);
+    assert_eq!(body.failed, 1);
+
+    let subscription = body.feeds[0].subscription.as_ref().unwrap();
+    assert_eq!(subscription.tags, vec!["Programming", "Weekly"]);
+
+    let error = body.feeds[1].error.as_ref().unwrap();
+    assert_eq!(error.code, 40015);
+    assert!(body.feeds[1].subscription.is_none());
+
+    let count = Subscription::count(doc! {}).await.unwrap();
+    assert_eq!(count, 1, "Should only create the valid feed subscription");
+  });
+}
+
+#[test]
+fn import_subscriptions_with_an_invalid_opml_document() {
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+    let endpoint_id = endpoint.id.unwrap().to_hex();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/subscriptions/import")
+      .header("Authorization", key)
+      .query(&[("endpoint", &endpoint_id)])
+      .body("<rss><channel></channel></rss>")
+      .send()
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  });
+}
+
+#[test]
+fn export_subscriptions_as_an_opml_document() {
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (application, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+    let feed = create_feed().await.unwrap();
+
+    let mut subscription = Subscription::new(
+      application.id.unwrap(),
+      feed.id.unwrap(),
+      endpoint.id.unwrap(),
+      feed.url.clone(),
+      None,
+    );
+    subscription.tags = vec!["Programming".to_string(), "Rust".to_string()];
+    Subscription::create(subscription).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let res = client
+      .get("http://localhost:8088/v1/subscriptions/export")
+      .header("Authorization", key)
+      .send()
+      .await
+      .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+      res.headers().get("content-type").unwrap(),
+      "text/x-opml; charset=utf-8"
+    );
+
+    let body = res.text().await.unwrap();
+    let outlines = opml::parse(body.as_bytes()).unwrap();
+    assert_eq!(
+      outlines,
+      vec![Outline {
+        url: "https://www.reddit.com/r/rust/.rss".to_string(),
+        title: Some("The Rust Programming Language".to_string()),
+        tags: vec!["Programming".to_string(), "Rust".to_string()],
+      }]
+    );
+  });
+}
@@ -0,0 +1,405 @@
+use bson::doc;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use mockito::{mock, Matcher};
+use reqwest::StatusCode;
+use serde_json::json;
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::time::sleep;
+use wither::bson::oid::ObjectId;
+
+use crate::models::entry::Entry;
+use crate::models::feed::Feed;
+use crate::models::job::{Job, JobKind};
+use crate::settings::get_settings;
+use crate::tests::setup::with_app;
+use crate::tests::utils::{create_feed, create_user, setup_application};
+use crate::utils::database_model::ModelExt;
+use crate::utils::date::{now, Date};
+
+lazy_static! {
+  static ref FIXTURE: &'static str = include_str!("../fixture/reddit_atom.xml");
+}
+
+const TOPIC: &str = "https://www.reddit.com/r/rust/.rss";
+const SECRET: &str = "SECRET";
+
+fn create_hub_feed() -> String {
+  format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>urn:websub</id>
+  <title>WebSub</title>
+  <updated>2022-10-01T00:00:00Z</updated>
+  <link rel="hub" href="{}/hub"/>
+  <link rel="self" href="{}"/>
+  <entry>
+    <id>urn:websub:1</id>
+    <title>First</title>
+    <link href="https://example.com/1"/>
+    <updated>2022-10-01T00:00:00Z</updated>
+  </entry>
+</feed>"#,
+    mockito::server_url(),
+    TOPIC
+  )
+}
+
+/// Set a WebSub subscription requested to the hub and not verified yet.
+async fn set_websub(feed_id: &ObjectId) {
+  Feed::update_one(
+    doc! { "_id": feed_id },
+    doc! {
+      "$set": {
+        "websub": {
+          "hub": format!("{}/hub", mockito::server_url()),
+          "topic": TOPIC,
+          "secret": SECRET,
+          "requested_at": now(),
+          "subscribed_at": null,
+          "expires_at": null
+        }
+      }
+    },
+    None,
+  )
+  .await
+  .unwrap();
+}
+
+#[test]
+fn post_subscriptions_subscribes_to_the_feed_hub() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(create_hub_feed())
+    .create();
+
+  let hub_mock = mock("POST", "/hub")
+    .match_body(Matcher::AllOf(vec![
+      Matcher::UrlEncoded("hub.mode".into(), "subscribe".into()),
+      Matcher::UrlEncoded("hub.topic".into(), TOPIC.into()),
+    ]))
+    .with_status(202)
+    .create();
+
+  with_app(async move {
+    let user = create_user("nicolas@test.com").await.unwrap();
+    let (_, key, endpoint) = setup_application(&user.id.unwrap()).await.unwrap();
+
+    let body = json!({
+      "url": TOPIC,
+      "endpoint": endpoint.id.unwrap().to_string(),
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post("http://localhost:8088/v1/subscriptions")
+      .header("Authorization", key)
+      .json(&body)
+      .send()
+      .await
+      .unwrap();
+
+    request_feed_mock.assert();
+    hub_mock.assert();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::CREATED;
+    assert_eq!(actual, expected);
+
+    // Feed from database:
+    let feed = Feed::find_one(doc! {}, None).await.unwrap().unwrap();
+    let websub = feed.websub.unwrap();
+    assert_eq!(websub.hub, format!("{}/hub", mockito::server_url()));
+    assert_eq!(websub.topic, TOPIC);
+    assert!(websub.requested_at.is_some());
+    assert!(websub.subscribed_at.is_none());
+  });
+}
+
+#[test]
+fn get_websub_verifies_the_subscription_intent() {
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    set_websub(&feed_id).await;
+
+    let client = reqwest::Client::new();
+    let res = client
+      .get(format!("http://localhost:8088/websub/{}", feed_id))
+      .query(&[
+        ("hub.mode", "subscribe"),
+        ("hub.topic", TOPIC),
+        ("hub.challenge", "CHALLENGE"),
+        ("hub.lease_seconds", "3600"),
+      ])
+      .send()
+      .await
+      .unwrap();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::OK;
+    assert_eq!(actual, expected);
+
+    // Body:
+    let body = res.text().await.unwrap();
+    assert_eq!(body, "CHALLENGE");
+
+    // Feed from database:
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    let websub = feed.websub.unwrap();
+    assert!(websub.subscribed_at.is_some());
+    assert!(websub.is_active());
+  });
+}
+
+#[test]
+fn get_websub_bounds_the_lease_seconds() {
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    let max_lease_seconds = get_settings().websub.lease_seconds;
+
+    for (lease_seconds, expected) in [
+      ("9223372036854775807", max_lease_seconds),
+      ("0", 1),
+      ("-3600", 1),
+    ] {
+      set_websub(&feed_id).await;
+
+      let client = reqwest::Client::new();
+      let res = client
+        .get(format!("http://localhost:8088/websub/{}", feed_id))
+        .query(&[
+          ("hub.mode", "subscribe"),
+          ("hub.topic", TOPIC),
+          ("hub.challenge", "CHALLENGE"),
+          ("hub.lease_seconds", lease_seconds),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+      // Status code:
+      let status_code = res.status();
+      let actual = status_code;
+      let expected_status = StatusCode::OK;
+      assert_eq!(actual, expected_status);
+
+      // Feed from database:
+      let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+      let websub = feed.websub.unwrap();
+      let subscribed_at = websub.subscribed_at.unwrap().to_chrono();
+      let expires_at = websub.expires_at.unwrap().to_chrono();
+      assert_eq!(
+        (expires_at - subscribed_at).num_seconds(),
+        expected,
+        "Should bound the lease of {} seconds",
+        lease_seconds
+      );
+    }
+  });
+}
+
+#[test]
+fn get_websub_without_a_pending_request() {
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    set_websub(&feed_id).await;
+
+    let expired_request: Date = (Utc::now() - chrono::Duration::hours(2)).into();
+    for requested_at in [None, Some(expired_request)] {
+      Feed::update_one(
+        doc! { "_id": &feed_id },
+        doc! { "$set": { "websub.requested_at": requested_at } },
+        None,
+      )
+      .await
+      .unwrap();
+
+      let client = reqwest::Client::new();
+      let res = client
+        .get(format!("http://localhost:8088/websub/{}", feed_id))
+        .query(&[
+          ("hub.mode", "subscribe"),
+          ("hub.topic", TOPIC),
+          ("hub.challenge", "CHALLENGE"),
+          ("hub.lease_seconds", "3600"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+      // Status code:
+      let status_code = res.status();
+      let actual = status_code;
+      let expected = StatusCode::NOT_FOUND;
+      assert_eq!(actual, expected);
+
+      // Feed from database:
+      let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+      let websub = feed.websub.unwrap();
+      assert!(websub.subscribed_at.is_none());
+      assert!(!websub.is_active());
+    }
+  });
+}
+
+#[test]
+fn get_websub_with_an_unknown_topic() {
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    set_websub(&feed_id).await;
+
+    let client = reqwest::Client::new();
+    let res = client
+      .get(format!("http://localhost:8088/websub/{}", feed_id))
+      .query(&[
+        ("hub.mode", "subscribe"),
+        ("hub.topic", "https://example.com/other.xml"),
+        ("hub.challenge", "CHALLENGE"),
+      ])
+      .send()
+      .await
+      .unwrap();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::NOT_FOUND;
+    assert_eq!(actual, expected);
+
+    // Feed from database:
+    let feed = Feed::find_by_id(&feed_id).await.unwrap().unwrap();
+    assert!(feed.websub.unwrap().subscribed_at.is_none());
+  });
+}
+
+#[test]
+fn post_websub_with_a_valid_signature_queues_a_feed_sync() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    set_websub(&feed_id).await;
+    // The feed was synced recently, the notification makes it due again.
+    let next_sync_at: Date = (Utc::now() + chrono::Duration::hours(1)).into();
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "next_sync_at": next_sync_at } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    let res = post_notification(&feed_id).await;
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::ACCEPTED;
+    assert_eq!(actual, expected);
+
+    // Queued job:
+    let jobs = Job::find(doc! {}, None).await.unwrap();
+    assert_eq!(jobs.len(), 1, "Should queue the feed sync");
+    assert_eq!(jobs[0].kind, JobKind::SyncFeed { feed: feed_id });
+    assert!(jobs[0].run_at.to_chrono() <= Utc::now());
+
+    // The worker running the job syncs the feed.
+    Feed::sync_due(feed_id).await.unwrap();
+
+    let count = Entry::count(doc! { "feed": &feed_id }).await.unwrap();
+    request_feed_mock.assert();
+    assert_eq!(count, 1, "Should have synced the feed");
+  });
+}
+
+#[test]
+fn post_websub_of_a_dead_feed_does_not_queue_a_sync() {
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    set_websub(&feed_id).await;
+    Feed::update_one(
+      doc! { "_id": &feed_id },
+      doc! { "$set": { "status": "dead" } },
+      None,
+    )
+    .await
+    .unwrap();
+
+    let res = post_notification(&feed_id).await;
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::ACCEPTED;
+    assert_eq!(actual, expected);
+
+    let count = Job::count(doc! {}).await.unwrap();
+    assert_eq!(count, 0, "Should not queue a sync of a dead feed");
+  });
+}
+
+#[test]
+fn post_websub_with_an_invalid_signature() {
+  let request_feed_mock = mock("GET", "/")
+    .with_status(200)
+    .with_body(FIXTURE.clone())
+    .expect(0)
+    .create();
+
+  with_app(async move {
+    let feed = create_feed().await.unwrap();
+    let feed_id = feed.id.unwrap();
+    set_websub(&feed_id).await;
+
+    let client = reqwest::Client::new();
+    let res = client
+      .post(format!("http://localhost:8088/websub/{}", feed_id))
+      .header("X-Hub-Signature", "sha256=0000")
+      .body(FIXTURE.to_string())
+      .send()
+      .await
+      .unwrap();
+
+    // Status code:
+    let status_code = res.status();
+    let actual = status_code;
+    let expected = StatusCode::ACCEPTED;
+    assert_eq!(actual, expected);
+
+    sleep(Duration::from_millis(500)).await;
+    request_feed_mock.assert();
+    let count = Job::count(doc! {}).await.unwrap();
+    assert_eq!(count, 0, "Should not queue a sync");
+  });
+}
+
+async fn post_notification(feed_id: &ObjectId) -> reqwest::Response {
+  let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+  mac.update(FIXTURE.as_bytes());
+  let signature = hex::encode(mac.finalize().into_bytes());
+
+  let client = reqwest::Client::new();
+  client
+    .post(format!("http://localhost:8088/websub/{}", feed_id))
+    .header("X-Hub-Signature", format!("sha256={}", signature))
+    .body(FIXTURE.to_string())
+    .send()
+    .await
+    .unwrap()
+}
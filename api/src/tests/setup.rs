@@ -12,6 +12,8 @@ use crate::models::application::Application;
 use crate::models::endpoint::Endpoint;
 use crate::models::entry::Entry;
 use crate::models::feed::Feed;
+use crate::models::feed_fetch::FeedFetch;
+use crate::models::job::Job;
 use crate::models::key::Key;
 use crate::models::subscription::Subscription;
 use crate::models::user::User;
@@ -84,6 +86,8 @@ async fn cleanup_database() {
   Endpoint::delete_many(doc! {}).await.unwrap();
   Entry::delete_many(doc! {}).await.unwrap();
   Feed::delete_many(doc! {}).await.unwrap();
+  FeedFetch::delete_many(doc! {}).await.unwrap();
+  Job::delete_many(doc! {}).await.unwrap();
   Key::delete_many(doc! {}).await.unwrap();
   Subscription::delete_many(doc! {}).await.unwrap();
   User::delete_many(doc! {}).await.unwrap();
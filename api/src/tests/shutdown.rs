@@ -0,0 +1,55 @@
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::Utc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::models::job::{Job, JobKind, JobStatus};
+use crate::schedulers;
+use crate::tests::setup::with_app;
+use crate::utils::database_model::ModelExt;
+use crate::utils::shutdown;
+
+// The shutdown can not be undone, no other test depends on it. Tests run
+// sequentially and share the same process.
+#[test]
+fn shutdown_waits_for_tracked_tasks_and_stops_claiming_jobs() {
+  with_app(async move {
+    let kind = JobKind::CleanupFeed {
+      feed: ObjectId::new(),
+    };
+    Job::enqueue(kind, Utc::now()).await.unwrap();
+
+    let is_done = Arc::new(AtomicBool::new(false));
+    let task_is_done = is_done.clone();
+    shutdown::spawn(async move {
+      sleep(Duration::from_millis(500)).await;
+      task_is_done.store(true, Ordering::SeqCst);
+    });
+
+    let start = Instant::now();
+    shutdown::trigger();
+    assert!(shutdown::is_shutting_down());
+
+    // Workers started during the shutdown do not claim jobs.
+    schedulers::jobs::start();
+
+    shutdown::wait_for_tasks().await;
+    assert!(
+      is_done.load(Ordering::SeqCst),
+      "Should wait for the tracked task"
+    );
+    assert!(start.elapsed() >= Duration::from_millis(500));
+
+    let jobs = Job::find(doc! {}, None).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(
+      jobs[0].status,
+      JobStatus::Queued,
+      "Should not claim the job"
+    );
+    assert_eq!(jobs[0].attempts, 0);
+  });
+}
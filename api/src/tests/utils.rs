@@ -3,13 +3,15 @@ use wither::bson::oid::ObjectId;
 use crate::errors::Error;
 use crate::models::application::Application;
 use crate::models::endpoint::Endpoint;
-use crate::models::feed::{Feed, FeedType};
+use crate::models::entry::IdStrategy;
+use crate::models::feed::{Feed, FeedStatus, FeedType};
 use crate::models::key::Key;
 use crate::models::user::hash_password;
 use crate::models::user::User;
 use crate::settings::get_settings;
 use crate::utils::database_model::ModelExt;
 use crate::utils::date;
+use crate::utils::sync_schedule::FeedHints;
 use crate::utils::token;
 
 pub async fn create_user<T: AsRef<str>>(email: T) -> Result<User, Error> {
@@ -65,8 +67,26 @@ pub async fn create_feed() -> Result<Feed, Error> {
     public_id: "PUBLIC_ID_FOO".to_string(),
     feed_type: FeedType::RSS2,
     url: "https://www.reddit.com/r/rust/.rss".to_string(),
+    aliases: vec![],
     title: Some("The Rust Programming Language".to_string()),
     description: Some("The official subreddit for the Rust programming language".to_string()),
+    selectors: None,
+    mapping: None,
+    podcast: None,
+    etag: None,
+    last_modified: None,
+    content_hash: None,
+    hints: FeedHints::default(),
+    next_sync_at: None,
+    websub: None,
+    retention: None,
+    id_strategy: IdStrategy::Guid,
+    status: FeedStatus::Healthy,
+    consecutive_failures: 0,
+    last_error: None,
+    last_error_at: None,
+    locked_until: None,
+    locked_by: None,
     synced_at: now,
     updated_at: now,
     created_at: now,
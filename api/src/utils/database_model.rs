@@ -1,6 +1,7 @@
 use async_trait::async_trait;
+use chrono::{Duration, Utc};
 use futures::stream::TryStreamExt;
-use serde::{de::DeserializeOwned, ser::Serialize};
+use serde::{de::DeserializeOwned, ser::Serialize, Deserialize};
 use validator::Validate;
 use wither::bson::doc;
 use wither::bson::from_bson;
@@ -23,6 +24,10 @@ use wither::ModelCursor;
 use crate::database;
 use crate::errors::BadRequest;
 use crate::errors::Error;
+use crate::errors::WriteError;
+use crate::settings::get_settings;
+use crate::utils::date::Date;
+use crate::utils::lease;
 
 // This is the Model trait. All models that have a MongoDB collection should
 // implement this and therefore inherit theses methods.
@@ -191,6 +196,49 @@ pub trait ModelExt {
       .map_err(Error::Mongo)
   }
 
+  /// Run the update statements with ordered update commands. Each statement
+  /// is a document with the `q`, `u` and optional `upsert` fields, statements
+  /// are applied in order and the commands stop at the first failing
+  /// statement. Statements are split in batches that fit in a MongoDB command.
+  async fn bulk_update(updates: Vec<Document>) -> Result<BulkUpdateResult, Error> {
+    let connection = database::get_connection();
+    let mut result = BulkUpdateResult::default();
+    let mut offset = 0;
+
+    for batch in get_batches(updates)? {
+      let size = batch.len();
+      let command = doc! {
+        "update": Self::T::COLLECTION_NAME,
+        "updates": batch,
+        "ordered": true,
+      };
+      let res = connection
+        .run_command(command, None)
+        .await
+        .map_err(Error::Mongo)?;
+      let res = from_bson::<BulkUpdateResult>(Bson::Document(res))
+        .map_err(Error::SerializeMongoResponse)?;
+
+      // Upserted indexes are relative to the batch.
+      result.modified += res.modified;
+      result
+        .upserted
+        .extend(res.upserted.into_iter().map(|mut upserted| {
+          if let Some(index) = upserted.get("index").and_then(Bson::as_i32) {
+            upserted.insert("index", index + offset as i32);
+          }
+          upserted
+        }));
+      if let Some(error) = res.write_errors.first() {
+        return Err(Error::Write(error.clone()));
+      }
+
+      offset += size;
+    }
+
+    Ok(result)
+  }
+
   async fn aggregate<A>(pipeline: Vec<Document>) -> Result<Vec<A>, Error>
   where
     A: Serialize + DeserializeOwned,
@@ -213,6 +261,48 @@ pub trait ModelExt {
     Ok(documents)
   }
 
+  /// Lease the document matching the query to this process. Documents leased
+  /// by another process are not matched until their lease expires, so only
+  /// one process works on a document at a time. Returns the leased document.
+  async fn acquire_lease(query: Document) -> Result<Option<Self::T>, Error> {
+    let now = Utc::now();
+    let lease_seconds = get_settings().schedulers.lease_seconds;
+    let locked_until: Date = (now + Duration::seconds(lease_seconds)).into();
+    let now: Date = now.into();
+
+    let query = doc! {
+      "$and": [
+        query,
+        {
+          "$or": [
+            { "locked_until": null },
+            { "locked_until": { "$lte": now } }
+          ]
+        }
+      ]
+    };
+    let update = doc! {
+      "$set": {
+        "locked_until": locked_until,
+        "locked_by": lease::get_owner()
+      }
+    };
+
+    Self::find_one_and_update(query, update).await
+  }
+
+  /// Release the document lease, if this process still holds it.
+  async fn release_lease(id: &ObjectId) -> Result<(), Error> {
+    Self::update_one(
+      doc! { "_id": id, "locked_by": lease::get_owner() },
+      doc! { "$unset": { "locked_until": 1_i32, "locked_by": 1_i32 } },
+      None,
+    )
+    .await?;
+
+    Ok(())
+  }
+
   async fn sync_indexes() -> Result<(), Error> {
     let connection = database::get_connection();
     Self::T::sync(connection).await.map_err(Error::Wither)?;
@@ -220,3 +310,46 @@ pub trait ModelExt {
     Ok(())
   }
 }
+
+// Limits of a MongoDB command. The size limit leaves room for the rest of the
+// command, besides the statements.
+const MAX_WRITE_BATCH_SIZE: usize = 100_000;
+const MAX_BATCH_BYTES: usize = 16 * 1024 * 1024 - 16 * 1024;
+
+/// Split the statements in batches within the MongoDB command limits.
+fn get_batches(statements: Vec<Document>) -> Result<Vec<Vec<Document>>, Error> {
+  let mut batches: Vec<Vec<Document>> = vec![];
+  let mut batch: Vec<Document> = vec![];
+  let mut batch_bytes = 0;
+
+  for statement in statements {
+    let bytes = bson::to_vec(&statement)?.len();
+    if !batch.is_empty()
+      && (batch.len() == MAX_WRITE_BATCH_SIZE || batch_bytes + bytes > MAX_BATCH_BYTES)
+    {
+      batches.push(std::mem::take(&mut batch));
+      batch_bytes = 0;
+    }
+
+    batch_bytes += bytes;
+    batch.push(statement);
+  }
+
+  if !batch.is_empty() {
+    batches.push(batch);
+  }
+
+  Ok(batches)
+}
+
+/// Result of a bulk update, upserted holds the index and ID of every inserted
+/// document.
+#[derive(Debug, Default, Deserialize)]
+pub struct BulkUpdateResult {
+  #[serde(rename = "nModified", default)]
+  pub modified: u64,
+  #[serde(default)]
+  pub upserted: Vec<Document>,
+  #[serde(rename = "writeErrors", default)]
+  pub write_errors: Vec<WriteError>,
+}
@@ -2,6 +2,8 @@ use chrono::DateTime;
 use chrono::ParseError;
 use chrono::Utc;
 
+use crate::errors::BadRequest;
+
 pub type Date = bson::DateTime;
 
 pub fn now() -> Date {
@@ -12,3 +14,11 @@ pub fn from_iso(iso: &str) -> Result<DateTime<Utc>, ParseError> {
   let date = chrono::DateTime::parse_from_rfc3339(iso)?.with_timezone(&Utc);
   Ok(date)
 }
+
+/// Parse the ISO date of the `from` query parameter.
+pub fn to_date<A>(iso: A) -> Result<DateTime<Utc>, BadRequest>
+where
+  A: AsRef<str>,
+{
+  from_iso(iso.as_ref()).map_err(|_e| BadRequest::new("from", "Invalid ISO string date"))
+}
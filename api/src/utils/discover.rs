@@ -0,0 +1,115 @@
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+// Content types advertised by websites in their feed links.
+const FEED_CONTENT_TYPES: [&str; 5] = [
+  "application/rss+xml",
+  "application/atom+xml",
+  "application/feed+json",
+  "application/json",
+  "application/rdf+xml",
+];
+
+// Paths where websites usually serve their feeds, used when the website does
+// not advertise any feed.
+const WELL_KNOWN_PATHS: [&str; 7] = [
+  "/feed",
+  "/rss",
+  "/feed.xml",
+  "/rss.xml",
+  "/atom.xml",
+  "/index.xml",
+  "/feed.json",
+];
+
+/// Feed advertised by a website with a `<link rel="alternate">` element.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveredFeed {
+  pub url: String,
+  pub title: Option<String>,
+  pub content_type: String,
+}
+
+/// Find the feeds advertised in the HTML document, in document order. Relative
+/// URLs are resolved using the document base element or the page URL.
+pub fn find_feeds(html: &[u8], page_url: &str) -> Vec<DiscoveredFeed> {
+  let document = Html::parse_document(&String::from_utf8_lossy(html));
+  let link_selector = Selector::parse("link[href]").unwrap();
+  let base_selector = Selector::parse("base[href]").unwrap();
+
+  let page_url = match Url::parse(page_url) {
+    Ok(url) => url,
+    Err(_) => return vec![],
+  };
+  let base_url = document
+    .select(&base_selector)
+    .next()
+    .and_then(|base| base.value().attr("href"))
+    .and_then(|href| page_url.join(href).ok())
+    .unwrap_or(page_url);
+
+  let mut feeds: Vec<DiscoveredFeed> = vec![];
+  for link in document.select(&link_selector) {
+    let link = link.value();
+    let is_alternate = link.attr("rel").is_some_and(|rel| {
+      rel
+        .split_whitespace()
+        .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+    });
+    let content_type = link
+      .attr("type")
+      .map(|content_type| content_type.trim().to_lowercase())
+      .filter(|content_type| FEED_CONTENT_TYPES.contains(&content_type.as_str()));
+
+    let (content_type, url) = match (is_alternate, content_type, link.attr("href")) {
+      (true, Some(content_type), Some(href)) => match base_url.join(href.trim()) {
+        Ok(url) => (content_type, url.to_string()),
+        Err(_) => continue,
+      },
+      _ => continue,
+    };
+
+    if feeds.iter().any(|feed| feed.url == url) {
+      continue;
+    }
+
+    feeds.push(DiscoveredFeed {
+      url,
+      title: link
+        .attr("title")
+        .map(|title| title.trim().to_owned())
+        .filter(|title| !title.is_empty()),
+      content_type,
+    });
+  }
+
+  feeds
+}
+
+/// URLs where the website could be serving its feed.
+pub fn get_well_known_urls(page_url: &str) -> Vec<String> {
+  let page_url = match Url::parse(page_url) {
+    Ok(url) => url,
+    Err(_) => return vec![],
+  };
+
+  WELL_KNOWN_PATHS
+    .iter()
+    .filter_map(|path| page_url.join(path).ok())
+    .map(|url| url.to_string())
+    .collect()
+}
+
+/// Check if the content is an HTML document, for servers that do not send a
+/// proper content type.
+pub fn is_html(content: &[u8]) -> bool {
+  let start = content
+    .iter()
+    .skip_while(|byte| byte.is_ascii_whitespace())
+    .take(15)
+    .map(|byte| byte.to_ascii_lowercase())
+    .collect::<Vec<u8>>();
+
+  start.starts_with(b"<!doctype html") || start.starts_with(b"<html")
+}
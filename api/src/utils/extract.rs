@@ -0,0 +1,135 @@
+use ego_tree::{NodeId, NodeRef};
+use lazy_static::lazy_static;
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+
+use crate::utils::html;
+
+// Articles with less text than this are not considered extracted, the page is
+// probably not an article.
+const MIN_ARTICLE_LENGTH: usize = 250;
+// Paragraphs shorter than this do not count towards their container score.
+const MIN_PARAGRAPH_LENGTH: usize = 25;
+
+// Elements that never contain the article.
+const SKIPPED_TAGS: [&str; 9] = [
+  "aside", "footer", "form", "header", "nav", "noscript", "script", "style", "template",
+];
+
+// Class and ID fragments of elements that usually are, or usually are not, the
+// main article.
+const POSITIVE_HINTS: [&str; 7] = [
+  "article", "body", "content", "entry", "main", "post", "text",
+];
+const NEGATIVE_HINTS: [&str; 13] = [
+  "ad-", "comment", "footer", "menu", "meta", "nav", "promo", "related", "share", "sidebar",
+  "social", "sponsor", "widget",
+];
+
+lazy_static! {
+  static ref PARAGRAPH_SELECTOR: Selector = Selector::parse("p, pre, td, blockquote").unwrap();
+  static ref LINK_SELECTOR: Selector = Selector::parse("a").unwrap();
+}
+
+/// Extract the main article of an HTML page, readability style. Paragraphs
+/// score their parent and grandparent elements by the amount of text they
+/// have, and the element with the best score, adjusted by its link density and
+/// class names, is the article. The article is returned as sanitized HTML,
+/// relative URLs are resolved using the page URL.
+pub fn extract_article(page: &[u8], page_url: &str) -> Option<String> {
+  let document = Html::parse_document(&String::from_utf8_lossy(page));
+
+  let mut scores: HashMap<NodeId, f64> = HashMap::new();
+  for paragraph in document.select(&PARAGRAPH_SELECTOR) {
+    if paragraph.ancestors().any(|node| is_unlikely(&node)) {
+      continue;
+    }
+
+    let text = get_text(&paragraph);
+    if text.chars().count() < MIN_PARAGRAPH_LENGTH {
+      continue;
+    }
+
+    let score = 1.0 + text.matches(',').count() as f64 + (text.len() / 100).min(3) as f64;
+    let mut ancestors = paragraph
+      .ancestors()
+      .filter(|node| node.value().is_element());
+    if let Some(parent) = ancestors.next() {
+      *scores.entry(parent.id()).or_insert(0.0) += score;
+    }
+    if let Some(grandparent) = ancestors.next() {
+      *scores.entry(grandparent.id()).or_insert(0.0) += score / 2.0;
+    }
+  }
+
+  let (article, _) = scores
+    .into_iter()
+    .filter_map(|(id, score)| {
+      let element = ElementRef::wrap(document.tree.get(id)?)?;
+      let score = score * (1.0 - get_link_density(&element)) + get_class_weight(&element);
+      Some((element, score))
+    })
+    .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+  if get_text(&article).chars().count() < MIN_ARTICLE_LENGTH {
+    return None;
+  }
+
+  let article = html::sanitize(&article.html(), Some(page_url));
+  Some(article.trim().to_owned())
+}
+
+fn is_unlikely(node: &NodeRef<Node>) -> bool {
+  let element = match ElementRef::wrap(*node) {
+    Some(element) => element,
+    None => return false,
+  };
+
+  SKIPPED_TAGS.contains(&element.value().name()) || get_class_weight(&element) < 0.0
+}
+
+/// Weight of the element class names and ID, positive for names usually used
+/// for articles and negative for names usually used for everything else.
+fn get_class_weight(element: &ElementRef) -> f64 {
+  let value = element.value();
+  let names = format!(
+    "{} {}",
+    value.attr("class").unwrap_or_default(),
+    value.id().unwrap_or_default()
+  )
+  .to_lowercase();
+
+  let mut weight = 0.0;
+  if POSITIVE_HINTS.iter().any(|hint| names.contains(hint)) {
+    weight += 25.0;
+  }
+  if NEGATIVE_HINTS.iter().any(|hint| names.contains(hint)) {
+    weight -= 25.0;
+  }
+  weight
+}
+
+/// Ratio of the element text that is inside links.
+fn get_link_density(element: &ElementRef) -> f64 {
+  let length = get_text(element).len();
+  if length == 0 {
+    return 0.0;
+  }
+
+  let link_length = element
+    .select(&LINK_SELECTOR)
+    .map(|link| get_text(&link).len())
+    .sum::<usize>();
+
+  link_length as f64 / length as f64
+}
+
+fn get_text(element: &ElementRef) -> String {
+  element
+    .text()
+    .collect::<Vec<&str>>()
+    .join(" ")
+    .split_whitespace()
+    .collect::<Vec<&str>>()
+    .join(" ")
+}
@@ -1,42 +1,648 @@
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
 use feed_rs::model::Feed;
 use feed_rs::parser;
 use lazy_static::lazy_static;
+use mime::Mime;
 use parser::ParseFeedError;
 use reqwest;
+use reqwest::header::{
+  HeaderMap, HeaderName, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+  LOCATION,
+};
+use reqwest::redirect::Policy;
 use reqwest::Error as ReqwestError;
+use reqwest::{Response, StatusCode};
 use std::time::Duration;
+use tracing::debug;
+use url::Url;
+
+use crate::errors::BadRequest;
+use crate::settings::get_settings;
+use crate::utils::discover::{self, DiscoveredFeed};
+use crate::utils::hash::sha256;
+use crate::utils::host_limiter::{self, Permit};
+use crate::utils::map_json::{map_items, Error as MapJsonError, JsonMapping};
+use crate::utils::outbound::{self, check_url, Error as OutboundError};
+use crate::utils::podcast::{self, PodcastFeed};
+use crate::utils::robots;
+use crate::utils::scrape::{scrape, Error as ScrapeError, ScrapedPage, Selectors};
+use crate::utils::sync_schedule::{FeedHints, ResponseHints};
+use crate::utils::websub::{self, Hub};
+use crate::utils::xml::Element;
 
 #[cfg(test)]
 use mockito;
 
 lazy_static! {
+  // Redirects are followed manually to keep track of them.
   static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+    .dns_resolver(outbound::resolver())
     .timeout(Duration::from_secs(5))
+    .redirect(Policy::none())
+    .user_agent(&get_settings().outbound.user_agent)
     .build()
     .expect("Failed to create a reqwest client");
 }
 
+// Feeds larger than this are not downloaded.
+const MAX_FEED_SIZE: usize = 10 * 1024 * 1024;
+// Maximum amount of redirects followed when fetching a feed.
+const MAX_REDIRECTS: usize = 10;
+// Maximum amount of feeds advertised by a website that are fetched to find a
+// valid one.
+const MAX_CANDIDATES: usize = 5;
+
+/// Errors fetching a feed. The messages are returned to our users, they should
+/// explain what is wrong with the feed URL.
 #[derive(thiserror::Error, Debug)]
 #[error("...")]
 pub enum Error {
+  #[error("Invalid feed URL")]
+  InvalidUrl(#[source] ReqwestError),
+
+  #[error("Failed to resolve the feed URL host name")]
+  Dns(#[source] ReqwestError),
+
+  #[error("Failed to connect to the feed server")]
+  Connect(#[source] ReqwestError),
+
+  #[error("Timed out while fetching the feed")]
+  Timeout(#[source] ReqwestError),
+
+  #[error("Failed to establish a secure connection with the feed server")]
+  Tls(#[source] ReqwestError),
+
+  #[error("Feed server responded with a {0} status code")]
+  HttpStatus(u16),
+
+  #[error("Feed URL redirected too many times")]
+  TooManyRedirects,
+
+  #[error("Feed is larger than the maximum allowed size of {} MB", MAX_FEED_SIZE / 1024 / 1024)]
+  TooLarge,
+
+  #[error("Feed URL responded with an unsupported content type {0}")]
+  UnsupportedContentType(String),
+
+  #[error("Feed URL does not point to a valid RSS, Atom or JSON feed")]
+  Parse(#[from] ParseFeedError),
+
+  #[error("Website does not advertise any valid RSS, Atom or JSON feed")]
+  NoFeedFound,
+
+  #[error("Feed URL is not allowed. {0}")]
+  Blocked(#[from] OutboundError),
+
+  #[error("Feed URL is disallowed by the website robots.txt")]
+  DisallowedByRobots,
+
+  #[error("Feed server is rate limiting our requests, retrying after {0}")]
+  RateLimited(DateTime<Utc>),
+
   #[error("{0}")]
-  Request(#[from] ReqwestError),
+  Scrape(#[from] ScrapeError),
 
   #[error("{0}")]
-  Parse(#[from] ParseFeedError),
+  MapJson(#[from] MapJsonError),
+
+  #[error("The selectors or JSON mapping did not match any item")]
+  NoItemsFound,
+
+  #[error("Failed to fetch the feed")]
+  Request(#[source] ReqwestError),
+}
+
+impl From<ReqwestError> for Error {
+  fn from(err: ReqwestError) -> Self {
+    if err.is_builder() {
+      return Error::InvalidUrl(err);
+    }
+    if err.is_timeout() {
+      return Error::Timeout(err);
+    }
+
+    // Reqwest does not expose the DNS and TLS failures, these are only
+    // available in the underlying connector error messages.
+    let mut source = std::error::Error::source(&err);
+    let mut messages = vec![];
+    while let Some(inner) = source {
+      messages.push(inner.to_string().to_lowercase());
+      source = inner.source();
+    }
+
+    let has_message = |patterns: &[&str]| {
+      messages
+        .iter()
+        .any(|message| patterns.iter().any(|pattern| message.contains(pattern)))
+    };
+
+    if has_message(&[
+      "dns error",
+      "failed to lookup address",
+      "name or service not known",
+    ]) {
+      return Error::Dns(err);
+    }
+    if has_message(&["certificate", "tls", "ssl", "handshake"]) {
+      return Error::Tls(err);
+    }
+    if err.is_connect() {
+      return Error::Connect(err);
+    }
+
+    Error::Request(err)
+  }
+}
+
+/// Values from a previous fetch used to avoid downloading and parsing a feed
+/// that did not change. The ETag and Last-Modified headers are sent back to
+/// the server as a conditional request, the hash covers servers that do not
+/// support conditional requests.
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+  pub hash: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Fetched {
+  pub content: Content,
+  /// Response status code and body size, the body of 304 responses is empty.
+  pub status: u16,
+  pub bytes: usize,
+  /// Validators to send on the next fetch.
+  pub validators: Validators,
+  pub hints: ResponseHints,
+  /// Redirects followed to fetch the feed.
+  pub redirects: Vec<Redirect>,
+}
+
+/// Redirect followed while fetching a feed.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+  pub from: String,
+  pub to: String,
+  pub status: u16,
+}
+
+impl Redirect {
+  pub fn is_permanent(&self) -> bool {
+    self.status == StatusCode::MOVED_PERMANENTLY.as_u16()
+      || self.status == StatusCode::PERMANENT_REDIRECT.as_u16()
+  }
+}
+
+/// URL the feed moved to, reached following only permanent redirects.
+pub fn get_permanent_url(redirects: &[Redirect]) -> Option<String> {
+  redirects
+    .iter()
+    .take_while(|redirect| redirect.is_permanent())
+    .last()
+    .map(|redirect| redirect.to.clone())
+}
+
+#[derive(Debug)]
+pub enum Content {
+  /// The server responded with a 304 Not Modified status code.
+  NotModified,
+  /// The server responded with the same body as the previous fetch.
+  Unchanged,
+  /// The feed changed since the previous fetch. Includes the WebSub hub
+  /// advertised by the feed and its podcast metadata, if any.
+  Modified(Box<Feed>, FeedHints, Option<Hub>, Option<Box<PodcastFeed>>),
+  /// The HTML page or JSON API response changed since the previous fetch,
+  /// with the items found using the feed selectors or JSON mapping.
+  Scraped(ScrapedPage),
+  /// The content changed since the previous fetch but it could not be parsed
+  /// or mapped into items.
+  Invalid(Error),
+}
+
+/// Where the entries of a feed come from. Feeds are parsed, HTML pages and
+/// JSON APIs are mapped into entries with the selectors or JSON pointers given
+/// by the user.
+#[derive(Debug, Clone, Copy)]
+pub enum Source<'a> {
+  Feed,
+  Html(&'a Selectors),
+  Json(&'a JsonMapping),
+}
+
+impl<'a> Source<'a> {
+  /// Source from the selectors or JSON mapping given by the user, at most one
+  /// of them can be given.
+  pub fn from_options(
+    selectors: Option<&'a Selectors>,
+    mapping: Option<&'a JsonMapping>,
+  ) -> Result<Self, BadRequest> {
+    match (selectors, mapping) {
+      (Some(_), Some(_)) => Err(BadRequest::new(
+        "mapping",
+        "Selectors and a JSON mapping can not be given together",
+      )),
+      (Some(selectors), None) => selectors
+        .validate()
+        .map(|_| Source::Html(selectors))
+        .map_err(|err| BadRequest::new("selectors", err.to_string())),
+      (None, Some(mapping)) => mapping
+        .validate()
+        .map(|_| Source::Json(mapping))
+        .map_err(|err| BadRequest::new("mapping", err.to_string())),
+      (None, None) => Ok(Source::Feed),
+    }
+  }
+
+  /// Items of the HTML page or JSON API response, feeds do not have items.
+  fn get_items(&self, content: &[u8], url: &str) -> Result<Option<ScrapedPage>, Error> {
+    match self {
+      Source::Feed => Ok(None),
+      Source::Html(selectors) => Ok(Some(scrape(content, url, selectors)?)),
+      Source::Json(mapping) => Ok(Some(map_items(content, url, mapping)?)),
+    }
+  }
+}
+
+/// Feed fetched from a URL given by a user, which can be a website URL.
+#[derive(Debug)]
+pub struct Resolved {
+  /// URL of the fetched feed, after following permanent redirects.
+  pub url: String,
+  pub feed: Feed,
+  /// WebSub hub advertised by the feed, if any.
+  pub hub: Option<Hub>,
+  /// Podcast metadata of the feed and its entries, if any.
+  pub podcast: Option<PodcastFeed>,
+  /// Feeds advertised by the website, empty when the URL is a feed URL.
+  pub discovered: Vec<DiscoveredFeed>,
+  /// Redirects followed to fetch the feed.
+  pub redirects: Vec<Redirect>,
+}
+
+/// Fetch the feed from the given URL. When the URL points to a website, the
+/// first valid feed advertised by the website is fetched instead. Websites
+/// that do not advertise any feed are checked for feeds in well known paths.
+pub async fn resolve_feed(url: String) -> Result<Resolved, Error> {
+  let Sent {
+    res,
+    redirects,
+    permit,
+  } = send(&url, HeaderMap::new()).await?;
+  let headers = res.headers().clone();
+  let content = get_body(res).await?;
+  drop(permit);
+  let url = get_permanent_url(&redirects).unwrap_or(url);
+
+  let err = match parser::parse(content.as_ref()) {
+    Ok(feed) => {
+      let hub = websub::discover(&feed, &headers, &url);
+      return Ok(Resolved {
+        url,
+        feed,
+        hub,
+        podcast: parse_podcast(&content),
+        discovered: vec![],
+        redirects,
+      });
+    }
+    Err(err) => err,
+  };
+
+  let is_html = get_content_type(&headers)
+    .map(|content_type| content_type.subtype() == mime::HTML)
+    .unwrap_or_else(|| discover::is_html(&content));
+
+  if !is_html {
+    return Err(Error::Parse(err));
+  }
+
+  // Websites can advertise any amount of feeds, only the first ones are
+  // fetched. Every well known path is checked.
+  let discovered = discover::find_feeds(&content, &url);
+  let candidates = if discovered.is_empty() {
+    discover::get_well_known_urls(&url)
+  } else {
+    discovered
+      .iter()
+      .take(MAX_CANDIDATES)
+      .map(|feed| feed.url.clone())
+      .collect()
+  };
+
+  for candidate in candidates {
+    match fetch_feed(candidate.clone()).await {
+      Ok((feed, hub, podcast, redirects)) => {
+        return Ok(Resolved {
+          url: get_permanent_url(&redirects).unwrap_or(candidate),
+          feed,
+          hub,
+          podcast,
+          discovered,
+          redirects,
+        })
+      }
+      Err(err) => debug!(
+        "Discovered feed {} is not valid. Error: {}",
+        &candidate, err
+      ),
+    }
+  }
+
+  Err(Error::NoFeedFound)
 }
 
-pub async fn get_feed(url: String) -> Result<Feed, Error> {
-  let url = get_url(url);
-  let content = CLIENT.get(&url).send().await?.bytes().await?;
+/// Fetch the feed and discover the WebSub hub it advertises and its podcast
+/// metadata, if any.
+async fn fetch_feed(
+  url: String,
+) -> Result<(Feed, Option<Hub>, Option<PodcastFeed>, Vec<Redirect>), Error> {
+  let Sent {
+    res,
+    redirects,
+    permit,
+  } = send(&url, HeaderMap::new()).await?;
+  let headers = res.headers().clone();
+  let content = get_body(res).await?;
+  drop(permit);
   let feed = parser::parse(content.as_ref())?;
+  let url = get_permanent_url(&redirects).unwrap_or(url);
+  let hub = websub::discover(&feed, &headers, &url);
+
+  Ok((feed, hub, parse_podcast(&content), redirects))
+}
+
+fn parse_podcast(content: &[u8]) -> Option<PodcastFeed> {
+  let document = Element::parse(content).ok()?;
+  podcast::parse(&document)
+}
+
+/// Fetch the feed using the validators from a previous fetch. The feed body is
+/// only parsed when the server reports that it was modified and its content
+/// hash is different from the previous one. HTML pages and JSON APIs are
+/// mapped into items instead. Content that can not be parsed is not an error,
+/// the response details are still returned.
+pub async fn get_feed_if_modified(
+  url: String,
+  validators: &Validators,
+  source: Source<'_>,
+) -> Result<Fetched, Error> {
+  let mut req_headers = HeaderMap::new();
+  if let Some(etag) = validators.etag.as_ref().and_then(|etag| etag.parse().ok()) {
+    req_headers.insert(IF_NONE_MATCH, etag);
+  }
+  let last_modified = validators.last_modified.as_ref();
+  if let Some(last_modified) = last_modified.and_then(|value| value.parse().ok()) {
+    req_headers.insert(IF_MODIFIED_SINCE, last_modified);
+  }
+
+  let Sent {
+    res,
+    redirects,
+    permit,
+  } = send(&url, req_headers).await?;
+  let url = get_permanent_url(&redirects).unwrap_or(url);
+  let status = res.status().as_u16();
+  let hints = ResponseHints::from_headers(res.headers());
+  let etag = get_header(res.headers(), ETAG);
+  let last_modified = get_header(res.headers(), LAST_MODIFIED);
+
+  if res.status() == StatusCode::NOT_MODIFIED {
+    // A 304 response can update the validators, the content stays the same.
+    let validators = Validators {
+      etag: etag.or_else(|| validators.etag.clone()),
+      last_modified: last_modified.or_else(|| validators.last_modified.clone()),
+      hash: validators.hash.clone(),
+    };
+
+    return Ok(Fetched {
+      content: Content::NotModified,
+      status,
+      bytes: 0,
+      validators,
+      hints,
+      redirects,
+    });
+  }
+
+  let headers = res.headers().clone();
+  let content = get_body(res).await?;
+  drop(permit);
+  let bytes = content.len();
+  let next_validators = Validators {
+    etag,
+    last_modified,
+    hash: Some(sha256(&content)),
+  };
+
+  let content = if next_validators.hash == validators.hash {
+    Content::Unchanged
+  } else {
+    match parse_content(&content, &headers, &url, source) {
+      Ok(content) => content,
+      Err(err) => Content::Invalid(err),
+    }
+  };
+
+  Ok(Fetched {
+    content,
+    status,
+    bytes,
+    validators: next_validators,
+    hints,
+    redirects,
+  })
+}
+
+/// Parse the feed, or map the HTML page or JSON API response into items.
+fn parse_content(
+  content: &Bytes,
+  headers: &HeaderMap,
+  url: &str,
+  source: Source<'_>,
+) -> Result<Content, Error> {
+  if let Some(scraped) = source.get_items(content, url)? {
+    return Ok(Content::Scraped(scraped));
+  }
+
+  let feed = parser::parse(content.as_ref())?;
+  // The document is parsed once for the elements feed-rs does not expose,
+  // JSON feeds are not XML documents.
+  let document = Element::parse(content).ok();
+  let feed_hints = FeedHints::new(&feed, document.as_ref());
+  let hub = websub::discover(&feed, headers, url);
+  let podcast = document.as_ref().and_then(podcast::parse).map(Box::new);
+
+  Ok(Content::Modified(Box::new(feed), feed_hints, hub, podcast))
+}
+
+/// Fetch a web page, E.g. the article an entry links to, with the same
+/// policies used to fetch feeds. Returns the page content and its URL after
+/// following redirects.
+pub async fn get_page(url: String) -> Result<(Bytes, String), Error> {
+  let Sent {
+    res,
+    redirects,
+    permit,
+  } = send(&url, HeaderMap::new()).await?;
+  let url = redirects
+    .last()
+    .map(|redirect| redirect.to.clone())
+    .unwrap_or(url);
+  let content = get_body(res).await?;
+  drop(permit);
+
+  Ok((content, url))
+}
+
+/// Fetch the HTML page or JSON API and find its items. Returns the items and
+/// the URL after following redirects.
+pub async fn fetch_items(url: String, source: Source<'_>) -> Result<(ScrapedPage, String), Error> {
+  let (content, url) = get_page(url).await?;
+  let scraped = source.get_items(&content, &url)?.unwrap_or(ScrapedPage {
+    title: None,
+    items: vec![],
+  });
+
+  Ok((scraped, url))
+}
+
+struct Sent {
+  res: Response,
+  redirects: Vec<Redirect>,
+  // The host concurrency slot, to drop once the body is read. It must not be
+  // held while requesting the same host again, e.g. the feeds a website
+  // advertises, concurrent requests would wait for each other forever.
+  permit: Permit,
+}
+
+/// Send a GET request following redirects, failing on unsuccessful responses
+/// and content types that can not be a feed.
+async fn send(url: &str, headers: HeaderMap) -> Result<Sent, Error> {
+  let mut url = url.to_owned();
+  let mut redirects: Vec<Redirect> = vec![];
+
+  let (res, permit) = loop {
+    // Every redirect is checked, a public URL could redirect to a private one.
+    let request_url = get_url(url.clone());
+    check_url(&request_url).await?;
+
+    let settings = get_settings();
+    if settings.hosts.respect_robots_txt && !robots::is_allowed(&url).await {
+      return Err(Error::DisallowedByRobots);
+    }
+
+    let host = Url::parse(&url)
+      .ok()
+      .and_then(|url| url.host_str().map(ToOwned::to_owned))
+      .unwrap_or_default();
+    let permit = host_limiter::acquire(&host)
+      .await
+      .map_err(Error::RateLimited)?;
+
+    let res = CLIENT
+      .get(request_url)
+      .headers(headers.clone())
+      .send()
+      .await?;
+
+    // Stop sending requests to a host that is rate limiting us, other feeds
+    // from the same host are not synced until the host allows it.
+    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+      let retry_after = ResponseHints::from_headers(res.headers())
+        .retry_after
+        .unwrap_or(settings.hosts.default_retry_after);
+      let retry_at = Utc::now() + ChronoDuration::seconds(retry_after);
+      host_limiter::set_retry_at(&host, retry_at);
+      return Err(Error::RateLimited(retry_at));
+    }
+
+    let status = res.status();
+    if !status.is_redirection() || status == StatusCode::NOT_MODIFIED {
+      break (res, permit);
+    }
+
+    if redirects.len() >= MAX_REDIRECTS {
+      return Err(Error::TooManyRedirects);
+    }
+
+    let next_url = get_header(res.headers(), LOCATION)
+      .and_then(|location| Url::parse(&url).ok()?.join(&location).ok())
+      .ok_or_else(|| Error::HttpStatus(status.as_u16()))?
+      .to_string();
+
+    redirects.push(Redirect {
+      from: url,
+      to: next_url.clone(),
+      status: status.as_u16(),
+    });
+    url = next_url;
+  };
+
+  let status = res.status();
+  if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+    return Err(Error::HttpStatus(status.as_u16()));
+  }
+
+  if let Some(content_type) = get_content_type(res.headers()) {
+    let is_media =
+      [mime::IMAGE, mime::AUDIO, mime::VIDEO, mime::FONT].contains(&content_type.type_());
+    let is_binary = matches!(content_type.subtype().as_str(), "pdf" | "zip" | "gzip");
+    if is_media || is_binary {
+      return Err(Error::UnsupportedContentType(
+        content_type.essence_str().to_owned(),
+      ));
+    }
+  }
+
+  Ok(Sent {
+    res,
+    redirects,
+    permit,
+  })
+}
+
+/// Read the response body up to the maximum feed size.
+async fn get_body(mut res: Response) -> Result<Bytes, Error> {
+  let content_length = res.content_length().unwrap_or(0);
+  if content_length > MAX_FEED_SIZE as u64 {
+    return Err(Error::TooLarge);
+  }
+
+  let mut content = BytesMut::with_capacity(content_length as usize);
+  while let Some(chunk) = res.chunk().await? {
+    if content.len() + chunk.len() > MAX_FEED_SIZE {
+      return Err(Error::TooLarge);
+    }
+    content.extend_from_slice(&chunk);
+  }
+
+  Ok(content.freeze())
+}
+
+fn get_content_type(headers: &HeaderMap) -> Option<Mime> {
+  headers
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.parse::<Mime>().ok())
+}
 
-  Ok(feed)
+fn get_header(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+  headers
+    .get(name)
+    .and_then(|value| value.to_str().ok())
+    .map(ToOwned::to_owned)
 }
 
 fn get_url(url: String) -> String {
+  // Every request is sent to the mock server in tests. URLs pointing to the
+  // mock server keep their path.
   #[cfg(test)]
-  let url = mockito::server_url();
+  let url = if url.starts_with(&mockito::server_url()) {
+    url
+  } else {
+    mockito::server_url()
+  };
 
   url
 }
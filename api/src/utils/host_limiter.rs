@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::sleep;
+
+use crate::settings::get_settings;
+
+lazy_static! {
+  static ref HOSTS: Mutex<HashMap<String, Host>> = Mutex::new(HashMap::new());
+}
+
+// Amount of hosts above which idle hosts are forgotten.
+const MAX_HOSTS: usize = 10_000;
+
+/// Request budget of a host. Tokens are refilled at the configured rate up to
+/// the configured burst, each request takes one token. The semaphore limits
+/// the amount of concurrent requests to the host.
+struct Host {
+  semaphore: Arc<Semaphore>,
+  tokens: f64,
+  refilled_at: Instant,
+  // Date until which the host asked us to stop sending requests, from a 429
+  // response.
+  retry_at: Option<DateTime<Utc>>,
+}
+
+impl Host {
+  fn new() -> Self {
+    let settings = &get_settings().hosts;
+    Self {
+      semaphore: Arc::new(Semaphore::new(settings.max_concurrency)),
+      tokens: settings.burst,
+      refilled_at: Instant::now(),
+      retry_at: None,
+    }
+  }
+
+  /// Take a token, or return how long to wait until a token is available.
+  fn take_token(&mut self) -> Option<Duration> {
+    let settings = &get_settings().hosts;
+    let rate = settings.requests_per_minute / 60.0;
+
+    let now = Instant::now();
+    let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+    self.tokens = (self.tokens + elapsed * rate).min(settings.burst);
+    self.refilled_at = now;
+
+    if self.tokens >= 1.0 {
+      self.tokens -= 1.0;
+      return None;
+    }
+
+    Some(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+  }
+
+  /// Hosts without requests in flight, with a full budget and not rate
+  /// limiting us are in the same state as a new host.
+  fn is_idle(&self) -> bool {
+    let settings = &get_settings().hosts;
+    let rate = settings.requests_per_minute / 60.0;
+    let elapsed = self.refilled_at.elapsed().as_secs_f64();
+
+    Arc::strong_count(&self.semaphore) == 1
+      && self.tokens + elapsed * rate >= settings.burst
+      && self
+        .retry_at
+        .filter(|retry_at| *retry_at > Utc::now())
+        .is_none()
+  }
+}
+
+/// Get the host budget, forgetting the idle hosts when there are too many.
+fn get_host<'a>(hosts: &'a mut HashMap<String, Host>, host: &str) -> &'a mut Host {
+  if hosts.len() >= MAX_HOSTS && !hosts.contains_key(host) {
+    hosts.retain(|_, host| !host.is_idle());
+  }
+  hosts.entry(host.to_owned()).or_insert_with(Host::new)
+}
+
+/// Permission to send a request to a host, the concurrency slot is released
+/// when the permit is dropped.
+pub struct Permit {
+  _permit: OwnedSemaphorePermit,
+}
+
+/// Wait until a request can be sent to the host. Fails with the date the host
+/// can be requested again when the host is rate limiting us.
+pub async fn acquire(host: &str) -> Result<Permit, DateTime<Utc>> {
+  let semaphore = {
+    let mut hosts = HOSTS.lock().unwrap();
+    let host = get_host(&mut hosts, host);
+    if let Some(retry_at) = host.retry_at.filter(|retry_at| *retry_at > Utc::now()) {
+      return Err(retry_at);
+    }
+    host.semaphore.clone()
+  };
+
+  let permit = semaphore
+    .acquire_owned()
+    .await
+    .expect("Host semaphore is never closed");
+
+  loop {
+    let wait = get_host(&mut HOSTS.lock().unwrap(), host).take_token();
+
+    match wait {
+      Some(wait) => sleep(wait).await,
+      None => break,
+    }
+  }
+
+  Ok(Permit { _permit: permit })
+}
+
+/// Stop sending requests to the host until the given date.
+pub fn set_retry_at(host: &str, retry_at: DateTime<Utc>) {
+  let mut hosts = HOSTS.lock().unwrap();
+  get_host(&mut hosts, host).retry_at = Some(retry_at);
+}
@@ -0,0 +1,161 @@
+use ammonia::{Builder, UrlRelative};
+use ego_tree::iter::Edge;
+use lazy_static::lazy_static;
+use scraper::{Html, Node};
+use std::collections::{HashMap, HashSet};
+use url::Url;
+
+// Tags kept when sanitizing, everything else is removed keeping its text.
+const ALLOWED_TAGS: [&str; 37] = [
+  "a",
+  "abbr",
+  "b",
+  "blockquote",
+  "br",
+  "code",
+  "del",
+  "dd",
+  "dl",
+  "dt",
+  "em",
+  "figcaption",
+  "figure",
+  "h1",
+  "h2",
+  "h3",
+  "h4",
+  "h5",
+  "h6",
+  "hr",
+  "i",
+  "img",
+  "li",
+  "ol",
+  "p",
+  "pre",
+  "s",
+  "strong",
+  "sub",
+  "sup",
+  "table",
+  "tbody",
+  "td",
+  "th",
+  "thead",
+  "tr",
+  "ul",
+];
+
+// Elements that start a new line when rendering plain text.
+const BLOCK_TAGS: [&str; 24] = [
+  "address",
+  "article",
+  "blockquote",
+  "br",
+  "dd",
+  "div",
+  "dl",
+  "dt",
+  "figcaption",
+  "figure",
+  "h1",
+  "h2",
+  "h3",
+  "h4",
+  "h5",
+  "h6",
+  "hr",
+  "li",
+  "ol",
+  "p",
+  "pre",
+  "section",
+  "tr",
+  "ul",
+];
+
+// Elements whose content is never rendered as text.
+const HIDDEN_TAGS: [&str; 4] = ["head", "script", "style", "template"];
+
+lazy_static! {
+  static ref TAG_ATTRIBUTES: HashMap<&'static str, HashSet<&'static str>> = HashMap::from([
+    ("a", HashSet::from(["href", "title"])),
+    ("abbr", HashSet::from(["title"])),
+    (
+      "img",
+      HashSet::from(["src", "alt", "title", "width", "height"])
+    ),
+    ("td", HashSet::from(["colspan", "rowspan"])),
+    ("th", HashSet::from(["colspan", "rowspan"])),
+  ]);
+}
+
+/// Sanitize the HTML keeping only the allowed tags and attributes. Relative
+/// URLs are resolved using the given base URL, usually the entry URL.
+pub fn sanitize(html: &str, base_url: Option<&str>) -> String {
+  let url_relative = match base_url.and_then(|url| Url::parse(url).ok()) {
+    Some(url) => UrlRelative::RewriteWithBase(url),
+    None => UrlRelative::PassThrough,
+  };
+
+  Builder::default()
+    .tags(HashSet::from(ALLOWED_TAGS))
+    .tag_attributes(TAG_ATTRIBUTES.clone())
+    .generic_attributes(HashSet::new())
+    .url_schemes(HashSet::from(["http", "https", "mailto"]))
+    .link_rel(Some("noopener noreferrer nofollow"))
+    .url_relative(url_relative)
+    .clean(html)
+    .to_string()
+}
+
+/// Render the HTML as plain text. Block elements are rendered in their own
+/// lines and whitespace is collapsed.
+pub fn to_text(html: &str) -> String {
+  let fragment = Html::parse_fragment(html);
+
+  let mut text = String::new();
+  let mut hidden = 0;
+  for edge in fragment.tree.root().traverse() {
+    match edge {
+      Edge::Open(node) => match node.value() {
+        Node::Element(element) if HIDDEN_TAGS.contains(&element.name()) => hidden += 1,
+        Node::Element(element) if BLOCK_TAGS.contains(&element.name()) => text.push('\n'),
+        Node::Text(content) if hidden == 0 => text.push_str(content),
+        _ => {}
+      },
+      Edge::Close(node) => match node.value() {
+        Node::Element(element) if HIDDEN_TAGS.contains(&element.name()) => hidden -= 1,
+        Node::Element(element) if BLOCK_TAGS.contains(&element.name()) => text.push('\n'),
+        _ => {}
+      },
+    }
+  }
+
+  text
+    .lines()
+    .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+    .filter(|line| !line.is_empty())
+    .collect::<Vec<String>>()
+    .join("\n")
+}
+
+/// Truncate the text to the given number of characters, cutting at the last
+/// word boundary and adding an ellipsis. Line breaks are replaced by spaces.
+pub fn excerpt(text: &str, max_chars: usize) -> String {
+  let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+  if text.chars().count() <= max_chars {
+    return text;
+  }
+
+  let truncated = text.chars().take(max_chars).collect::<String>();
+  let truncated = match truncated.rfind(' ') {
+    Some(index) if index > 0 => &truncated[..index],
+    _ => truncated.as_str(),
+  };
+
+  format!(
+    "{}…",
+    truncated.trim_end_matches(|c: char| c.is_ascii_punctuation())
+  )
+}
@@ -0,0 +1,20 @@
+use lazy_static::lazy_static;
+use std::env;
+
+use crate::utils::create_random_string::create_random_string;
+
+lazy_static! {
+  // Identifies this process in the leases it holds. The host name helps to
+  // find the instance holding a lease, the random suffix tells apart processes
+  // running in the same host.
+  static ref OWNER: String = format!(
+    "{}-{}",
+    env::var("HOSTNAME").unwrap_or_else(|_| "api".to_owned()),
+    create_random_string(8)
+  );
+}
+
+/// Owner of the leases taken by this process.
+pub fn get_owner() -> &'static str {
+  &OWNER
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use crate::utils::scrape::{parse_date, ScrapedItem, ScrapedPage};
+
+#[derive(thiserror::Error, Debug)]
+#[error("...")]
+pub enum Error {
+  #[error("Invalid {0} JSON pointer")]
+  InvalidPointer(&'static str),
+
+  #[error("Response is not valid JSON")]
+  Parse(#[from] serde_json::Error),
+
+  #[error("The items JSON pointer does not point to an array")]
+  NotAnArray,
+}
+
+/// JSON pointers used to turn a JSON API response into feed entries. The items
+/// pointer points to the array of items in the response, the other pointers
+/// are relative to each item. E.g. `/data/releases` and `/name`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonMapping {
+  pub items: String,
+  pub id: Option<String>,
+  pub title: Option<String>,
+  pub url: Option<String>,
+  pub date: Option<String>,
+  pub summary: Option<String>,
+}
+
+impl JsonMapping {
+  /// Check every pointer is a valid JSON pointer, an empty pointer points to
+  /// the whole document.
+  pub fn validate(&self) -> Result<(), Error> {
+    let pointers = [
+      ("items", Some(&self.items)),
+      ("id", self.id.as_ref()),
+      ("title", self.title.as_ref()),
+      ("url", self.url.as_ref()),
+      ("date", self.date.as_ref()),
+      ("summary", self.summary.as_ref()),
+    ];
+
+    for (name, pointer) in pointers {
+      if let Some(pointer) = pointer {
+        if !pointer.is_empty() && !pointer.starts_with('/') {
+          return Err(Error::InvalidPointer(name));
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Find the response items using the given mapping, in response order. Items
+/// without an ID, a title nor a URL are skipped. Relative URLs are resolved
+/// using the response URL.
+pub fn map_items(content: &[u8], url: &str, mapping: &JsonMapping) -> Result<ScrapedPage, Error> {
+  mapping.validate()?;
+  let document = serde_json::from_slice::<Value>(content)?;
+  let items = document
+    .pointer(&mapping.items)
+    .and_then(Value::as_array)
+    .ok_or(Error::NotAnArray)?;
+  let base_url = Url::parse(url).ok();
+
+  let items = items
+    .iter()
+    .filter_map(|item| {
+      let get =
+        |pointer: &Option<String>| pointer.as_deref().and_then(|pointer| item.pointer(pointer));
+
+      let id = get(&mapping.id).and_then(to_string);
+      let title = get(&mapping.title).and_then(to_string);
+      let url = get(&mapping.url).and_then(to_string).map(|url| {
+        match base_url.as_ref().and_then(|base| base.join(&url).ok()) {
+          Some(url) => url.to_string(),
+          None => url,
+        }
+      });
+
+      if id.is_none() && title.is_none() && url.is_none() {
+        return None;
+      }
+
+      Some(ScrapedItem {
+        id,
+        title,
+        url,
+        published_at: get(&mapping.date).and_then(to_date),
+        summary: get(&mapping.summary).and_then(to_string),
+      })
+    })
+    .collect();
+
+  Ok(ScrapedPage { title: None, items })
+}
+
+fn to_string(value: &Value) -> Option<String> {
+  let value = match value {
+    Value::String(value) => value.trim().to_owned(),
+    Value::Number(value) => value.to_string(),
+    Value::Bool(value) => value.to_string(),
+    _ => return None,
+  };
+
+  Some(value).filter(|value| !value.is_empty())
+}
+
+/// Dates are strings or UNIX timestamps, in seconds or milliseconds.
+fn to_date(value: &Value) -> Option<DateTime<Utc>> {
+  match value {
+    Value::String(value) => parse_date(value),
+    Value::Number(value) => {
+      let timestamp = value.as_i64()?;
+      match timestamp.abs() > 100_000_000_000 {
+        true => Utc.timestamp_millis_opt(timestamp).single(),
+        false => Utc.timestamp_opt(timestamp, 0).single(),
+      }
+    }
+    _ => None,
+  }
+}
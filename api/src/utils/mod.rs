@@ -2,11 +2,27 @@ pub mod create_random_string;
 pub mod custom_response;
 pub mod database_model;
 pub mod date;
+pub mod discover;
+pub mod extract;
 pub mod get_feed;
 pub mod hash;
+pub mod host_limiter;
+pub mod html;
+pub mod lease;
+pub mod map_json;
+pub mod normalize_url;
+pub mod opml;
+pub mod outbound;
 pub mod pagination;
+pub mod podcast;
 pub mod request_query;
+pub mod robots;
+pub mod scrape;
 pub mod serde;
+pub mod shutdown;
+pub mod sync_schedule;
 pub mod to_object_id;
 pub mod to_url;
 pub mod token;
+pub mod websub;
+pub mod xml;
@@ -0,0 +1,58 @@
+use url::Url;
+
+use crate::errors::{BadRequest, Error};
+
+// Query parameters added by marketing tools, these do not change the feed.
+const TRACKING_PARAMETERS: [&str; 3] = ["fbclid", "gclid", "mc_cid"];
+
+/// Normalize a feed URL so equivalent URLs are stored the same way. The scheme
+/// and host are lowercased and default ports, fragments, tracking query
+/// parameters and trailing slashes are removed.
+pub fn normalize_url<S: AsRef<str>>(url: S) -> Result<String, Error> {
+  let invalid_url = || Error::BadRequest(BadRequest::new("url", "Invalid URL"));
+
+  let mut url = Url::parse(url.as_ref().trim()).map_err(|_| invalid_url())?;
+  if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+    return Err(invalid_url());
+  }
+
+  url.set_fragment(None);
+
+  let query = url
+    .query_pairs()
+    .filter(|(name, _)| !name.starts_with("utm_") && !TRACKING_PARAMETERS.contains(&name.as_ref()))
+    .map(|(name, value)| (name.into_owned(), value.into_owned()))
+    .collect::<Vec<(String, String)>>();
+
+  if query.is_empty() {
+    url.set_query(None);
+  } else {
+    url.query_pairs_mut().clear().extend_pairs(query);
+  }
+
+  let path = url.path().to_owned();
+  if path.len() > 1 && path.ends_with('/') {
+    url.set_path(path.trim_end_matches('/'));
+  }
+
+  Ok(url.to_string())
+}
+
+/// URLs considered equivalent to the given URL when looking up feeds, the
+/// normalized URL using HTTP and HTTPS.
+pub fn get_url_variants<S: AsRef<str>>(url: S) -> Vec<String> {
+  let mut variants = vec![url.as_ref().to_owned()];
+
+  if let Ok(normalized) = normalize_url(url) {
+    let other_scheme = match normalized.strip_prefix("https://") {
+      Some(rest) => format!("http://{}", rest),
+      None => normalized.replacen("http://", "https://", 1),
+    };
+    variants.push(normalized);
+    variants.push(other_scheme);
+  }
+
+  variants.sort();
+  variants.dedup();
+  variants
+}
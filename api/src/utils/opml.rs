@@ -0,0 +1,153 @@
+use quick_xml::escape::escape;
+
+use crate::utils::xml::Element;
+
+#[derive(thiserror::Error, Debug)]
+#[error("...")]
+pub enum Error {
+  #[error("Document is not valid XML")]
+  Parse(#[from] quick_xml::Error),
+
+  #[error("Document is not an OPML document")]
+  NotOpml,
+}
+
+/// Feed outline of an OPML document. Tags are the titles of the folders the
+/// outline is in and the categories of the outline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outline {
+  pub url: String,
+  pub title: Option<String>,
+  pub tags: Vec<String>,
+}
+
+/// Find the feed outlines of the OPML document, in document order. Outlines
+/// without a feed URL are folders, outlines with the same feed URL are merged.
+pub fn parse(content: &[u8]) -> Result<Vec<Outline>, Error> {
+  let document = Element::parse(content)?;
+  let body = document
+    .child("opml")
+    .and_then(|opml| opml.child("body"))
+    .ok_or(Error::NotOpml)?;
+
+  let mut outlines: Vec<Outline> = vec![];
+  collect_outlines(body, &[], &mut outlines);
+  Ok(outlines)
+}
+
+fn collect_outlines(parent: &Element, folders: &[String], outlines: &mut Vec<Outline>) {
+  for element in parent.children("outline") {
+    let title = get_attr(element, "title").or_else(|| get_attr(element, "text"));
+
+    let url = match get_attr(element, "xmlUrl") {
+      Some(url) => url,
+      None => {
+        // Folders can be nested, every folder is a tag of the outlines in it.
+        let mut folders = folders.to_vec();
+        folders.extend(title);
+        collect_outlines(element, &folders, outlines);
+        continue;
+      }
+    };
+
+    // Categories are comma separated, slash delimited paths. E.g.
+    // "/Tech/Rust,/News".
+    let categories = get_attr(element, "category").unwrap_or_default();
+    let categories = categories
+      .split(',')
+      .flat_map(|category| category.split('/'))
+      .map(|category| category.trim().to_owned())
+      .filter(|category| !category.is_empty());
+
+    let mut tags = vec![];
+    for tag in folders.iter().cloned().chain(categories) {
+      if !tags.contains(&tag) {
+        tags.push(tag);
+      }
+    }
+
+    match outlines.iter_mut().find(|outline| outline.url == url) {
+      Some(outline) => {
+        for tag in tags {
+          if !outline.tags.contains(&tag) {
+            outline.tags.push(tag);
+          }
+        }
+      }
+      None => outlines.push(Outline { url, title, tags }),
+    }
+  }
+}
+
+/// Attribute names are matched case insensitively, readers do not agree on
+/// the xmlUrl casing.
+fn get_attr(element: &Element, name: &str) -> Option<String> {
+  element
+    .attributes
+    .iter()
+    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+    .map(|(_, value)| value.trim().to_owned())
+    .filter(|value| !value.is_empty())
+}
+
+/// Render the outlines as an OPML 2.0 document. Outlines are grouped in
+/// folders by their first tag, all of their tags are kept as categories.
+pub fn render(title: &str, outlines: &[Outline]) -> String {
+  let mut body = String::new();
+  let mut folders: Vec<(&str, Vec<&Outline>)> = vec![];
+  for outline in outlines {
+    match outline.tags.first() {
+      Some(tag) => match folders.iter_mut().find(|(folder, _)| folder == tag) {
+        Some((_, outlines)) => outlines.push(outline),
+        None => folders.push((tag, vec![outline])),
+      },
+      None => body.push_str(&render_outline(outline, "    ")),
+    }
+  }
+
+  for (folder, outlines) in folders {
+    let folder = escape(folder);
+    body.push_str(&format!(
+      "    <outline text=\"{}\" title=\"{}\">\n",
+      folder, folder
+    ));
+    for outline in outlines {
+      body.push_str(&render_outline(outline, "      "));
+    }
+    body.push_str("    </outline>\n");
+  }
+
+  format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+     <opml version=\"2.0\">\n  \
+       <head>\n    \
+         <title>{}</title>\n  \
+       </head>\n  \
+       <body>\n{}  \
+       </body>\n\
+     </opml>\n",
+    escape(title),
+    body
+  )
+}
+
+fn render_outline(outline: &Outline, indent: &str) -> String {
+  let title = escape(outline.title.as_deref().unwrap_or(&outline.url)).into_owned();
+  let mut attributes = format!(
+    "type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"",
+    title,
+    title,
+    escape(&outline.url)
+  );
+  if !outline.tags.is_empty() {
+    let categories = outline
+      .tags
+      .iter()
+      .map(|tag| format!("/{}", tag.replace([',', '/'], " ")))
+      .collect::<Vec<String>>()
+      .join(",");
+    attributes.push_str(&format!(" category=\"{}\"", escape(&categories)));
+  }
+
+  format!("{}<outline {}/>\n", indent, attributes)
+}
@@ -0,0 +1,163 @@
+use hyper::client::connect::dns::Name;
+use ipnet::IpNet;
+use lazy_static::lazy_static;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::lookup_host;
+use url::{Host, Url};
+
+use crate::settings::get_settings;
+
+lazy_static! {
+  // Addresses that are not reachable on the public internet. Requests to these
+  // addresses could reach services running on our infrastructure.
+  static ref BLOCKED_NETWORKS: Vec<IpNet> = [
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.88.99.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "64:ff9b::/96",
+    "100::/64",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+  ]
+  .iter()
+  .map(|network| network.parse().unwrap())
+  .collect();
+}
+
+/// Errors returned when a URL is not allowed by the outbound policy.
+#[derive(thiserror::Error, Debug)]
+#[error("...")]
+pub enum Error {
+  #[error("URL is not valid")]
+  InvalidUrl,
+
+  #[error("URL scheme {0} is not allowed")]
+  Scheme(String),
+
+  #[error("URL port {0} is not allowed")]
+  Port(u16),
+
+  #[error("Failed to resolve the URL host name")]
+  Dns(#[source] std::io::Error),
+
+  #[error("URL resolves to the address {0}, which is not allowed")]
+  Address(IpAddr),
+}
+
+/// Check if a request to the given URL is allowed. Only HTTP(S) URLs with
+/// allowed ports are allowed, and the host must resolve to public addresses.
+/// Hosts and networks in the outbound allowlist are always allowed, this is
+/// used by self-hosted deployments to reach services in their network.
+///
+/// The URL of every redirect must be checked too, which is why outbound
+/// clients do not follow redirects on their own. Outbound clients also use the
+/// policy resolver, so the host can not resolve to other addresses when the
+/// request is sent.
+pub async fn check_url(url: &str) -> Result<(), Error> {
+  let settings = &get_settings().outbound;
+  let url = Url::parse(url).map_err(|_| Error::InvalidUrl)?;
+
+  let scheme = url.scheme();
+  if !matches!(scheme, "http" | "https") {
+    return Err(Error::Scheme(scheme.to_owned()));
+  }
+
+  let port = url.port_or_known_default().ok_or(Error::InvalidUrl)?;
+  if !settings.allowed_ports.contains(&port) {
+    return Err(Error::Port(port));
+  }
+
+  let host = url.host().ok_or(Error::InvalidUrl)?;
+  if is_allowed_host(url.host_str().unwrap_or_default()) {
+    return Ok(());
+  }
+
+  let address = match host {
+    Host::Ipv4(address) => IpAddr::V4(address),
+    Host::Ipv6(address) => IpAddr::V6(address),
+    Host::Domain(domain) => return resolve(domain).await.map(|_| ()),
+  };
+
+  match is_allowed(&address) {
+    true => Ok(()),
+    false => Err(Error::Address(address)),
+  }
+}
+
+/// DNS resolver of the outbound clients. Hosts can resolve to different
+/// addresses on every lookup, the addresses checked by `check_url` are not
+/// necessarily the ones the client connects to. This resolver applies the
+/// policy to the addresses the client connects to.
+pub struct PolicyResolver;
+
+impl Resolve for PolicyResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    Box::pin(async move {
+      let addresses = resolve(name.as_str()).await?;
+      let addresses: Addrs = Box::new(addresses.into_iter());
+      Ok(addresses)
+    })
+  }
+}
+
+pub fn resolver() -> Arc<PolicyResolver> {
+  Arc::new(PolicyResolver)
+}
+
+/// Resolve the host name, failing when it resolves to an address not allowed
+/// by the outbound policy.
+pub async fn resolve(host: &str) -> Result<Vec<SocketAddr>, Error> {
+  // The port is set by the client.
+  let addresses = lookup_host((host, 0))
+    .await
+    .map_err(Error::Dns)?
+    .collect::<Vec<SocketAddr>>();
+  if is_allowed_host(host) {
+    return Ok(addresses);
+  }
+
+  match addresses.iter().find(|address| !is_allowed(&address.ip())) {
+    Some(address) => Err(Error::Address(address.ip())),
+    None => Ok(addresses),
+  }
+}
+
+fn is_allowed_host(host: &str) -> bool {
+  get_settings()
+    .outbound
+    .allowed_hosts
+    .iter()
+    .any(|allowed| allowed.eq_ignore_ascii_case(host))
+}
+
+fn is_allowed(address: &IpAddr) -> bool {
+  // IPv4 addresses mapped to IPv6 are checked as IPv4 addresses.
+  let address = match address {
+    IpAddr::V6(v6) => v6
+      .to_ipv4_mapped()
+      .map(IpAddr::V4)
+      .unwrap_or(IpAddr::V6(*v6)),
+    IpAddr::V4(_) => *address,
+  };
+
+  let is_in = |networks: &[IpNet]| networks.iter().any(|network| network.contains(&address));
+  is_in(&get_settings().outbound.allowed_networks) || !is_in(&BLOCKED_NETWORKS)
+}
@@ -0,0 +1,328 @@
+use feed_rs::model::Entry as RawEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::utils::xml::Element;
+
+/// Podcast metadata of the feed, from the iTunes and Podcasting 2.0
+/// namespaces.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Podcast {
+  pub author: Option<String>,
+  pub image: Option<String>,
+  pub explicit: Option<bool>,
+  /// Episodic or serial.
+  pub podcast_type: Option<String>,
+  #[serde(default)]
+  pub categories: Vec<String>,
+}
+
+/// Podcast metadata of an entry. Duration is in seconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Episode {
+  pub duration: Option<i64>,
+  pub episode: Option<i32>,
+  pub season: Option<i32>,
+  /// Full, trailer or bonus.
+  pub episode_type: Option<String>,
+  pub explicit: Option<bool>,
+  pub image: Option<String>,
+  /// The episode audio or video file.
+  pub enclosure: Option<EpisodeEnclosure>,
+  #[serde(default)]
+  pub transcripts: Vec<Transcript>,
+  pub chapters: Option<Chapters>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpisodeEnclosure {
+  pub url: String,
+  pub content_type: Option<String>,
+  pub length: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+  pub url: String,
+  pub content_type: Option<String>,
+  pub language: Option<String>,
+  /// Captions when the transcript is meant to be shown as closed captions.
+  pub rel: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chapters {
+  pub url: String,
+  pub content_type: Option<String>,
+}
+
+const ITUNES_NAMESPACE: &str = "http://www.itunes.com/dtds/podcast-1.0.dtd";
+const PODCAST_NAMESPACES: [&str; 2] = [
+  "https://podcastindex.org/namespace/1.0",
+  // Used by the first feeds adopting the namespace.
+  "https://github.com/Podcastindex-org/podcast-namespace/blob/main/docs/1.0.md",
+];
+const RSS_1_NAMESPACE: &str = "http://purl.org/rss/1.0/";
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// Podcast metadata of the feed and its entries. Entries without any podcast
+/// metadata do not have an episode.
+#[derive(Debug, Clone)]
+pub struct PodcastFeed {
+  pub podcast: Podcast,
+  episodes: Vec<Episode>,
+  // Index of the episodes by the guid, or Atom id, of their entry and by
+  // their link.
+  guids: HashMap<String, usize>,
+  links: HashMap<String, usize>,
+}
+
+impl PodcastFeed {
+  /// Podcast metadata of the entry parsed by feed-rs. Entries are found by
+  /// their guid, feed-rs generates an ID for entries without one, these are
+  /// found by their link.
+  pub fn get_episode(&self, entry: &RawEntry) -> Option<Episode> {
+    let link = entry.links.first().map(|link| link.href.trim());
+    self
+      .guids
+      .get(entry.id.trim())
+      .or_else(|| self.links.get(link?))
+      .map(|index| self.episodes[*index].clone())
+  }
+}
+
+/// Parse the podcast metadata of the feed document. Returns `None` when the
+/// feed does not use the iTunes nor the Podcasting 2.0 namespaces.
+pub fn parse(document: &Element) -> Option<PodcastFeed> {
+  let root = document.children.first()?;
+  // RSS 2.0 items are inside the channel element, Atom entries and RSS 1.0
+  // items are direct children of the root element.
+  let channel = root
+    .children
+    .iter()
+    .find(|element| is_rss_element(element, "channel"))
+    .unwrap_or(root);
+  let items = channel
+    .children
+    .iter()
+    .chain(
+      root
+        .children
+        .iter()
+        .filter(|_| !std::ptr::eq(channel, root)),
+    )
+    .filter(|element| is_rss_element(element, "item") || element.is(ATOM_NAMESPACE, "entry"))
+    .collect::<Vec<&Element>>();
+
+  let is_podcast = channel
+    .children
+    .iter()
+    .chain(items.iter().flat_map(|item| item.children.iter()))
+    .any(is_podcast_element);
+  if !is_podcast {
+    return None;
+  }
+
+  let podcast = Podcast {
+    author: get_itunes_text(channel, "author"),
+    image: get_image(channel),
+    explicit: get_explicit(channel),
+    podcast_type: get_itunes_text(channel, "type").map(|value| value.to_lowercase()),
+    categories: get_categories(channel),
+  };
+
+  let mut podcast_feed = PodcastFeed {
+    podcast,
+    episodes: vec![],
+    guids: HashMap::new(),
+    links: HashMap::new(),
+  };
+
+  for item in items {
+    let episode = Episode {
+      duration: get_itunes_text(item, "duration").and_then(|value| parse_duration(&value)),
+      episode: get_number(item, "episode"),
+      season: get_number(item, "season"),
+      episode_type: get_itunes_text(item, "episodeType").map(|value| value.to_lowercase()),
+      explicit: get_explicit(item),
+      image: get_image(item),
+      enclosure: item.child("enclosure").and_then(|enclosure| {
+        Some(EpisodeEnclosure {
+          url: get_attr(enclosure, "url")?,
+          content_type: get_attr(enclosure, "type"),
+          length: get_attr(enclosure, "length").and_then(|length| length.parse().ok()),
+        })
+      }),
+      transcripts: get_podcast_children(item, "transcript")
+        .filter_map(|transcript| {
+          Some(Transcript {
+            url: get_attr(transcript, "url")?,
+            content_type: get_attr(transcript, "type"),
+            language: get_attr(transcript, "language"),
+            rel: get_attr(transcript, "rel"),
+          })
+        })
+        .collect(),
+      chapters: get_podcast_children(item, "chapters")
+        .next()
+        .and_then(|chapters| {
+          Some(Chapters {
+            url: get_attr(chapters, "url")?,
+            content_type: get_attr(chapters, "type"),
+          })
+        }),
+    };
+    if episode == Episode::default() {
+      continue;
+    }
+
+    let index = podcast_feed.episodes.len();
+    podcast_feed.episodes.push(episode);
+    // The first entry wins when several entries share a guid or a link, like
+    // feed-rs entries are matched in document order.
+    if let Some(guid) = get_guid(item) {
+      podcast_feed.guids.entry(guid).or_insert(index);
+    }
+    if let Some(link) = get_link(item) {
+      podcast_feed.links.entry(link).or_insert(index);
+    }
+  }
+
+  Some(podcast_feed)
+}
+
+/// RSS 2.0 elements do not have a namespace, RSS 1.0 elements have their own.
+fn is_rss_element(element: &Element, name: &str) -> bool {
+  let is_rss = match element.namespace.as_deref() {
+    None => true,
+    Some(namespace) => namespace == RSS_1_NAMESPACE,
+  };
+  is_rss && element.local_name() == name
+}
+
+fn is_itunes_namespace(namespace: &str) -> bool {
+  // Older feeds use the namespace URI with a different case.
+  namespace.eq_ignore_ascii_case(ITUNES_NAMESPACE)
+}
+
+fn is_podcast_element(element: &Element) -> bool {
+  match element.namespace.as_deref() {
+    Some(namespace) => is_itunes_namespace(namespace) || PODCAST_NAMESPACES.contains(&namespace),
+    None => false,
+  }
+}
+
+fn get_itunes_children<'a>(
+  element: &'a Element,
+  name: &'a str,
+) -> impl Iterator<Item = &'a Element> {
+  element.children.iter().filter(move |child| {
+    let is_itunes = child.namespace.as_deref().map(is_itunes_namespace);
+    is_itunes == Some(true) && child.local_name() == name
+  })
+}
+
+fn get_podcast_children<'a>(
+  element: &'a Element,
+  name: &'a str,
+) -> impl Iterator<Item = &'a Element> {
+  PODCAST_NAMESPACES
+    .iter()
+    .flat_map(move |namespace| element.children_in(namespace, name))
+}
+
+fn get_itunes_text(element: &Element, name: &str) -> Option<String> {
+  get_itunes_children(element, name).find_map(get_text)
+}
+
+fn get_podcast_text(element: &Element, name: &str) -> Option<String> {
+  get_podcast_children(element, name).find_map(get_text)
+}
+
+fn get_text(element: &Element) -> Option<String> {
+  Some(element.text.trim().to_owned()).filter(|text| !text.is_empty())
+}
+
+/// The RSS guid or Atom id of the entry, the value feed-rs uses as the entry
+/// ID.
+fn get_guid(item: &Element) -> Option<String> {
+  match item.namespace.as_deref() {
+    Some(ATOM_NAMESPACE) => item.child_in(ATOM_NAMESPACE, "id").and_then(get_text),
+    None => item.child("guid").and_then(get_text),
+    Some(_) => None,
+  }
+}
+
+/// The first link of the entry, the link text or the href attribute of Atom
+/// links.
+fn get_link(item: &Element) -> Option<String> {
+  let link = item
+    .children
+    .iter()
+    .find(|child| child.local_name() == "link" && child.namespace == item.namespace)?;
+  get_attr(link, "href").or_else(|| get_text(link))
+}
+
+fn get_attr(element: &Element, name: &str) -> Option<String> {
+  element
+    .attr(name)
+    .map(|value| value.trim().to_owned())
+    .filter(|value| !value.is_empty())
+}
+
+/// Image URL from the itunes:image href attribute, some feeds use the
+/// element text instead.
+fn get_image(element: &Element) -> Option<String> {
+  let image = get_itunes_children(element, "image").next()?;
+  get_attr(image, "href").or_else(|| get_text(image))
+}
+
+/// The itunes:explicit element is "true" or "false", older feeds use "yes",
+/// "explicit", "no" and "clean".
+fn get_explicit(element: &Element) -> Option<bool> {
+  let explicit = get_itunes_text(element, "explicit")?.to_lowercase();
+  match explicit.as_str() {
+    "true" | "yes" | "explicit" => Some(true),
+    "false" | "no" | "clean" => Some(false),
+    _ => None,
+  }
+}
+
+/// Categories and their subcategories, which are nested itunes:category
+/// elements.
+fn get_categories(element: &Element) -> Vec<String> {
+  let mut categories = vec![];
+  for category in get_itunes_children(element, "category") {
+    categories.extend(get_attr(category, "text"));
+    categories.extend(get_categories(category));
+  }
+  // Categories can be repeated under different parent categories.
+  let mut seen = HashSet::new();
+  categories.retain(|category| seen.insert(category.clone()));
+  categories
+}
+
+/// Episode and season numbers are integers, Podcasting 2.0 episodes can be
+/// decimals. Decimals are truncated.
+fn get_number(element: &Element, name: &str) -> Option<i32> {
+  let number = get_itunes_text(element, name).or_else(|| get_podcast_text(element, name))?;
+  number
+    .parse::<i32>()
+    .ok()
+    .or_else(|| number.parse::<f64>().ok().map(|number| number as i32))
+}
+
+/// Durations are given in seconds or as HH:MM:SS or MM:SS. Durations too large
+/// to be real are ignored.
+fn parse_duration(duration: &str) -> Option<i64> {
+  let mut seconds: i64 = 0;
+  for part in duration.split(':') {
+    let part = part.trim().parse::<f64>().ok()?;
+    if !part.is_finite() || part < 0.0 || part >= i64::MAX as f64 {
+      return None;
+    }
+    seconds = seconds.checked_mul(60)?.checked_add(part as i64)?;
+  }
+
+  Some(seconds)
+}
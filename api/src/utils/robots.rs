@@ -0,0 +1,249 @@
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use reqwest::redirect::Policy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use url::Url;
+
+use crate::settings::get_settings;
+use crate::utils::host_limiter;
+use crate::utils::outbound::{self, check_url};
+
+lazy_static! {
+  static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+    .dns_resolver(outbound::resolver())
+    .timeout(std::time::Duration::from_secs(5))
+    .redirect(Policy::none())
+    .user_agent(&get_settings().outbound.user_agent)
+    .build()
+    .expect("Failed to create a reqwest client");
+  static ref CACHE: Mutex<HashMap<String, (Rules, DateTime<Utc>)>> = Mutex::new(HashMap::new());
+}
+
+// Time the robots.txt file of a website is cached.
+const CACHE_HOURS: i64 = 24;
+// Maximum amount of websites with a cached robots.txt file.
+const MAX_CACHED_ORIGINS: usize = 10_000;
+// Content past the first 500 KiB of a robots.txt file is ignored, as allowed
+// by RFC 9309.
+const MAX_ROBOTS_SIZE: usize = 500 * 1024;
+
+/// Allow and disallow rules of the robots.txt groups that apply to us.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+  allow: Vec<String>,
+  disallow: Vec<String>,
+}
+
+impl Rules {
+  /// Parse the groups matching the user agent product name, or the `*` group
+  /// when there is not a specific group for us.
+  pub fn parse(content: &str, product: &str) -> Self {
+    let mut specific = Rules::default();
+    let mut wildcard = Rules::default();
+    let mut has_specific = false;
+
+    // User agents of the current group, a group starts with one or more
+    // User-agent lines.
+    let mut agents: Vec<String> = vec![];
+    let mut is_reading_agents = false;
+
+    for line in content.lines() {
+      let line = line.split('#').next().unwrap_or_default().trim();
+      let (name, value) = match line.split_once(':') {
+        Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+        None => continue,
+      };
+
+      if name == "user-agent" {
+        if !is_reading_agents {
+          agents.clear();
+        }
+        is_reading_agents = true;
+        agents.push(value.to_lowercase());
+        continue;
+      }
+      is_reading_agents = false;
+
+      let is_specific = agents.iter().any(|agent| agent == product);
+      let is_wildcard = agents.iter().any(|agent| agent == "*");
+      has_specific = has_specific || is_specific;
+
+      let rules = match (is_specific, is_wildcard) {
+        (true, _) => &mut specific,
+        (false, true) => &mut wildcard,
+        _ => continue,
+      };
+
+      // An empty disallow value allows everything.
+      match name.as_str() {
+        "allow" if !value.is_empty() => rules.allow.push(value.to_owned()),
+        "disallow" if !value.is_empty() => rules.disallow.push(value.to_owned()),
+        _ => {}
+      }
+    }
+
+    if has_specific {
+      specific
+    } else {
+      wildcard
+    }
+  }
+
+  /// The longest matching rule wins, allow rules win ties.
+  pub fn is_allowed(&self, path: &str) -> bool {
+    let longest = |rules: &[String]| {
+      rules
+        .iter()
+        .filter(|rule| matches(rule, path))
+        .map(|rule| rule.len())
+        .max()
+    };
+
+    match (longest(&self.allow), longest(&self.disallow)) {
+      (_, None) => true,
+      (None, Some(_)) => false,
+      (Some(allow), Some(disallow)) => allow >= disallow,
+    }
+  }
+}
+
+/// Match the path against a robots.txt rule as described in RFC 9309. Rules
+/// match path prefixes, `*` matches any sequence of characters and a trailing
+/// `$` matches the end of the path.
+fn matches(rule: &str, path: &str) -> bool {
+  let (rule, is_anchored) = match rule.strip_suffix('$') {
+    Some(rule) => (rule.as_bytes(), true),
+    None => (rule.as_bytes(), false),
+  };
+  let path = path.as_bytes();
+
+  // Position in the rule and the path.
+  let (mut r, mut p) = (0, 0);
+  // Position of the last `*` and of the path when it was found, the rule is
+  // matched again from there with the `*` matching one more character when
+  // the rest of the rule does not match.
+  let mut star: Option<(usize, usize)> = None;
+
+  while p < path.len() {
+    if r < rule.len() && rule[r] == b'*' {
+      star = Some((r, p));
+      r += 1;
+    } else if r < rule.len() && rule[r] == path[p] {
+      r += 1;
+      p += 1;
+    } else if r == rule.len() && !is_anchored {
+      return true;
+    } else if let Some((star_r, star_p)) = star {
+      star = Some((star_r, star_p + 1));
+      r = star_r + 1;
+      p = star_p + 1;
+    } else {
+      return false;
+    }
+  }
+
+  rule[r..].iter().all(|byte| *byte == b'*')
+}
+
+/// Check if the website robots.txt allows us to request the URL. Websites
+/// without a robots.txt file, or where it can not be fetched, allow every URL.
+pub async fn is_allowed(url: &str) -> bool {
+  let url = match Url::parse(url) {
+    Ok(url) => url,
+    Err(_) => return true,
+  };
+
+  let origin = url.origin().ascii_serialization();
+  let mut path = url.path().to_owned();
+  if let Some(query) = url.query() {
+    path = format!("{}?{}", path, query);
+  }
+
+  let cached = CACHE
+    .lock()
+    .unwrap()
+    .get(&origin)
+    .filter(|(_, expires_at)| *expires_at > Utc::now())
+    .map(|(rules, _)| rules.clone());
+
+  let rules = match cached {
+    Some(rules) => rules,
+    None => match fetch_rules(&url, &origin).await {
+      Some(rules) => {
+        cache_rules(origin, rules.clone());
+        rules
+      }
+      // The host is rate limiting us, the robots.txt file is fetched on the
+      // next request.
+      None => Rules::default(),
+    },
+  };
+
+  rules.is_allowed(&path)
+}
+
+fn cache_rules(origin: String, rules: Rules) {
+  let now = Utc::now();
+  let mut cache = CACHE.lock().unwrap();
+
+  // Make room removing the expired files first, then the files closest to
+  // expire.
+  if cache.len() >= MAX_CACHED_ORIGINS {
+    cache.retain(|_, (_, expires_at)| *expires_at > now);
+  }
+  if cache.len() >= MAX_CACHED_ORIGINS {
+    let oldest = cache
+      .iter()
+      .min_by_key(|(_, (_, expires_at))| *expires_at)
+      .map(|(origin, _)| origin.clone());
+    if let Some(oldest) = oldest {
+      cache.remove(&oldest);
+    }
+  }
+
+  cache.insert(origin, (rules, now + Duration::hours(CACHE_HOURS)));
+}
+
+/// Fetch the rules of the website. Files that can not be fetched allow every
+/// URL, no rules are returned when the host is rate limiting us.
+async fn fetch_rules(url: &Url, origin: &str) -> Option<Rules> {
+  let robots_url = format!("{}/robots.txt", origin);
+  if check_url(&robots_url).await.is_err() {
+    return Some(Rules::default());
+  }
+
+  // The robots.txt file counts as any other request to the host.
+  let host = url.host_str().unwrap_or_default();
+  let _permit = host_limiter::acquire(host).await.ok()?;
+
+  let mut res = match CLIENT.get(&robots_url).send().await {
+    Ok(res) if res.status().is_success() => res,
+    _ => return Some(Rules::default()),
+  };
+
+  // The product name is the User-Agent up to the first slash or space, E.g.
+  // `therssproject` for `therssproject/1.0 (+https://therssproject.com)`.
+  let user_agent = &get_settings().outbound.user_agent;
+  let product = user_agent
+    .split(|char: char| char == '/' || char.is_whitespace())
+    .next()
+    .unwrap_or_default()
+    .to_lowercase();
+
+  let mut content = Vec::new();
+  loop {
+    match res.chunk().await {
+      Ok(Some(chunk)) => content.extend_from_slice(&chunk),
+      Ok(None) => break,
+      Err(_) => return Some(Rules::default()),
+    }
+    if content.len() >= MAX_ROBOTS_SIZE {
+      content.truncate(MAX_ROBOTS_SIZE);
+      break;
+    }
+  }
+
+  let content = String::from_utf8_lossy(&content);
+  Some(Rules::parse(&content, &product))
+}
@@ -0,0 +1,208 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+// Date formats tried, in order, when the date element is not a RFC 3339 or RFC
+// 2822 date.
+const DATE_TIME_FORMATS: [&str; 3] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"];
+const DATE_FORMATS: [&str; 7] = [
+  "%Y-%m-%d",
+  "%Y/%m/%d",
+  "%d/%m/%Y",
+  "%B %d, %Y",
+  "%b %d, %Y",
+  "%d %B %Y",
+  "%d %b %Y",
+];
+
+#[derive(thiserror::Error, Debug)]
+#[error("...")]
+pub enum Error {
+  #[error("Invalid {0} CSS selector")]
+  InvalidSelector(&'static str),
+}
+
+/// CSS selectors used to turn an HTML page into feed entries. The item
+/// selector matches every entry container, the other selectors are matched
+/// inside each container. The link selector can match the link element or any
+/// of its parents, when it is not given the first link of the item is used.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Selectors {
+  pub item: String,
+  pub title: String,
+  pub link: Option<String>,
+  pub date: Option<String>,
+  pub summary: Option<String>,
+}
+
+impl Selectors {
+  /// Check every selector is a valid CSS selector.
+  pub fn validate(&self) -> Result<(), Error> {
+    self.compile().map(|_| ())
+  }
+
+  fn compile(&self) -> Result<Compiled, Error> {
+    let parse = |selector: &str, name: &'static str| {
+      Selector::parse(selector).map_err(|_| Error::InvalidSelector(name))
+    };
+    let parse_option = |selector: &Option<String>, name: &'static str| {
+      selector
+        .as_deref()
+        .map(|selector| parse(selector, name))
+        .transpose()
+    };
+
+    Ok(Compiled {
+      item: parse(&self.item, "item")?,
+      title: parse(&self.title, "title")?,
+      link: parse(self.link.as_deref().unwrap_or("a[href]"), "link")?,
+      date: parse_option(&self.date, "date")?,
+      summary: parse_option(&self.summary, "summary")?,
+    })
+  }
+}
+
+struct Compiled {
+  item: Selector,
+  title: Selector,
+  link: Selector,
+  date: Option<Selector>,
+  summary: Option<Selector>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScrapedPage {
+  pub title: Option<String>,
+  pub items: Vec<ScrapedItem>,
+}
+
+/// Entry found in an HTML page or a JSON API response. The summary is the
+/// HTML of the summary element. Only JSON items have an ID, HTML items are
+/// identified by their link.
+#[derive(Debug, Clone)]
+pub struct ScrapedItem {
+  pub id: Option<String>,
+  pub title: Option<String>,
+  pub url: Option<String>,
+  pub published_at: Option<DateTime<Utc>>,
+  pub summary: Option<String>,
+}
+
+/// Find the page items using the given selectors, in document order. Items
+/// without a title nor a link are skipped. Relative links are resolved using
+/// the page URL.
+pub fn scrape(html: &[u8], page_url: &str, selectors: &Selectors) -> Result<ScrapedPage, Error> {
+  let selectors = selectors.compile()?;
+  let document = Html::parse_document(&String::from_utf8_lossy(html));
+  let page_url = Url::parse(page_url).ok();
+
+  let title = Selector::parse("title")
+    .ok()
+    .and_then(|selector| document.select(&selector).next())
+    .map(|title| get_text(&title))
+    .filter(|title| !title.is_empty());
+
+  let items = document
+    .select(&selectors.item)
+    .filter_map(|item| {
+      let title = item
+        .select(&selectors.title)
+        .next()
+        .map(|title| get_text(&title))
+        .filter(|title| !title.is_empty());
+
+      let url = item
+        .select(&selectors.link)
+        .next()
+        .and_then(get_href)
+        .map(
+          |href| match page_url.as_ref().and_then(|url| url.join(&href).ok()) {
+            Some(url) => url.to_string(),
+            None => href,
+          },
+        );
+
+      if title.is_none() && url.is_none() {
+        return None;
+      }
+
+      let published_at = selectors
+        .date
+        .as_ref()
+        .and_then(|selector| item.select(selector).next())
+        .and_then(|date| {
+          let value = date.value();
+          let date = value
+            .attr("datetime")
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| get_text(&date));
+          parse_date(&date)
+        });
+
+      let summary = selectors
+        .summary
+        .as_ref()
+        .and_then(|selector| item.select(selector).next())
+        .map(|summary| summary.inner_html().trim().to_owned())
+        .filter(|summary| !summary.is_empty());
+
+      Some(ScrapedItem {
+        id: None,
+        title,
+        url,
+        published_at,
+        summary,
+      })
+    })
+    .collect();
+
+  Ok(ScrapedPage { title, items })
+}
+
+/// The link of the element, or of its first descendant link.
+fn get_href(element: ElementRef) -> Option<String> {
+  if let Some(href) = element.value().attr("href") {
+    return Some(href.trim().to_owned());
+  }
+
+  let selector = Selector::parse("a[href]").unwrap();
+  element
+    .select(&selector)
+    .next()
+    .and_then(|link| link.value().attr("href"))
+    .map(|href| href.trim().to_owned())
+}
+
+/// Parse a date as found in HTML pages and JSON APIs.
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+  let date = date.trim();
+  if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+    return Some(date.into());
+  }
+  if let Ok(date) = DateTime::parse_from_rfc2822(date) {
+    return Some(date.into());
+  }
+
+  let date_time = DATE_TIME_FORMATS
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+    .or_else(|| {
+      DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+    })?;
+
+  Some(Utc.from_utc_datetime(&date_time))
+}
+
+fn get_text(element: &ElementRef) -> String {
+  element
+    .text()
+    .collect::<Vec<&str>>()
+    .join(" ")
+    .split_whitespace()
+    .collect::<Vec<&str>>()
+    .join(" ")
+}
@@ -1,5 +1,7 @@
 use bson::DateTime;
-use serde::Serializer;
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::utils::date::from_iso;
 
 pub fn bson_datetime_option_as_rfc3339_string<S: Serializer>(
   date: &Option<DateTime>,
@@ -13,3 +15,14 @@ pub fn bson_datetime_option_as_rfc3339_string<S: Serializer>(
     None => serializer.serialize_none(),
   }
 }
+
+pub fn bson_datetime_option_from_rfc3339_string<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Option<DateTime>, D::Error> {
+  match Option::<String>::deserialize(deserializer)? {
+    Some(rfc3339_string) => from_iso(&rfc3339_string)
+      .map(|date| Some(date.into()))
+      .map_err(serde::de::Error::custom),
+    None => Ok(None),
+  }
+}
@@ -0,0 +1,107 @@
+use lazy_static::lazy_static;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+lazy_static! {
+  static ref SHUTDOWN: watch::Sender<bool> = watch::channel(false).0;
+  static ref TASKS_DONE: Notify = Notify::new();
+}
+
+// Amount of tracked tasks still running.
+static TASKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Start the shutdown. Schedulers stop picking up new work and the tracked
+/// tasks are waited for.
+pub fn trigger() {
+  info!("Shutting down");
+  SHUTDOWN.send_replace(true);
+}
+
+pub fn is_shutting_down() -> bool {
+  *SHUTDOWN.borrow()
+}
+
+/// Resolves once the shutdown started.
+pub async fn wait() {
+  let mut receiver = SHUTDOWN.subscribe();
+  while !*receiver.borrow_and_update() {
+    if receiver.changed().await.is_err() {
+      return;
+    }
+  }
+}
+
+/// Sleep for the given duration, waking up early when the shutdown starts.
+pub async fn sleep(duration: Duration) {
+  tokio::select! {
+    _ = tokio::time::sleep(duration) => {},
+    _ = wait() => {},
+  }
+}
+
+/// Resolves on SIGINT or SIGTERM, the signal sent by most process managers to
+/// stop a process.
+pub async fn wait_for_signal() {
+  let ctrl_c = async {
+    tokio::signal::ctrl_c()
+      .await
+      .expect("Failed to install the SIGINT handler");
+  };
+
+  #[cfg(unix)]
+  let terminate = async {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+      .expect("Failed to install the SIGTERM handler")
+      .recv()
+      .await;
+  };
+
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    _ = ctrl_c => {},
+    _ = terminate => {},
+  }
+}
+
+/// Spawn a task the shutdown waits for, for work that must not be cut off
+/// halfway, like syncing a feed or sending a webhook.
+pub fn spawn<F>(task: F) -> JoinHandle<F::Output>
+where
+  F: Future + Send + 'static,
+  F::Output: Send + 'static,
+{
+  TASKS.fetch_add(1, Ordering::SeqCst);
+  tokio::spawn(async move {
+    let _guard = TaskGuard;
+    task.await
+  })
+}
+
+/// Resolves once every tracked task finished.
+pub async fn wait_for_tasks() {
+  loop {
+    // Notified futures receive the notifications sent after they are created,
+    // it is created before checking the count to not miss the last one.
+    let done = TASKS_DONE.notified();
+    if TASKS.load(Ordering::SeqCst) == 0 {
+      return;
+    }
+    done.await;
+  }
+}
+
+struct TaskGuard;
+
+impl Drop for TaskGuard {
+  fn drop(&mut self) {
+    if TASKS.fetch_sub(1, Ordering::SeqCst) == 1 {
+      TASKS_DONE.notify_waiters();
+    }
+  }
+}
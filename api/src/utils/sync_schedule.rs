@@ -0,0 +1,257 @@
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc, Weekday};
+use feed_rs::model::Feed as RawFeed;
+use rand::Rng;
+use reqwest::header::{HeaderMap, CACHE_CONTROL, RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::get_settings;
+use crate::utils::date::Date;
+use crate::utils::xml::Element;
+
+// Interval used when a feed does not have enough dated entries to estimate
+// how often it publishes.
+const DEFAULT_PUBLISH_INTERVAL: i64 = 60 * 60;
+// Amount of recent entries used to estimate the publish interval.
+const PUBLISH_INTERVAL_SAMPLE: usize = 10;
+
+/// Scheduling hints taken from the feed document. These are stored in the
+/// feed so they can be used when the server responds with a 304 status code.
+/// Intervals are expressed in seconds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedHints {
+  /// Median time between the most recent entries.
+  pub publish_interval: Option<i64>,
+  pub last_published_at: Option<Date>,
+  /// RSS <ttl> element.
+  pub ttl: Option<i64>,
+  /// Syndication module sy:updatePeriod and sy:updateFrequency elements.
+  pub update_period: Option<i64>,
+  /// RSS <skipHours> element, hours in GMT.
+  #[serde(default)]
+  pub skip_hours: Vec<u32>,
+  /// RSS <skipDays> element, days as number of days from Monday.
+  #[serde(default)]
+  pub skip_days: Vec<u32>,
+}
+
+/// Scheduling hints taken from the HTTP response headers. Intervals are
+/// expressed in seconds.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseHints {
+  pub max_age: Option<i64>,
+  pub retry_after: Option<i64>,
+}
+
+impl FeedHints {
+  /// The XML hints are read from the parsed feed document, JSON feeds do not
+  /// have a document.
+  pub fn new(raw_feed: &RawFeed, document: Option<&Element>) -> Self {
+    let mut dates = raw_feed
+      .entries
+      .iter()
+      .filter_map(|entry| entry.published.or(entry.updated))
+      .collect::<Vec<DateTime<Utc>>>();
+    dates.sort_unstable_by(|a, b| b.cmp(a));
+    dates.truncate(PUBLISH_INTERVAL_SAMPLE);
+
+    let mut hints = Self {
+      publish_interval: get_publish_interval(&dates),
+      last_published_at: dates.first().map(|date| (*date).into()),
+      ttl: raw_feed.ttl.map(|ttl| i64::from(ttl) * 60),
+      ..Default::default()
+    };
+
+    let document = match document {
+      Some(document) => document,
+      None => return hints,
+    };
+    // RSS 2.0 hints are inside the channel element, Atom and RSS 1.0 hints
+    // are direct children of the root element.
+    let root = match document.children.first() {
+      Some(root) => root,
+      None => return hints,
+    };
+    let channel = root.child("channel").unwrap_or(root);
+    let elements = channel.children.iter().chain(root.children.iter());
+
+    let mut update_period = None;
+    let mut update_frequency = None;
+    for element in elements {
+      match element.local_name() {
+        "updatePeriod" => update_period = get_update_period(element.text.trim()),
+        "updateFrequency" => update_frequency = element.text.trim().parse::<i64>().ok(),
+        "skipHours" => {
+          hints.skip_hours = element
+            .children("hour")
+            .filter_map(|hour| hour.text.trim().parse::<u32>().ok())
+            // Some feeds use 24 for midnight.
+            .map(|hour| hour % 24)
+            .collect();
+        }
+        "skipDays" => {
+          hints.skip_days = element
+            .children("day")
+            .filter_map(|day| day.text.trim().parse::<Weekday>().ok())
+            .map(|day| day.num_days_from_monday())
+            .collect();
+        }
+        _ => {}
+      }
+    }
+
+    if update_period.is_some() || update_frequency.is_some() {
+      // The syndication module defaults to once a day.
+      let period = update_period.unwrap_or(60 * 60 * 24);
+      let frequency = update_frequency
+        .filter(|frequency| *frequency > 0)
+        .unwrap_or(1);
+      hints.update_period = Some(period / frequency);
+    }
+
+    hints
+  }
+}
+
+impl ResponseHints {
+  pub fn from_headers(headers: &HeaderMap) -> Self {
+    let max_age = headers
+      .get(CACHE_CONTROL)
+      .and_then(|value| value.to_str().ok())
+      .and_then(get_max_age);
+
+    let retry_after = headers
+      .get(RETRY_AFTER)
+      .and_then(|value| value.to_str().ok())
+      .and_then(get_retry_after);
+
+    Self {
+      max_age,
+      retry_after,
+    }
+  }
+}
+
+/// Compute the date of the next sync. Feeds are synced twice per publish
+/// interval, feeds that stopped publishing are synced less often. Hints from
+/// the feed and the server are respected as the minimum interval and the
+/// result is bounded by the configured intervals, with some jitter so feeds
+/// do not end up synced in bursts. Retry-After is bounded by the maximum
+/// interval too.
+pub fn get_next_sync_at(
+  hints: &FeedHints,
+  response_hints: &ResponseHints,
+  now: DateTime<Utc>,
+) -> DateTime<Utc> {
+  let settings = &get_settings().feeds;
+
+  let mut interval = hints.publish_interval.unwrap_or(DEFAULT_PUBLISH_INTERVAL) / 2;
+
+  if let Some(last_published_at) = hints.last_published_at {
+    let idle = (now - last_published_at.to_chrono()).num_seconds();
+    interval = interval.max(idle / 4);
+  }
+
+  let server_hints = [hints.ttl, hints.update_period, response_hints.max_age];
+  for hint in server_hints.into_iter().flatten() {
+    interval = interval.max(hint);
+  }
+
+  let interval = interval.clamp(settings.min_sync_interval, settings.max_sync_interval);
+  let mut interval = with_jitter(interval);
+
+  // The server explicitly asked us to come back later. The request is honored
+  // up to the maximum interval, feeds are never left unsynced for longer.
+  if let Some(retry_after) = response_hints.retry_after {
+    interval = interval.max(retry_after.min(settings.max_sync_interval));
+  }
+
+  let mut next_sync_at = now + Duration::seconds(interval);
+
+  // Move the sync out of the skipped hours and days. The amount of iterations
+  // is bounded in case the feed skips every hour of the week.
+  for _ in 0..(24 * 7) {
+    let is_skipped_hour = hints.skip_hours.contains(&next_sync_at.hour());
+    let is_skipped_day = hints
+      .skip_days
+      .contains(&next_sync_at.weekday().num_days_from_monday());
+
+    if !is_skipped_hour && !is_skipped_day {
+      break;
+    }
+
+    let minutes = i64::from(60 - next_sync_at.minute());
+    next_sync_at += Duration::minutes(minutes);
+    next_sync_at = next_sync_at.with_second(0).unwrap_or(next_sync_at);
+  }
+
+  next_sync_at
+}
+
+/// Compute the date of the next sync after a failed sync. The interval grows
+/// exponentially with the amount of consecutive failures, starting from the
+/// minimum interval and bounded by the maximum interval.
+pub fn get_retry_at(consecutive_failures: i32, now: DateTime<Utc>) -> DateTime<Utc> {
+  let settings = &get_settings().feeds;
+
+  let exponent = (consecutive_failures - 1).clamp(0, 32) as u32;
+  let interval = settings
+    .min_sync_interval
+    .saturating_mul(2_i64.saturating_pow(exponent))
+    .min(settings.max_sync_interval);
+
+  now + Duration::seconds(with_jitter(interval))
+}
+
+fn with_jitter(interval: i64) -> i64 {
+  let jitter = get_settings().feeds.sync_jitter;
+  let jitter = rand::thread_rng().gen_range(-jitter..=jitter);
+  (interval as f64 * (1.0 + jitter)) as i64
+}
+
+/// Median of the time elapsed between the given dates, sorted from the most
+/// recent to the least recent.
+fn get_publish_interval(dates: &[DateTime<Utc>]) -> Option<i64> {
+  let mut intervals = dates
+    .windows(2)
+    .map(|pair| (pair[0] - pair[1]).num_seconds())
+    .collect::<Vec<i64>>();
+
+  if intervals.is_empty() {
+    return None;
+  }
+
+  intervals.sort_unstable();
+  Some(intervals[intervals.len() / 2])
+}
+
+fn get_update_period(period: &str) -> Option<i64> {
+  let hour = 60 * 60;
+  match period {
+    "hourly" => Some(hour),
+    "daily" => Some(hour * 24),
+    "weekly" => Some(hour * 24 * 7),
+    "monthly" => Some(hour * 24 * 30),
+    "yearly" => Some(hour * 24 * 365),
+    _ => None,
+  }
+}
+
+fn get_max_age(cache_control: &str) -> Option<i64> {
+  cache_control
+    .split(',')
+    .filter_map(|directive| directive.trim().split_once('='))
+    .find(|(name, _)| name.eq_ignore_ascii_case("max-age"))
+    .and_then(|(_, value)| value.trim_matches('"').parse::<i64>().ok())
+}
+
+/// Retry-After is either an amount of seconds or an HTTP date.
+fn get_retry_after(retry_after: &str) -> Option<i64> {
+  if let Ok(seconds) = retry_after.trim().parse::<i64>() {
+    return Some(seconds);
+  }
+
+  DateTime::parse_from_rfc2822(retry_after.trim())
+    .ok()
+    .map(|date| (date.with_timezone(&Utc) - Utc::now()).num_seconds())
+    .filter(|seconds| *seconds > 0)
+}
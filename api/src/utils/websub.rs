@@ -0,0 +1,179 @@
+use feed_rs::model::Feed;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use reqwest::header::{HeaderMap, LINK};
+use reqwest::redirect::Policy;
+use reqwest::Error as ReqwestError;
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+use std::time::Duration;
+
+use crate::settings::get_settings;
+use crate::utils::outbound::{self, check_url, Error as OutboundError};
+
+lazy_static! {
+  static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+    .dns_resolver(outbound::resolver())
+    .timeout(Duration::from_secs(5))
+    .redirect(Policy::none())
+    .user_agent(&get_settings().outbound.user_agent)
+    .build()
+    .expect("Failed to create a reqwest client");
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("...")]
+pub enum Error {
+  #[error("{0}")]
+  Request(#[from] ReqwestError),
+
+  #[error("WebSub hub responded with a {0} status code")]
+  HttpStatus(u16),
+
+  #[error("WebSub hub URL is not allowed. {0}")]
+  Blocked(#[from] OutboundError),
+}
+
+/// Hub advertised by a feed and the topic URL to subscribe to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hub {
+  pub url: String,
+  pub topic: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Mode {
+  Subscribe,
+  Unsubscribe,
+}
+
+impl Mode {
+  fn as_str(&self) -> &'static str {
+    match self {
+      Mode::Subscribe => "subscribe",
+      Mode::Unsubscribe => "unsubscribe",
+    }
+  }
+}
+
+/// Discover the feed hub from the HTTP Link headers or the feed links. The
+/// Link headers take precedence as described in the WebSub specification. The
+/// topic is the feed self link, or the feed URL when it does not have one.
+pub fn discover(feed: &Feed, headers: &HeaderMap, url: &str) -> Option<Hub> {
+  let mut links = headers
+    .get_all(LINK)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(parse_link_header)
+    .collect::<Vec<(String, String)>>();
+
+  let feed_links = feed.links.iter().filter_map(|link| {
+    link
+      .rel
+      .clone()
+      .map(|rel| (link.href.clone(), rel.to_lowercase()))
+  });
+  links.extend(feed_links);
+
+  let find_link = |rel: &str| {
+    links
+      .iter()
+      .find(|(_, rels)| rels.split_whitespace().any(|value| value == rel))
+      .map(|(href, _)| href.clone())
+  };
+
+  let hub = find_link("hub")?;
+  let topic = find_link("self").unwrap_or_else(|| url.to_owned());
+
+  Some(Hub { url: hub, topic })
+}
+
+/// Send a subscription request to the hub. The hub verifies the intent
+/// asynchronously calling the callback URL.
+pub async fn request(
+  hub: &str,
+  mode: Mode,
+  topic: &str,
+  callback: &str,
+  secret: &str,
+  lease_seconds: i64,
+) -> Result<(), Error> {
+  let lease_seconds = lease_seconds.to_string();
+  let mut form = vec![
+    ("hub.mode", mode.as_str()),
+    ("hub.topic", topic),
+    ("hub.callback", callback),
+  ];
+
+  if let Mode::Subscribe = mode {
+    form.push(("hub.secret", secret));
+    form.push(("hub.lease_seconds", &lease_seconds));
+  }
+
+  check_url(hub).await?;
+  let res = CLIENT.post(hub).form(&form).send().await?;
+  let status = res.status();
+  if !status.is_success() {
+    return Err(Error::HttpStatus(status.as_u16()));
+  }
+
+  Ok(())
+}
+
+/// Verify the X-Hub-Signature header value of a content distribution request,
+/// E.g. `sha256=<hex encoded HMAC of the body>`.
+pub fn verify_signature(secret: &str, signature: &str, body: &[u8]) -> bool {
+  let (method, signature) = match signature.split_once('=') {
+    Some(parts) => parts,
+    None => return false,
+  };
+
+  let signature = match hex::decode(signature.trim()) {
+    Ok(signature) => signature,
+    Err(_) => return false,
+  };
+
+  let secret = secret.as_bytes();
+  match method.trim().to_lowercase().as_str() {
+    "sha1" => verify_hmac::<Hmac<Sha1>>(secret, body, &signature),
+    "sha256" => verify_hmac::<Hmac<Sha256>>(secret, body, &signature),
+    "sha384" => verify_hmac::<Hmac<Sha384>>(secret, body, &signature),
+    "sha512" => verify_hmac::<Hmac<Sha512>>(secret, body, &signature),
+    _ => false,
+  }
+}
+
+fn verify_hmac<M: Mac + KeyInit>(secret: &[u8], body: &[u8], signature: &[u8]) -> bool {
+  let mut mac = match <M as KeyInit>::new_from_slice(secret) {
+    Ok(mac) => mac,
+    Err(_) => return false,
+  };
+
+  mac.update(body);
+  mac.verify_slice(signature).is_ok()
+}
+
+/// Parse a Link header value into (URL, rel) pairs. E.g.
+/// `<https://hub.example.com/>; rel="hub", <https://example.com/feed>; rel="self"`
+fn parse_link_header(value: &str) -> Vec<(String, String)> {
+  value
+    .split(',')
+    .filter_map(|link| {
+      let mut parts = link.split(';');
+      let href = parts
+        .next()?
+        .trim()
+        .strip_prefix('<')?
+        .strip_suffix('>')?
+        .to_owned();
+
+      let rel = parts
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("rel"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_lowercase())?;
+
+      Some((href, rel))
+    })
+    .collect()
+}
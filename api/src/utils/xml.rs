@@ -0,0 +1,158 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+/// Minimal XML tree used to read the feed elements that feed-rs does not
+/// expose (E.g. the syndication module or the RSS skipHours element). Element
+/// and attribute names are kept as they appear in the document, including the
+/// namespace prefix. The namespace URI of the elements is resolved from the
+/// `xmlns` declarations, prefixes are chosen by each document.
+#[derive(Debug, Default, Clone)]
+pub struct Element {
+  pub name: String,
+  pub namespace: Option<String>,
+  pub attributes: Vec<(String, String)>,
+  pub children: Vec<Element>,
+  pub text: String,
+}
+
+impl Element {
+  /// Parse the XML document into a tree. The returned element is a nameless
+  /// node wrapping the document root element.
+  pub fn parse(content: &[u8]) -> Result<Self, quick_xml::Error> {
+    let mut reader = Reader::from_reader(content);
+    reader.trim_text(true);
+    reader.check_end_names(false);
+
+    let mut stack = vec![Element::default()];
+    loop {
+      match reader.read_event()? {
+        Event::Start(start) => {
+          let element = Self::from_start(&start, &stack);
+          stack.push(element);
+        }
+        Event::Empty(start) => {
+          let element = Self::from_start(&start, &stack);
+          stack.last_mut().unwrap().children.push(element);
+        }
+        // Unbalanced end tags are ignored, feeds in the wild are not always
+        // well formed.
+        Event::End(_) if stack.len() > 1 => {
+          let element = stack.pop().unwrap();
+          stack.last_mut().unwrap().children.push(element);
+        }
+        Event::Text(text) => {
+          let text = text
+            .unescape()
+            .map(|text| text.into_owned())
+            .unwrap_or_else(|_| String::from_utf8_lossy(&text).into_owned());
+          stack.last_mut().unwrap().text.push_str(&text);
+        }
+        Event::CData(data) => {
+          let text = String::from_utf8_lossy(&data.into_inner()).into_owned();
+          stack.last_mut().unwrap().text.push_str(&text);
+        }
+        Event::Eof => break,
+        _ => {}
+      }
+    }
+
+    // Close elements left open by a truncated document.
+    while stack.len() > 1 {
+      let element = stack.pop().unwrap();
+      stack.last_mut().unwrap().children.push(element);
+    }
+
+    Ok(stack.pop().unwrap())
+  }
+
+  /// Element name without the namespace prefix.
+  pub fn local_name(&self) -> &str {
+    match self.name.split_once(':') {
+      Some((_, name)) => name,
+      None => &self.name,
+    }
+  }
+
+  /// Check the element namespace URI and local name.
+  pub fn is(&self, namespace: &str, name: &str) -> bool {
+    self.namespace.as_deref() == Some(namespace) && self.local_name() == name
+  }
+
+  pub fn attr(&self, name: &str) -> Option<&str> {
+    self
+      .attributes
+      .iter()
+      .find(|(key, _)| key == name)
+      .map(|(_, value)| value.as_str())
+  }
+
+  pub fn child(&self, name: &str) -> Option<&Element> {
+    self.children.iter().find(|child| child.name == name)
+  }
+
+  pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+    self.children.iter().filter(move |child| child.name == name)
+  }
+
+  /// Child elements in the given namespace with the given local name.
+  pub fn children_in<'a>(
+    &'a self,
+    namespace: &'a str,
+    name: &'a str,
+  ) -> impl Iterator<Item = &'a Element> {
+    self
+      .children
+      .iter()
+      .filter(move |child| child.is(namespace, name))
+  }
+
+  pub fn child_in(&self, namespace: &str, name: &str) -> Option<&Element> {
+    self.children.iter().find(|child| child.is(namespace, name))
+  }
+
+  /// Namespace URI bound to the prefix by the element or its ancestors, an
+  /// empty prefix is the default namespace.
+  fn get_namespace(&self, ancestors: &[Element], prefix: &str) -> Option<String> {
+    let key = match prefix {
+      "" => "xmlns".to_owned(),
+      prefix => format!("xmlns:{}", prefix),
+    };
+
+    std::iter::once(self)
+      .chain(ancestors.iter().rev())
+      .find_map(|element| element.attr(&key))
+      .filter(|namespace| !namespace.is_empty())
+      .map(ToOwned::to_owned)
+  }
+
+  fn from_start(start: &BytesStart, ancestors: &[Element]) -> Self {
+    // Malformed attributes are skipped, like unbalanced end tags.
+    let attributes = start
+      .attributes()
+      .with_checks(false)
+      .flatten()
+      .map(|attribute| {
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        let value = attribute
+          .unescape_value()
+          .map(|value| value.into_owned())
+          .unwrap_or_else(|_| String::from_utf8_lossy(&attribute.value).into_owned());
+        (key, value)
+      })
+      .collect();
+
+    let mut element = Self {
+      name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+      namespace: None,
+      attributes,
+      children: vec![],
+      text: String::new(),
+    };
+    let prefix = match element.name.split_once(':') {
+      Some((prefix, _)) => prefix,
+      None => "",
+    };
+    element.namespace = element.get_namespace(ancestors, prefix);
+    element
+  }
+}